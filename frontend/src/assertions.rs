@@ -0,0 +1,466 @@
+use std::{fmt::Display, fs, path::Path, str::FromStr};
+
+use utils::{events::{Event, EventType}, AppState, TaskState};
+
+use crate::ayu_event_handlers::{EventContext, EventHandler};
+
+/// Number of unchanged lines shown around a difference in a trace.
+const DIFF_CONTEXT: usize = 2;
+
+/// Maximum number of lines of the diff, which are printed.
+const MAX_DIFF_LINES: usize = 50;
+
+/// Maximum number of cells of the table for the longest common subsequence.
+/// Larger differences are compared line by line instead.
+const MAX_DIFF_TABLE: usize = 1 << 24;
+
+/// Errors which can occur when reading expectations.
+#[derive(Debug, PartialEq)]
+pub enum ExpectationError {
+    Io(String),
+    Syntax { line: usize, msg: String },
+}
+
+impl Display for ExpectationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ExpectationError::Io(msg) => write!(f, "Unable to read expectations: {}", msg),
+            ExpectationError::Syntax { line, msg } => write!(f, "Invalid expectations, line {}: {}", line, msg),
+        }
+    }
+}
+
+/// Comparison used for event counts.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Comparison {
+    Equal,
+    AtLeast,
+    AtMost,
+}
+
+impl Comparison {
+    fn holds(&self, actual: u64, expected: u64) -> bool {
+        match self {
+            Comparison::Equal => actual == expected,
+            Comparison::AtLeast => actual >= expected,
+            Comparison::AtMost => actual <= expected,
+        }
+    }
+}
+
+impl Display for Comparison {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let op = match self {
+            Comparison::Equal => "",
+            Comparison::AtLeast => ">=",
+            Comparison::AtMost => "<=",
+        };
+        write!(f, "{}", op)
+    }
+}
+
+/// A single expectation on a session.
+#[derive(Debug, PartialEq)]
+pub enum Expectation {
+    /// The events need to match the lines of a golden trace exactly, values of `*` match anything (`trace <file>`).
+    Trace(Vec<String>),
+    /// Number of events of a type (`count AddTask >=5`).
+    Count(EventType, Comparison, u64),
+    /// Events of a task need to happen in the given order, `None` stands for every task (`order 5: PreRunTask RunTask`).
+    Order(Option<u64>, Vec<EventType>),
+    /// Number of tasks left in the final state (`tasks 0`).
+    Tasks(usize),
+    /// Number of functions in the final state (`functions 2`).
+    Functions(usize),
+    /// Number of dependencies in the final state (`dependencies 4`).
+    Dependencies(usize),
+    /// Lifecycle state of a task in the final state (`task 3 finished`).
+    TaskState(u64, TaskState),
+}
+
+/// Expectations on the event stream and the final state of a session.
+///
+/// Each line of an expectation file contains one expectation, e.g.
+/// ```text
+/// # exact order of all events, recorded with --record
+/// trace golden.trace
+/// count AddTask 5
+/// count PreRunTask >=1
+/// order *: AddTask PreRunTask RunTask PostRunTask
+/// tasks 0
+/// functions 1
+/// dependencies 4
+/// task 3 finished
+/// ```
+#[derive(Debug)]
+pub struct Expectations {
+    expectations: Vec<Expectation>,
+}
+
+impl Expectations {
+    /// Read expectations from a file. Paths of golden traces are relative to the file.
+    pub fn from_file(path: &str) -> Result<Self, ExpectationError> {
+        let file = fs::read_to_string(path).map_err(|e| ExpectationError::Io(e.to_string()))?;
+        let dir = Path::new(path).parent().unwrap_or(Path::new("."));
+
+        Self::parse(&file, |trace| fs::read_to_string(dir.join(trace)).map_err(|e| format!("{}: {}", trace, e)))
+    }
+
+    /// Parse expectations, golden traces are read with `read_trace`.
+    fn parse(file: &str, read_trace: impl Fn(&str) -> Result<String, String>) -> Result<Self, ExpectationError> {
+        let mut expectations = Vec::new();
+
+        for (i, line) in file.lines().enumerate() {
+            // remove comments
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let syntax_error = |msg: String| ExpectationError::Syntax { line: i + 1, msg };
+            let expectation = match line.split_whitespace().collect::<Vec<_>>()[..] {
+                ["trace", path] => {
+                    let trace = read_trace(path).map_err(ExpectationError::Io)?;
+                    Expectation::Trace(trace_lines(&trace))
+                },
+                ["count", event, n] => {
+                    let (comparison, n) = match n {
+                        n if n.starts_with(">=") => (Comparison::AtLeast, &n[2..]),
+                        n if n.starts_with("<=") => (Comparison::AtMost, &n[2..]),
+                        n => (Comparison::Equal, n.trim_start_matches('=')),
+                    };
+                    Expectation::Count(event.parse().map_err(syntax_error)?, comparison, parse_number(n).map_err(syntax_error)?)
+                },
+                ["order", ..] => {
+                    let (task, events) = line["order".len()..].split_once(':').ok_or(syntax_error(String::from("needs to be in the form of `order <task_id|*>: <events>`")))?;
+                    let task = match task.trim() {
+                        "*" => None,
+                        id => Some(parse_number(id).map_err(syntax_error)?),
+                    };
+                    let events = events.split_whitespace().map(EventType::from_str).collect::<Result<Vec<_>, _>>().map_err(syntax_error)?;
+                    Expectation::Order(task, events)
+                },
+                ["tasks", n] => Expectation::Tasks(parse_number(n).map_err(syntax_error)?),
+                ["functions", n] => Expectation::Functions(parse_number(n).map_err(syntax_error)?),
+                ["dependencies", n] => Expectation::Dependencies(parse_number(n).map_err(syntax_error)?),
+                ["task", id, task_state] => Expectation::TaskState(parse_number(id).map_err(syntax_error)?, task_state.parse().map_err(syntax_error)?),
+                _ => return Err(syntax_error(format!("Invalid expectation: {}", line))),
+            };
+            expectations.push(expectation);
+        }
+
+        Ok(Expectations { expectations })
+    }
+
+    /// Check all expectations against the received events and the final state.
+    ///
+    /// Returns a readable description for every expectation that does not hold.
+    pub fn check(&self, events: &[Event], state: &AppState) -> Vec<String> {
+        self.expectations.iter().filter_map(|e| check_expectation(e, events, state)).collect()
+    }
+}
+
+/// Collects the received events, so the expectations can be checked after the session.
+pub struct ExpectationChecker {
+    expectations: Expectations,
+    received: Vec<Event>,
+}
+
+impl ExpectationChecker {
+    /// Creates a checker, which did not receive any events yet.
+    pub fn new(expectations: Expectations) -> Self {
+        Self { expectations, received: Vec::new() }
+    }
+
+    /// Check all expectations against the events received so far and the state, see [Expectations::check].
+    pub fn check(&self, state: &AppState) -> Vec<String> {
+        self.expectations.check(&self.received, state)
+    }
+}
+
+impl EventHandler for ExpectationChecker {
+    fn on_event(&mut self, _ctx: &mut EventContext, event: &Event) {
+        self.received.push(*event);
+    }
+}
+
+/// Check a single expectation, returns a description of the mismatch, if it doesn't hold.
+fn check_expectation(expectation: &Expectation, events: &[Event], state: &AppState) -> Option<String> {
+    match expectation {
+        Expectation::Trace(expected) => {
+            let actual = events.iter().map(|e| e.to_string()).collect::<Vec<_>>();
+            let diff = diff_traces(expected, &actual)?;
+            Some(format!("Trace does not match (- expected, + actual):\n{}", diff))
+        },
+        Expectation::Count(event_type, comparison, n) => {
+            let actual = events.iter().filter(|e| e.event_type() == *event_type).count() as u64;
+            (!comparison.holds(actual, *n)).then(|| format!("Expected {}{} {:?} events, got {}", comparison, n, event_type, actual))
+        },
+        Expectation::Order(task_id, order) => {
+            let task_ids = match task_id {
+                Some(id) => vec![*id],
+                None => events.iter().filter(|e| e.event_type() == EventType::AddTask).filter_map(|e| e.task_id()).collect(),
+            };
+            let failures = task_ids.into_iter()
+                .filter_map(|id| check_order(id, order, events))
+                .collect::<Vec<_>>();
+            (!failures.is_empty()).then(|| failures.join("\n"))
+        },
+        Expectation::Tasks(n) => {
+            let actual = state.get_tasks().len();
+            (actual != *n).then(|| format!("Expected {} tasks in final state, got {}", n, actual))
+        },
+        Expectation::Functions(n) => {
+            let actual = state.get_functions().len();
+            (actual != *n).then(|| format!("Expected {} functions in final state, got {}", n, actual))
+        },
+        Expectation::Dependencies(n) => {
+            let actual = state.get_dependencies().len();
+            (actual != *n).then(|| format!("Expected {} dependencies in final state, got {}", n, actual))
+        },
+        Expectation::TaskState(id, task_state) => match state.get_task(*id) {
+            Some(task) if task.get_state() == *task_state => None,
+            Some(task) => Some(format!("Expected task {} to be {} in final state, but it is {}", id, task_state, task.get_state())),
+            None => Some(format!("Expected task {} to be {} in final state, but it does not exist", id, task_state)),
+        },
+    }
+}
+
+/// Check if the events of a task happen in the given order. Events of other types are ignored.
+fn check_order(task_id: u64, order: &[EventType], events: &[Event]) -> Option<String> {
+    let actual = events.iter()
+        .filter(|e| e.task_id() == Some(task_id) && order.contains(&e.event_type()))
+        .map(|e| e.event_type())
+        .collect::<Vec<_>>();
+
+    // every event needs to happen, and for each pair, the first occurrence needs to be in order
+    let positions = order.iter().map(|t| actual.iter().position(|a| a == t)).collect::<Vec<_>>();
+    let is_ordered = positions.iter().all(|p| p.is_some()) && positions.windows(2).all(|w| w[0] < w[1]);
+
+    (!is_ordered).then(|| format!("Expected events of task {} in order {:?}, got {:?}", task_id, order, actual))
+}
+
+/// Split a trace into its lines, ignoring comments and empty lines.
+fn trace_lines(trace: &str) -> Vec<String> {
+    trace.lines()
+        .map(|l| l.split('#').next().unwrap_or_default().trim())
+        .filter(|l| !l.is_empty())
+        .map(|l| l.to_string())
+        .collect()
+}
+
+/// Compare a line of a golden trace with an actual line. Values of `*` in the expected line match anything.
+fn line_matches(expected: &str, actual: &str) -> bool {
+    let expected = expected.split_whitespace().collect::<Vec<_>>();
+    let actual = actual.split_whitespace().collect::<Vec<_>>();
+
+    expected.len() == actual.len() && expected.iter().zip(actual.iter()).all(|(e, a)| match e.strip_suffix("=*") {
+        Some(key) => a.split_once('=').is_some_and(|(k, _)| k == key),
+        None => e == a,
+    })
+}
+
+/// A line of a diff between two traces, with its line number.
+#[derive(Debug, PartialEq)]
+enum DiffLine<'a> {
+    Same(usize, &'a str),
+    Removed(usize, &'a str),
+    Added(usize, &'a str),
+}
+
+/// Create a readable diff between an expected and an actual trace. Returns None, if the traces match.
+fn diff_traces(expected: &[String], actual: &[String]) -> Option<String> {
+    let diff = diff_lines(expected, actual);
+    if diff.iter().all(|d| matches!(d, DiffLine::Same(..))) {
+        return None;
+    }
+
+    // only show lines close to a change
+    let is_shown = |idx: usize| {
+        let start = idx.saturating_sub(DIFF_CONTEXT);
+        let end = (idx + DIFF_CONTEXT + 1).min(diff.len());
+        diff[start..end].iter().any(|d| !matches!(d, DiffLine::Same(..)))
+    };
+
+    let mut output = Vec::new();
+    let mut last_shown = None;
+    for (idx, line) in diff.iter().enumerate().filter(|(idx, _)| is_shown(*idx)) {
+        if last_shown.is_some_and(|last| last + 1 != idx) {
+            output.push(String::from("  ..."));
+        }
+        last_shown = Some(idx);
+        output.push(match line {
+            DiffLine::Same(nr, l) => format!("  {:>5}   {}", nr, l),
+            DiffLine::Removed(nr, l) => format!("- {:>5}   {}", nr, l),
+            DiffLine::Added(nr, l) => format!("+ {:>5}   {}", nr, l),
+        });
+    }
+
+    if output.len() > MAX_DIFF_LINES {
+        let omitted = output.len() - MAX_DIFF_LINES;
+        output.truncate(MAX_DIFF_LINES);
+        output.push(format!("  ... ({} more lines)", omitted));
+    }
+
+    Some(output.join("\n"))
+}
+
+/// Compute a line based diff of both traces.
+///
+/// Uses the longest common subsequence, unless the differing part is too large for its table.
+fn diff_lines<'a>(expected: &'a [String], actual: &'a [String]) -> Vec<DiffLine<'a>> {
+    // strip common prefix and suffix, so the table only covers the part that differs
+    let prefix = expected.iter().zip(actual.iter()).take_while(|(e, a)| line_matches(e, a)).count();
+    let suffix = expected[prefix..].iter().rev().zip(actual[prefix..].iter().rev()).take_while(|(e, a)| line_matches(e, a)).count();
+    let exp = &expected[prefix..expected.len() - suffix];
+    let act = &actual[prefix..actual.len() - suffix];
+
+    let mut diff = (0..prefix).map(|i| DiffLine::Same(i + 1, actual[i].as_str())).collect::<Vec<_>>();
+    if (exp.len() + 1).saturating_mul(act.len() + 1) > MAX_DIFF_TABLE {
+        diff.extend(diff_lines_in_order(exp, act, prefix));
+    } else {
+        diff.extend(diff_lines_lcs(exp, act, prefix));
+    }
+    let offset = actual.len() - suffix;
+    diff.extend((offset..actual.len()).map(|j| DiffLine::Same(j + 1, actual[j].as_str())));
+
+    diff
+}
+
+/// Diff two traces with the longest common subsequence. The line numbers start after the given prefix.
+fn diff_lines_lcs<'a>(exp: &'a [String], act: &'a [String], prefix: usize) -> Vec<DiffLine<'a>> {
+    // lcs[i][j] is the length of the longest common subsequence of exp[i..] and act[j..]
+    let mut lcs = vec![vec![0usize; act.len() + 1]; exp.len() + 1];
+    for i in (0..exp.len()).rev() {
+        for j in (0..act.len()).rev() {
+            lcs[i][j] = if line_matches(&exp[i], &act[j]) { lcs[i + 1][j + 1] + 1 } else { lcs[i + 1][j].max(lcs[i][j + 1]) };
+        }
+    }
+
+    let mut diff = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < exp.len() || j < act.len() {
+        if i < exp.len() && j < act.len() && line_matches(&exp[i], &act[j]) {
+            diff.push(DiffLine::Same(prefix + j + 1, act[j].as_str()));
+            i += 1;
+            j += 1;
+        } else if i < exp.len() && (j == act.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+            diff.push(DiffLine::Removed(prefix + i + 1, exp[i].as_str()));
+            i += 1;
+        } else {
+            diff.push(DiffLine::Added(prefix + j + 1, act[j].as_str()));
+            j += 1;
+        }
+    }
+
+    diff
+}
+
+/// Diff two traces by comparing the lines at the same position, which needs no table for large traces.
+fn diff_lines_in_order<'a>(exp: &'a [String], act: &'a [String], prefix: usize) -> Vec<DiffLine<'a>> {
+    let mut diff = Vec::new();
+    for idx in 0..exp.len().max(act.len()) {
+        match (exp.get(idx), act.get(idx)) {
+            (Some(e), Some(a)) if line_matches(e, a) => diff.push(DiffLine::Same(prefix + idx + 1, a.as_str())),
+            (e, a) => {
+                diff.extend(e.map(|e| DiffLine::Removed(prefix + idx + 1, e.as_str())));
+                diff.extend(a.map(|a| DiffLine::Added(prefix + idx + 1, a.as_str())));
+            },
+        }
+    }
+
+    diff
+}
+
+/// Parse a number of an expectation.
+fn parse_number<T: FromStr>(n: &str) -> Result<T, String> {
+    n.parse::<T>().map_err(|_| format!("Invalid number: {}", n))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn events() -> Vec<Event> {
+        vec![
+            Event::PreInit { rt: 0, pid: 42 },
+            Event::AddTask { task_id: 1, func_id: 0, priority: 0, scope_id: 0 },
+            Event::AddTask { task_id: 2, func_id: 0, priority: 0, scope_id: 0 },
+            Event::PreRunTask { task_id: 1, thread_id: 0 },
+            Event::RunTask { task_id: 1 },
+            Event::RunTask { task_id: 2 },
+            Event::PreRunTask { task_id: 2, thread_id: 1 },
+            Event::Finish,
+        ]
+    }
+
+    #[test]
+    fn parse_expectations() {
+        let file = "trace golden.trace\ncount AddTask >=2\norder *: AddTask RunTask\ntask 3 running # comment";
+        let expectations = Expectations::parse(file, |_| Ok(String::from("PreInit rt=0 pid=*\nFinish"))).unwrap();
+        assert_eq!(expectations.expectations, vec![
+            Expectation::Trace(vec![String::from("PreInit rt=0 pid=*"), String::from("Finish")]),
+            Expectation::Count(EventType::AddTask, Comparison::AtLeast, 2),
+            Expectation::Order(None, vec![EventType::AddTask, EventType::RunTask]),
+            Expectation::TaskState(3, TaskState::Running),
+        ]);
+
+        assert!(matches!(Expectations::parse("\ncount Nothing 2", |_| Ok(String::new())), Err(ExpectationError::Syntax { line: 2, .. })));
+    }
+
+    #[test]
+    fn check_counts_and_order() {
+        let state = AppState::new();
+        let file = "count AddTask 2\ncount Barrier >=1\norder 1: PreRunTask RunTask\norder 2: PreRunTask RunTask";
+        let failures = Expectations::parse(file, |_| Ok(String::new())).unwrap().check(&events(), &state);
+
+        assert_eq!(failures, vec![
+            String::from("Expected >=1 Barrier events, got 0"),
+            String::from("Expected events of task 2 in order [PreRunTask, RunTask], got [RunTask, PreRunTask]"),
+        ]);
+    }
+
+    #[test]
+    fn check_final_state() {
+        let mut state = AppState::new();
+        let _ = state.create_task(3, false, None, 0);
+        let expectations = Expectations::parse("tasks 1\nfunctions 0\ntask 3 finished", |_| Ok(String::new())).unwrap();
+
+        assert_eq!(expectations.check(&[], &state), vec![String::from("Expected task 3 to be finished in final state, but it is created")]);
+    }
+
+    #[test]
+    fn matching_trace_with_wildcards() {
+        let expected = trace_lines(&events().iter().map(|e| e.to_string()).collect::<Vec<_>>().join("\n").replace("pid=42", "pid=*"));
+        assert!(diff_traces(&expected, &events().iter().map(|e| e.to_string()).collect::<Vec<_>>()).is_none());
+    }
+
+    #[test]
+    fn diff_mismatching_trace() {
+        let expected = ["a", "b", "c", "d"].map(String::from);
+        let actual = ["a", "c", "x", "d"].map(String::from);
+
+        assert_eq!(diff_lines(&expected, &actual), vec![
+            DiffLine::Same(1, "a"),
+            DiffLine::Removed(2, "b"),
+            DiffLine::Same(2, "c"),
+            DiffLine::Added(3, "x"),
+            DiffLine::Same(4, "d"),
+        ]);
+        assert!(diff_traces(&expected, &actual).unwrap().contains("-     2   b"));
+    }
+
+    #[test]
+    fn diff_large_trace() {
+        let expected = (0..100_000).map(|i| format!("RunTask task_id={}", i)).collect::<Vec<_>>();
+        let mut actual = expected.clone();
+        actual[10] = String::from("Finish");
+        actual[99_990] = String::from("Finish");
+
+        let diff = diff_lines(&expected, &actual);
+        assert_eq!(diff.len(), 100_002);
+        assert_eq!(diff[10], DiffLine::Removed(11, "RunTask task_id=10"));
+        assert_eq!(diff[11], DiffLine::Added(11, "Finish"));
+        assert!(diff_traces(&expected, &actual).unwrap().contains("+ 99991   Finish"));
+    }
+}
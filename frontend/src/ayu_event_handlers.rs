@@ -1,150 +1,360 @@
 use std::io::Read;
 use std::net::TcpStream;
-use std::sync::{RwLock, Arc};
+use std::sync::{Arc, RwLock};
 
-use utils::AppState;
-use utils::events::{self, Event, EventError};
+use utils::{AppState, TaskState};
+use utils::events::{self, Event, EventError, EventMeta};
+
+use crate::commands;
 
 /// Possible outcomes after handling an event. Usually, a event will return Success.
 /// A finish event, will return Exit, which causes the frontend to shut down.
+/// If a handler stopped the session (e.g. a script quit), Stop is returned.
+#[derive(Debug, PartialEq)]
 pub enum EventResult {
     Exit,
     Success,
+    Stop,
 }
 
-/// Handle incoming events and update the state of the Application (which tasks are currently added etc.), if necessary.
-pub fn handle_event(buf: &[u8], mut state_lock: &mut Arc<RwLock<AppState>>, stream: &mut TcpStream) -> Result<EventResult, EventError> {
-    let result = match Event::try_from(buf)? {
-        Event::PreInit { rt, pid } => handle_pre_init(rt, pid, &mut state_lock),
-        Event::Init { n_threads } => handle_init(n_threads, &mut state_lock),
-        Event::AddTask { task_id, func_id, priority, scope_id } => handle_add_task(task_id, func_id, priority, scope_id, &mut state_lock),
-        Event::RegisterFunction { func_id, string_len } => {
-            // try to read the name from the stream
-            let name = if string_len > 0 {
-                let mut buf = [0; 128];
-                let bytes_read = stream.read(&mut buf).unwrap_or(0);
-                if bytes_read != string_len {
-                    eprintln!("String Length doesn't match bytes read, defaulting to empty function name.");
-                    String::new()
-                } else {
-                    events::read_function_name_from_buffer(&buf[..string_len])
-                }
-            } else {
-                String::new()
-            };
-            handle_register_function(func_id, name, string_len, &mut state_lock)
-        },
-        Event::AddDependency { to_id, from_id, memaddr, orig_memaddr } => handle_add_dependency(to_id, from_id, memaddr, orig_memaddr, &mut state_lock),
-        Event::AddTaskToQueue { task_id, thread_id } => handle_add_task_to_queue(task_id, thread_id, &mut state_lock),
-        Event::PreRunTask { task_id, thread_id } => handle_pre_run_task(task_id, thread_id, &mut state_lock),
-        Event::RunTask { task_id } => handle_run_task(task_id, &mut state_lock),
-        Event::PostRunTask { task_id } => handle_post_run_task(task_id, &mut state_lock),
-        Event::RemoveTask { task_id } => handle_remove_task(task_id, &mut state_lock),
-        Event::Barrier => handle_barrier(&mut state_lock),
-        Event::WaitOn { task_id } => handle_wait_on(task_id, &mut state_lock),
-        Event::Finish => handle_finish(&mut state_lock),
-    }; 
-
-    Ok(result)
+/// Everything a handler may need besides the event itself.
+pub struct EventContext<'a> {
+    /// Information about the received event, e.g. its timestamp.
+    pub meta: &'a EventMeta,
+    pub state: &'a Arc<RwLock<AppState>>,
+    /// The stream to Ayudame, which can be used to send requests.
+    pub stream: &'a TcpStream,
+    is_stopped: bool,
 }
 
-fn handle_pre_init(rt: u64, pid: u64, state_lock: &RwLock<AppState>) -> EventResult {
-    println!("Got PreInit event, rt: {rt}, pid: {pid}");
-    if let Ok(mut state) = state_lock.write() {
-        state.is_pre_init = true;
+impl<'a> EventContext<'a> {
+    pub fn new(meta: &'a EventMeta, state: &'a Arc<RwLock<AppState>>, stream: &'a TcpStream) -> Self {
+        Self { meta, state, stream, is_stopped: false }
     }
 
-    EventResult::Success
+    /// Stop receiving events after the current event was handled by all handlers.
+    pub fn stop(&mut self) {
+        self.is_stopped = true;
+    }
 }
 
-fn handle_init(n_threads: u64, state_lock: &RwLock<AppState>) -> EventResult {
-    println!("Got Init event, n_threads: {n_threads}");
-    if let Ok(mut state) = state_lock.write() {
-        state.is_init = true;
-    }
+/// Callbacks for the events received from Ayudame.
+///
+/// Every callback does nothing by default, so a handler only implements the events it is interested in.
+/// The callback for the specific event is called first, followed by [EventHandler::on_event].
+#[allow(unused_variables)]
+pub trait EventHandler {
+    fn on_pre_init(&mut self, ctx: &mut EventContext, rt: u64, pid: u64) {}
+    fn on_init(&mut self, ctx: &mut EventContext, n_threads: u64) {}
+    /// The name of the function was already read from the stream.
+    fn on_register_function(&mut self, ctx: &mut EventContext, func_id: u64, name: &str, string_len: usize) {}
+    fn on_add_task(&mut self, ctx: &mut EventContext, task_id: u64, func_id: u64, priority: u64, scope_id: u64) {}
+    fn on_add_dependency(&mut self, ctx: &mut EventContext, to_id: u64, from_id: u64, memaddr: u64, orig_memaddr: u64) {}
+    fn on_add_task_to_queue(&mut self, ctx: &mut EventContext, task_id: u64, thread_id: u64) {}
+    fn on_pre_run_task(&mut self, ctx: &mut EventContext, task_id: u64, thread_id: u64) {}
+    fn on_run_task(&mut self, ctx: &mut EventContext, task_id: u64) {}
+    fn on_post_run_task(&mut self, ctx: &mut EventContext, task_id: u64) {}
+    fn on_remove_task(&mut self, ctx: &mut EventContext, task_id: u64) {}
+    fn on_barrier(&mut self, ctx: &mut EventContext) {}
+    fn on_wait_on(&mut self, ctx: &mut EventContext, task_id: u64) {}
+    fn on_finish(&mut self, ctx: &mut EventContext) {}
+    /// Called for every event, after the callback for the specific event.
+    fn on_event(&mut self, ctx: &mut EventContext, event: &Event) {}
+    /// Called if a received buffer could not be decoded into an event.
+    fn on_decode_error(&mut self, error: &EventError) {}
+}
 
-    EventResult::Success
+/// Allows adding handlers by reference, e.g. if they need to be inspected after the session.
+impl<T: EventHandler + ?Sized> EventHandler for &mut T {
+    fn on_pre_init(&mut self, ctx: &mut EventContext, rt: u64, pid: u64) { (**self).on_pre_init(ctx, rt, pid) }
+    fn on_init(&mut self, ctx: &mut EventContext, n_threads: u64) { (**self).on_init(ctx, n_threads) }
+    fn on_register_function(&mut self, ctx: &mut EventContext, func_id: u64, name: &str, string_len: usize) { (**self).on_register_function(ctx, func_id, name, string_len) }
+    fn on_add_task(&mut self, ctx: &mut EventContext, task_id: u64, func_id: u64, priority: u64, scope_id: u64) { (**self).on_add_task(ctx, task_id, func_id, priority, scope_id) }
+    fn on_add_dependency(&mut self, ctx: &mut EventContext, to_id: u64, from_id: u64, memaddr: u64, orig_memaddr: u64) { (**self).on_add_dependency(ctx, to_id, from_id, memaddr, orig_memaddr) }
+    fn on_add_task_to_queue(&mut self, ctx: &mut EventContext, task_id: u64, thread_id: u64) { (**self).on_add_task_to_queue(ctx, task_id, thread_id) }
+    fn on_pre_run_task(&mut self, ctx: &mut EventContext, task_id: u64, thread_id: u64) { (**self).on_pre_run_task(ctx, task_id, thread_id) }
+    fn on_run_task(&mut self, ctx: &mut EventContext, task_id: u64) { (**self).on_run_task(ctx, task_id) }
+    fn on_post_run_task(&mut self, ctx: &mut EventContext, task_id: u64) { (**self).on_post_run_task(ctx, task_id) }
+    fn on_remove_task(&mut self, ctx: &mut EventContext, task_id: u64) { (**self).on_remove_task(ctx, task_id) }
+    fn on_barrier(&mut self, ctx: &mut EventContext) { (**self).on_barrier(ctx) }
+    fn on_wait_on(&mut self, ctx: &mut EventContext, task_id: u64) { (**self).on_wait_on(ctx, task_id) }
+    fn on_finish(&mut self, ctx: &mut EventContext) { (**self).on_finish(ctx) }
+    fn on_event(&mut self, ctx: &mut EventContext, event: &Event) { (**self).on_event(ctx, event) }
+    fn on_decode_error(&mut self, error: &EventError) { (**self).on_decode_error(error) }
 }
 
-fn handle_add_task(task_id: u64, func_id: u64, priority: u64, scope_id: u64, state_lock: &RwLock<AppState>) -> EventResult {
-    println!("Got AddTask Event.");
-    let function_id = if func_id == 0 { Some(func_id) } else { None };
-    let is_critical = if priority > 0 { true } else { false };
-    if let Ok(mut state) = state_lock.write() {
-        let _ = state.create_task(task_id, is_critical, function_id, scope_id);
+/// Handlers, which are called in the order they were added for every event.
+///
+/// Usually, the [StateHandler] comes first, so all following handlers see the updated state.
+#[derive(Default)]
+pub struct HandlerChain<'a> {
+    handlers: Vec<Box<dyn EventHandler + Send + 'a>>,
+}
+
+impl<'a> HandlerChain<'a> {
+    /// Creates a chain without handlers.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a handler to the end of the chain.
+    pub fn push(&mut self, handler: impl EventHandler + Send + 'a) {
+        self.handlers.push(Box::new(handler));
+    }
+
+    /// Call all handlers for an event.
+    pub fn handle(&mut self, event: &Event, ctx: &mut EventContext) -> EventResult {
+        // RegisterFunction sends the name of the function in a second buffer
+        let name = match *event {
+            Event::RegisterFunction { string_len, .. } => read_function_name(ctx.stream, string_len),
+            _ => String::new(),
+        };
+
+        for handler in self.handlers.iter_mut() {
+            match *event {
+                Event::PreInit { rt, pid } => handler.on_pre_init(ctx, rt, pid),
+                Event::Init { n_threads } => handler.on_init(ctx, n_threads),
+                Event::AddTask { task_id, func_id, priority, scope_id } => handler.on_add_task(ctx, task_id, func_id, priority, scope_id),
+                Event::RegisterFunction { func_id, string_len } => handler.on_register_function(ctx, func_id, &name, string_len),
+                Event::AddDependency { to_id, from_id, memaddr, orig_memaddr } => handler.on_add_dependency(ctx, to_id, from_id, memaddr, orig_memaddr),
+                Event::AddTaskToQueue { task_id, thread_id } => handler.on_add_task_to_queue(ctx, task_id, thread_id),
+                Event::PreRunTask { task_id, thread_id } => handler.on_pre_run_task(ctx, task_id, thread_id),
+                Event::RunTask { task_id } => handler.on_run_task(ctx, task_id),
+                Event::PostRunTask { task_id } => handler.on_post_run_task(ctx, task_id),
+                Event::RemoveTask { task_id } => handler.on_remove_task(ctx, task_id),
+                Event::Barrier => handler.on_barrier(ctx),
+                Event::WaitOn { task_id } => handler.on_wait_on(ctx, task_id),
+                Event::Finish => handler.on_finish(ctx),
+            }
+            handler.on_event(ctx, event);
+        }
+
+        match *event {
+            _ if ctx.is_stopped => EventResult::Stop,
+            Event::Finish => EventResult::Exit,
+            _ => EventResult::Success,
+        }
     }
 
-    EventResult::Success
+    /// Call all handlers for a buffer, which could not be decoded.
+    pub fn decode_error(&mut self, error: &EventError) {
+        self.handlers.iter_mut().for_each(|h| h.on_decode_error(error));
+    }
 }
 
-fn handle_register_function(func_id: u64, name: String, string_len: usize, state_lock: &RwLock<AppState>) -> EventResult {
-    println!("Got RegisterFunction event");
+/// Try to read the name of a registered function from the stream.
+fn read_function_name(mut stream: &TcpStream, string_len: usize) -> String {
+    if string_len == 0 {
+        return String::new();
+    }
+
+    // read exactly the name, the next event may already follow it
+    let mut buf = vec![0; string_len];
+    if stream.read_exact(&mut buf).is_err() {
+        eprintln!("String Length doesn't match bytes read, defaulting to empty function name.");
+        return String::new();
+    }
+
+    let name = events::read_function_name_from_buffer(&buf);
     if name.len() != string_len {
         eprintln!("name length({}) not matching provided string_len ({}).", name.len(), string_len)
     }
+    name
+}
 
-    if let Ok(mut state) = state_lock.write() {
-        state.create_function(func_id, name);
+/// Updates the state of the Application (which tasks are currently added etc.).
+pub struct StateHandler;
+
+impl EventHandler for StateHandler {
+    fn on_pre_init(&mut self, ctx: &mut EventContext, _rt: u64, _pid: u64) {
+        if let Ok(mut state) = ctx.state.write() {
+            state.is_pre_init = true;
+        }
     }
 
-    EventResult::Success
-}
+    fn on_init(&mut self, ctx: &mut EventContext, _n_threads: u64) {
+        if let Ok(mut state) = ctx.state.write() {
+            state.is_init = true;
+        }
+    }
 
-fn handle_add_dependency(to_id: u64, from_id: u64, memaddr: u64, orig_memaddr: u64, state_lock: &RwLock<AppState>) -> EventResult {
-    println!("Got AddDependency event, memadd: {:x}, orig_memaddr: {:x}", memaddr, orig_memaddr);
-    if let Ok(mut state) = state_lock.write() {
-        state.add_dependency(to_id, from_id);
+    fn on_register_function(&mut self, ctx: &mut EventContext, func_id: u64, name: &str, _string_len: usize) {
+        if let Ok(mut state) = ctx.state.write() {
+            state.create_function(func_id, name.to_string());
+        }
     }
 
-    EventResult::Success
-}
+    fn on_add_task(&mut self, ctx: &mut EventContext, task_id: u64, func_id: u64, priority: u64, scope_id: u64) {
+        let is_critical = priority > 0;
+        if let Ok(mut state) = ctx.state.write() {
+            // fall back to a task without function, if the function was never registered
+            if state.create_task(task_id, is_critical, Some(func_id), scope_id).is_err() {
+                let _ = state.create_task(task_id, is_critical, None, scope_id);
+            }
+            state.record_task_time(task_id, TaskState::Created, ctx.meta.timestamp, None);
+        }
+    }
 
-fn handle_add_task_to_queue(task_id: u64, thread_id: u64, _state: &RwLock<AppState>) -> EventResult {
-    println!("Got add AddTaskToQueue event, task_id: {task_id}, thread_id: {thread_id}");
-    
-    EventResult::Success
-}
+    fn on_add_dependency(&mut self, ctx: &mut EventContext, to_id: u64, from_id: u64, _memaddr: u64, _orig_memaddr: u64) {
+        if let Ok(mut state) = ctx.state.write() {
+            // to_id depends on from_id, so from_id is the parent
+            state.add_dependency(from_id, to_id);
+        }
+    }
 
-fn handle_pre_run_task(task_id: u64, thread_id: u64, _state: &RwLock<AppState>) -> EventResult {
-    println!("Got PreRunTask event, task_id: {task_id}, thread_id: {thread_id}");
-    
-    EventResult::Success
-}
+    fn on_add_task_to_queue(&mut self, ctx: &mut EventContext, task_id: u64, thread_id: u64) {
+        set_task_state(ctx, task_id, TaskState::Queued, Some(thread_id));
+    }
 
-fn handle_run_task(task_id: u64, _state: &RwLock<AppState>) -> EventResult {
-    println!("Got RunTask event, task_id: {task_id}");
+    fn on_pre_run_task(&mut self, ctx: &mut EventContext, task_id: u64, thread_id: u64) {
+        set_task_state(ctx, task_id, TaskState::PreRun, Some(thread_id));
+    }
 
-    EventResult::Success
-}
+    fn on_run_task(&mut self, ctx: &mut EventContext, task_id: u64) {
+        set_task_state(ctx, task_id, TaskState::Running, None);
+    }
 
-fn handle_post_run_task(task_id: u64, _state: &RwLock<AppState>) -> EventResult {
-    println!("Got PostRunTask event, task_id: {task_id} ");
+    fn on_post_run_task(&mut self, ctx: &mut EventContext, task_id: u64) {
+        set_task_state(ctx, task_id, TaskState::Finished, None);
+    }
 
-    EventResult::Success
+    fn on_remove_task(&mut self, ctx: &mut EventContext, task_id: u64) {
+        if let Ok(mut state) = ctx.state.write() {
+            state.delete_task(task_id);
+        }
+    }
 }
 
-fn handle_remove_task(task_id: u64, state_lock: &RwLock<AppState>) -> EventResult {
-    println!("Got RemoveTask event, task_id: {task_id}");
-    if let Ok(mut state) = state_lock.write() {
-        state.delete_task(task_id);
+/// Update the state of a task and record the timestamp of the event.
+fn set_task_state(ctx: &EventContext, task_id: u64, task_state: TaskState, thread_id: Option<u64>) {
+    if let Ok(mut state) = ctx.state.write() {
+        state.set_task_state(task_id, task_state);
+        state.record_task_time(task_id, task_state, ctx.meta.timestamp, thread_id);
     }
-    EventResult::Success
 }
 
-fn handle_barrier(_state: &RwLock<AppState>) -> EventResult {
-    println!("Got Barrier event.");
+/// Prints a human readable message for every event, which is the default output of the frontend.
+pub struct PrintHandler;
 
-    EventResult::Success
-}
+impl EventHandler for PrintHandler {
+    fn on_pre_init(&mut self, _ctx: &mut EventContext, rt: u64, pid: u64) {
+        println!("Got PreInit event, rt: {rt}, pid: {pid}");
+    }
+
+    fn on_init(&mut self, _ctx: &mut EventContext, n_threads: u64) {
+        println!("Got Init event, n_threads: {n_threads}");
+    }
+
+    fn on_register_function(&mut self, _ctx: &mut EventContext, _func_id: u64, _name: &str, _string_len: usize) {
+        println!("Got RegisterFunction event");
+    }
+
+    fn on_add_task(&mut self, _ctx: &mut EventContext, _task_id: u64, _func_id: u64, _priority: u64, _scope_id: u64) {
+        println!("Got AddTask Event.");
+    }
+
+    fn on_add_dependency(&mut self, _ctx: &mut EventContext, _to_id: u64, _from_id: u64, memaddr: u64, orig_memaddr: u64) {
+        println!("Got AddDependency event, memadd: {:x}, orig_memaddr: {:x}", memaddr, orig_memaddr);
+    }
+
+    fn on_add_task_to_queue(&mut self, _ctx: &mut EventContext, task_id: u64, thread_id: u64) {
+        println!("Got add AddTaskToQueue event, task_id: {task_id}, thread_id: {thread_id}");
+    }
 
-fn handle_wait_on(task_id: u64,_state: &RwLock<AppState>) -> EventResult {
-    println!("Got WaitOn event, task_id: {task_id}");
+    fn on_pre_run_task(&mut self, _ctx: &mut EventContext, task_id: u64, thread_id: u64) {
+        println!("Got PreRunTask event, task_id: {task_id}, thread_id: {thread_id}");
+    }
+
+    fn on_run_task(&mut self, _ctx: &mut EventContext, task_id: u64) {
+        println!("Got RunTask event, task_id: {task_id}");
+    }
+
+    fn on_post_run_task(&mut self, _ctx: &mut EventContext, task_id: u64) {
+        println!("Got PostRunTask event, task_id: {task_id} ");
+    }
+
+    fn on_remove_task(&mut self, _ctx: &mut EventContext, task_id: u64) {
+        println!("Got RemoveTask event, task_id: {task_id}");
+    }
+
+    fn on_barrier(&mut self, _ctx: &mut EventContext) {
+        println!("Got Barrier event.");
+    }
 
-    EventResult::Success
+    fn on_wait_on(&mut self, _ctx: &mut EventContext, task_id: u64) {
+        println!("Got WaitOn event, task_id: {task_id}");
+    }
+
+    fn on_finish(&mut self, ctx: &mut EventContext) {
+        println!("Got finish event, exiting...");
+        print!("{}", commands::stats(&ctx.state.read().unwrap(), None).unwrap_or_default());
+    }
+
+    fn on_decode_error(&mut self, error: &EventError) {
+        eprintln!("Unable to handle received event: {}", error);
+    }
 }
 
-fn handle_finish(_state: &RwLock<AppState>) -> EventResult {
-    println!("Got finish event, exiting...");
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::*;
+
+    /// Counts the events and stops the session after the given number of events.
+    struct StopAfter(usize);
+
+    impl EventHandler for StopAfter {
+        fn on_event(&mut self, ctx: &mut EventContext, _event: &Event) {
+            self.0 -= 1;
+            if self.0 == 0 {
+                ctx.stop();
+            }
+        }
+    }
 
-    EventResult::Exit
-}
\ No newline at end of file
+    #[test]
+    fn handler_chain() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let state = Arc::new(RwLock::new(AppState::new()));
+        let meta = EventMeta { rt: 0, timestamp: Duration::ZERO, received: UNIX_EPOCH };
+
+        let mut stop_after = StopAfter(3);
+        let mut handlers = HandlerChain::new();
+        handlers.push(StateHandler);
+        handlers.push(&mut stop_after);
+
+        let mut handle = |event| handlers.handle(&event, &mut EventContext::new(&meta, &state, &stream));
+        assert_eq!(handle(Event::AddTask { task_id: 1, func_id: 2, priority: 0, scope_id: 0 }), EventResult::Success);
+        assert_eq!(handle(Event::RunTask { task_id: 1 }), EventResult::Success);
+        assert_eq!(state.read().unwrap().get_tasks()[0].get_state(), TaskState::Running);
+        assert_eq!(handle(Event::Finish), EventResult::Stop);
+        drop(handlers);
+        assert_eq!(stop_after.0, 0);
+    }
+
+    #[test]
+    fn state_handler_stores_parent_and_function() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let state = Arc::new(RwLock::new(AppState::new()));
+        let meta = EventMeta { rt: 0, timestamp: Duration::ZERO, received: UNIX_EPOCH };
+
+        let mut handlers = HandlerChain::new();
+        handlers.push(StateHandler);
+        let mut handle = |event| handlers.handle(&event, &mut EventContext::new(&meta, &state, &stream));
+        handle(Event::RegisterFunction { func_id: 5, string_len: 0 });
+        handle(Event::AddTask { task_id: 1, func_id: 5, priority: 0, scope_id: 0 });
+        handle(Event::AddTask { task_id: 2, func_id: 9, priority: 0, scope_id: 0 });
+        // task 2 depends on task 1
+        handle(Event::AddDependency { to_id: 2, from_id: 1, memaddr: 0, orig_memaddr: 0 });
+
+        let state = state.read().unwrap();
+        assert_eq!(state.get_task(1).unwrap().get_function().map(|f| f.id), Some(5));
+        assert!(state.get_task(2).unwrap().get_function().is_none());
+        assert_eq!(state.get_task(1).unwrap().get_child_ids(), [2]);
+        assert_eq!(state.get_task(2).unwrap().get_parent_ids(), [1]);
+    }
+}
@@ -0,0 +1,355 @@
+use std::{fmt::Display, sync::{Arc, Mutex}};
+
+use utils::{events::Event, AppState, TaskState};
+
+use crate::{ayu_event_handlers::{EventContext, EventHandler}, commands::{self, Command}};
+
+/// A condition, which is evaluated by the frontend for every incoming event.
+///
+/// Ayudame itself only knows `Break`, `Continue` and `BreakAtTask`, so these conditions are checked
+/// on the client side and a `Break` request is sent as soon as one of them matches.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Condition {
+    /// A task of the function with the given id or name is about to run.
+    FunctionStarts(String),
+    /// A task is about to run on the thread with the given id.
+    ThreadRuns(u64),
+    /// The given number of tasks has finished. Triggers only once.
+    TasksFinished { n: u64, finished: u64 },
+    /// A task has more than the given number of parents.
+    ParentsExceed(usize),
+}
+
+impl Condition {
+    /// Returns a description of the match, if the event (which has already been applied to the state) matches the condition.
+    fn matches(&mut self, event: &Event, state: &AppState) -> Option<String> {
+        match (self, *event) {
+            (Condition::FunctionStarts(function), Event::PreRunTask { task_id, .. }) => {
+                let expected = state.find_function(function)?;
+                let actual = state.get_task(task_id)?.get_function()?;
+                (expected.id == actual.id).then(|| format!("task {} of function {} starts", task_id, actual.get_name()))
+            },
+            (Condition::ThreadRuns(thread), Event::PreRunTask { task_id, thread_id }) => {
+                (*thread == thread_id).then(|| format!("task {} starts on thread {}", task_id, thread_id))
+            },
+            (Condition::TasksFinished { n, finished }, Event::PostRunTask { .. }) => {
+                // keep counting after the breakpoint was hit, so it does not trigger again
+                *finished += 1;
+                (*finished == *n).then(|| format!("{} tasks finished", n))
+            },
+            (Condition::ParentsExceed(k), Event::AddDependency { to_id, .. }) => {
+                let parents = state.get_task(to_id)?.get_parent_ids().len();
+                (parents > *k).then(|| format!("task {} has {} parents", to_id, parents))
+            },
+            _ => None,
+        }
+    }
+}
+
+impl Display for Condition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Condition::FunctionStarts(function) => write!(f, "when a task of function {} starts", function),
+            Condition::ThreadRuns(thread_id) => write!(f, "when thread {} runs a task", thread_id),
+            Condition::TasksFinished { n, finished } => write!(f, "after {} tasks finished ({} so far)", n, finished),
+            Condition::ParentsExceed(k) => write!(f, "when a task has more than {} parents", k),
+        }
+    }
+}
+
+/// All conditional breakpoints set by the user.
+/// 
+/// Also keeps track of the functions, for which a pause on function is emulated with break at task requests, 
+/// together with the tasks a break was requested for.
+/// 
+/// Functions, for which a pause on function request was sent, are watched: if a task of such a function runs
+/// on without a continue or step in between, Ayudame does not support the request and the pause is emulated instead.
+#[derive(Debug, Default)]
+pub struct Breakpoints {
+    next_id: usize,
+    conditions: Vec<(usize, Condition)>,
+    emulated_functions: Vec<(u64, Vec<u64>)>,
+    requested_functions: Vec<u64>,
+    /// The function and task, for which Ayudame should have paused.
+    expected_pause: Option<(u64, u64)>,
+}
+
+impl Breakpoints {
+    /// Creates an empty list of breakpoints.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a new conditional breakpoint and return its id.
+    pub fn add(&mut self, condition: Condition) -> usize {
+        self.next_id += 1;
+        self.conditions.push((self.next_id, condition));
+        self.next_id
+    }
+
+    /// Remove the breakpoint with the given id.
+    pub fn delete(&mut self, id: usize) -> Option<Condition> {
+        let idx = self.conditions.iter().position(|(i, _)| *i == id)?;
+        Some(self.conditions.remove(idx).1)
+    }
+
+    /// Check all conditions for an event. Returns the id of the first breakpoint that was hit and a description.
+    ///
+    /// All conditions are evaluated, so counting conditions stay up to date.
+    pub fn check(&mut self, event: &Event, state: &AppState) -> Option<(usize, String)> {
+        let mut hit = None;
+        for (id, condition) in self.conditions.iter_mut() {
+            if let Some(reason) = condition.matches(event, state) {
+                hit = hit.or(Some((*id, reason)));
+            }
+        }
+
+        hit
+    }
+
+    /// Start emulating a pause on function for the function with the given id.
+    /// 
+    /// Returns the ids of all tasks of the function, which did not run yet and need a break at task request.
+    pub fn emulate_pause_on_function(&mut self, function_id: u64, state: &AppState) -> Vec<u64> {
+        let tasks = state.get_tasks()
+            .iter()
+            .filter(|t| matches!(t.get_state(), TaskState::Created | TaskState::Queued))
+            .filter(|t| t.get_function().is_some_and(|f| f.id == function_id))
+            .map(|t| t.get_id())
+            .collect::<Vec<_>>();
+
+        match self.emulated_functions.iter_mut().find(|(id, _)| *id == function_id) {
+            Some((_, broken)) => broken.extend(tasks.iter()),
+            None => self.emulated_functions.push((function_id, tasks.clone())),
+        }
+
+        tasks
+    }
+
+    /// Stop emulating a pause on function for the function with the given id.
+    /// 
+    /// Returns the ids of all tasks, for which a break at task was requested.
+    pub fn stop_emulating_pause_on_function(&mut self, function_id: u64) -> Vec<u64> {
+        match self.emulated_functions.iter().position(|(id, _)| *id == function_id) {
+            Some(idx) => self.emulated_functions.remove(idx).1,
+            None => Vec::new(),
+        }
+    }
+
+    /// Returns true, if a pause on function is emulated for the function with the given id.
+    pub fn is_emulated(&self, function_id: u64) -> bool {
+        self.emulated_functions.iter().any(|(id, _)| *id == function_id)
+    }
+
+    /// Remember that a pause on function request was sent, so a missing pause can be detected.
+    pub fn request_pause_on_function(&mut self, function_id: u64, is_on: bool) {
+        self.requested_functions.retain(|id| *id != function_id);
+        if is_on {
+            self.requested_functions.push(function_id);
+        }
+    }
+
+    /// Called before a continue or step request is sent, so a task may run on after a pause.
+    ///
+    /// Returns the pause, which was expected before, so it can be restored if the request could not be sent.
+    pub fn resume(&mut self) -> Option<(u64, u64)> {
+        self.expected_pause.take()
+    }
+
+    /// Expect the pause again, which was returned by [Breakpoints::resume], unless a new pause is expected already.
+    pub fn undo_resume(&mut self, expected_pause: Option<(u64, u64)>) {
+        self.expected_pause = self.expected_pause.or(expected_pause);
+    }
+
+    /// Returns the id of a requested function, if one of its tasks ran on without pausing.
+    ///
+    /// The function is no longer watched, it is up to the caller to emulate the pause.
+    pub fn missing_pause(&mut self, event: &Event, state: &AppState) -> Option<u64> {
+        match *event {
+            Event::PreRunTask { task_id, .. } => {
+                let function_id = state.get_task(task_id)?.get_function()?.id;
+                if self.requested_functions.contains(&function_id) {
+                    self.expected_pause = Some((function_id, task_id));
+                }
+                None
+            },
+            Event::RunTask { task_id } | Event::PostRunTask { task_id } => {
+                let (function_id, _) = self.expected_pause.take_if(|(_, id)| *id == task_id)?;
+                self.requested_functions.retain(|id| *id != function_id);
+                Some(function_id)
+            },
+            _ => None,
+        }
+    }
+
+    /// Returns the id of a newly added task, if its function is paused on by emulation.
+    pub fn task_to_break(&mut self, event: &Event) -> Option<u64> {
+        let Event::AddTask { task_id, func_id, .. } = *event else {
+            return None;
+        };
+        let (_, broken) = self.emulated_functions.iter_mut().find(|(id, _)| *id == func_id)?;
+        broken.push(task_id);
+
+        Some(task_id)
+    }
+}
+
+impl Display for Breakpoints {
+    /// Lists all breakpoints, one per line.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.conditions.is_empty() {
+            writeln!(f, "No conditional breakpoints set.")?;
+        }
+        for (id, condition) in &self.conditions {
+            writeln!(f, "{}: break {}", id, condition)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Checks the conditional breakpoints for every event and sends a break request, if one of them matches.
+///
+/// Also sends a break at task request for new tasks of functions, for which pause on function is emulated,
+/// and falls back to emulation, if Ayudame does not pause on a requested function.
+pub struct BreakpointHandler {
+    breakpoints: Arc<Mutex<Breakpoints>>,
+    /// Called with a message for every request that was sent.
+    report: Box<dyn FnMut(String) + Send>,
+}
+
+impl BreakpointHandler {
+    /// Creates a handler for the breakpoints, which are shared with the command line.
+    pub fn new(breakpoints: Arc<Mutex<Breakpoints>>, report: impl FnMut(String) + Send + 'static) -> Self {
+        Self { breakpoints, report: Box::new(report) }
+    }
+}
+
+impl EventHandler for BreakpointHandler {
+    fn on_event(&mut self, ctx: &mut EventContext, event: &Event) {
+        let hit = self.breakpoints.lock().unwrap().check(event, &ctx.state.read().unwrap());
+        if let Some((id, reason)) = hit {
+            (self.report)(format!("Conditional breakpoint {} hit: {}, sending break request", id, reason));
+            let _ = commands::send(&Command::Break, ctx.state, ctx.stream);
+        }
+
+        let missing = self.breakpoints.lock().unwrap().missing_pause(event, &ctx.state.read().unwrap());
+        if let Some(function_id) = missing {
+            let tasks = self.breakpoints.lock().unwrap().emulate_pause_on_function(function_id, &ctx.state.read().unwrap());
+            (self.report)(format!(
+                "Ayudame did not pause on function {}, sending break request and emulating with break at task for {} tasks",
+                function_id, tasks.len()
+            ));
+            let _ = commands::send(&Command::Break, ctx.state, ctx.stream);
+            for task_id in tasks {
+                let _ = commands::send(&Command::BreakAtTask(task_id), ctx.state, ctx.stream);
+            }
+        }
+
+        let task_to_break = self.breakpoints.lock().unwrap().task_to_break(event);
+        if let Some(task_id) = task_to_break {
+            (self.report)(format!("Emulating pause on function, sending break at task {}", task_id));
+            let _ = commands::send(&Command::BreakAtTask(task_id), ctx.state, ctx.stream);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn function_and_thread_conditions() {
+        let mut state = AppState::new();
+        state.create_function(4, "foo".to_string());
+        let _ = state.create_task(1, false, Some(4), 0);
+        let _ = state.create_task(2, false, None, 0);
+
+        let mut breakpoints = Breakpoints::new();
+        let foo = breakpoints.add(Condition::FunctionStarts("foo".to_string()));
+        let thread = breakpoints.add(Condition::ThreadRuns(3));
+
+        assert_eq!(breakpoints.check(&Event::PreRunTask { task_id: 2, thread_id: 0 }, &state), None);
+        assert_eq!(breakpoints.check(&Event::PreRunTask { task_id: 1, thread_id: 0 }, &state).map(|h| h.0), Some(foo));
+        assert_eq!(breakpoints.check(&Event::PreRunTask { task_id: 2, thread_id: 3 }, &state).map(|h| h.0), Some(thread));
+
+        assert!(breakpoints.delete(foo).is_some());
+        assert_eq!(breakpoints.check(&Event::PreRunTask { task_id: 1, thread_id: 0 }, &state), None);
+    }
+
+    #[test]
+    fn emulate_pause_on_function() {
+        let mut state = AppState::new();
+        state.create_function(4, "foo".to_string());
+        let _ = state.create_task(1, false, Some(4), 0);
+        let _ = state.create_task(2, false, Some(4), 0);
+        let _ = state.create_task(3, false, None, 0);
+        state.set_task_state(2, TaskState::Running);
+
+        let mut breakpoints = Breakpoints::new();
+        assert_eq!(breakpoints.emulate_pause_on_function(4, &state), vec![1]);
+
+        let event = Event::AddTask { task_id: 5, func_id: 4, priority: 0, scope_id: 0 };
+        assert_eq!(breakpoints.task_to_break(&event), Some(5));
+        let event = Event::AddTask { task_id: 6, func_id: 0, priority: 0, scope_id: 0 };
+        assert_eq!(breakpoints.task_to_break(&event), None);
+
+        assert_eq!(breakpoints.stop_emulating_pause_on_function(4), vec![1, 5]);
+        let event = Event::AddTask { task_id: 7, func_id: 4, priority: 0, scope_id: 0 };
+        assert_eq!(breakpoints.task_to_break(&event), None);
+    }
+
+    #[test]
+    fn fall_back_to_emulation() {
+        let mut state = AppState::new();
+        state.create_function(4, "foo".to_string());
+        for id in 1..=3 {
+            let _ = state.create_task(id, false, Some(4), 0);
+        }
+
+        let mut breakpoints = Breakpoints::new();
+        breakpoints.request_pause_on_function(4, true);
+
+        // Ayudame paused and the user continued
+        assert_eq!(breakpoints.missing_pause(&Event::PreRunTask { task_id: 1, thread_id: 0 }, &state), None);
+        assert_eq!(breakpoints.resume(), Some((4, 1)));
+        assert_eq!(breakpoints.missing_pause(&Event::RunTask { task_id: 1 }, &state), None);
+
+        // Ayudame did not pause
+        assert_eq!(breakpoints.missing_pause(&Event::PreRunTask { task_id: 2, thread_id: 0 }, &state), None);
+        assert_eq!(breakpoints.missing_pause(&Event::RunTask { task_id: 2 }, &state), Some(4));
+        assert_eq!(breakpoints.missing_pause(&Event::PreRunTask { task_id: 3, thread_id: 0 }, &state), None);
+        assert_eq!(breakpoints.missing_pause(&Event::RunTask { task_id: 3 }, &state), None);
+    }
+
+    #[test]
+    fn tasks_finished_condition() {
+        let state = AppState::new();
+        let mut breakpoints = Breakpoints::new();
+        breakpoints.add(Condition::TasksFinished { n: 2, finished: 0 });
+
+        let event = Event::PostRunTask { task_id: 1 };
+        assert!(breakpoints.check(&event, &state).is_none());
+        assert!(breakpoints.check(&event, &state).is_some());
+        assert!(breakpoints.check(&event, &state).is_none());
+        assert!(breakpoints.check(&event, &state).is_none());
+    }
+
+    #[test]
+    fn parents_exceed_condition() {
+        let mut state = AppState::new();
+        for id in 1..=3 {
+            let _ = state.create_task(id, false, None, 0);
+        }
+        let mut breakpoints = Breakpoints::new();
+        breakpoints.add(Condition::ParentsExceed(1));
+
+        state.add_dependency(1, 3);
+        let event = Event::AddDependency { to_id: 3, from_id: 1, memaddr: 0, orig_memaddr: 0 };
+        assert!(breakpoints.check(&event, &state).is_none());
+
+        state.add_dependency(2, 3);
+        let event = Event::AddDependency { to_id: 3, from_id: 2, memaddr: 0, orig_memaddr: 0 };
+        assert!(breakpoints.check(&event, &state).is_some());
+    }
+}
@@ -0,0 +1,350 @@
+use std::{collections::VecDeque, io::Cursor, net::{TcpStream, ToSocketAddrs}, sync::{mpsc::{self, Receiver, Sender}, Arc, Mutex, RwLock}, thread};
+
+use serde::{Deserialize, Serialize};
+use tiny_http::{Header, Method, Request as HttpRequest, Response, Server};
+use tungstenite::{handshake::derive_accept_key, protocol::Role, Message, WebSocket};
+use utils::{events::{Event, EventMeta}, stats::TaskTiming, AppState, TaskState};
+
+use crate::{ayu_event_handlers::{EventContext, EventHandler}, breakpoints::Breakpoints, commands::{self, Command}, export::{self, Recording}, output};
+
+/// Number of events kept for `GET /events`.
+const RECENT_EVENTS: usize = 1000;
+
+/// A response of the http server.
+type HttpResponse = Response<Cursor<Vec<u8>>>;
+
+/// Snapshot of the state, as returned by `GET /state`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StateSnapshot {
+    pub is_pre_init: bool,
+    pub is_init: bool,
+    pub functions: Vec<FunctionSnapshot>,
+    pub tasks: Vec<TaskSnapshot>,
+    /// Pairs of (parent, child).
+    pub dependencies: Vec<(u64, u64)>,
+    /// Timestamps of all tasks, including removed ones.
+    #[serde(default)]
+    pub timings: Vec<TaskTiming>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FunctionSnapshot {
+    pub id: u64,
+    pub name: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TaskSnapshot {
+    pub id: u64,
+    pub state: TaskState,
+    pub function: Option<u64>,
+    pub parents: Vec<u64>,
+    pub children: Vec<u64>,
+}
+
+impl StateSnapshot {
+    pub fn new(state: &AppState) -> Self {
+        Self {
+            is_pre_init: state.is_pre_init,
+            is_init: state.is_init,
+            functions: state.get_functions()
+                .iter()
+                .map(|f| FunctionSnapshot { id: f.id, name: f.get_name().to_string() })
+                .collect(),
+            tasks: state.get_tasks()
+                .iter()
+                .map(|t| TaskSnapshot {
+                    id: t.get_id(),
+                    state: t.get_state(),
+                    function: t.get_function().map(|f| f.id),
+                    parents: t.get_parent_ids(),
+                    children: t.get_child_ids(),
+                })
+                .collect(),
+            dependencies: state.get_dependencies(),
+            timings: state.get_task_timings().cloned().collect(),
+        }
+    }
+}
+
+/// Connects the received events to the http server.
+///
+/// Keeps the most recent events and pushes new events to all WebSocket clients.
+#[derive(Debug, Default)]
+pub struct Bridge {
+    recent: Mutex<VecDeque<String>>,
+    subscribers: Mutex<Vec<Sender<String>>>,
+}
+
+impl Bridge {
+    /// Creates a bridge without any events or clients.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a received event and push it to all WebSocket clients, the event needs to be handled already.
+    pub fn publish(&self, event: &Event, meta: &EventMeta, state: &AppState) {
+        let line = output::to_json_line(event, meta, state);
+
+        // clients which disconnected dropped their receiver
+        self.subscribers.lock().unwrap().retain(|s| s.send(line.clone()).is_ok());
+
+        let mut recent = self.recent.lock().unwrap();
+        recent.push_back(line);
+        if recent.len() > RECENT_EVENTS {
+            recent.pop_front();
+        }
+    }
+
+    /// Get a channel, which receives all events published from now on.
+    fn subscribe(&self) -> Receiver<String> {
+        let (sender, receiver) = mpsc::channel();
+        self.subscribers.lock().unwrap().push(sender);
+        receiver
+    }
+
+    /// All recent events as json array.
+    fn recent_events(&self) -> String {
+        let recent = self.recent.lock().unwrap();
+        format!("[{}]", recent.iter().map(String::as_str).collect::<Vec<_>>().join(","))
+    }
+}
+
+impl EventHandler for Arc<Bridge> {
+    fn on_event(&mut self, ctx: &mut EventContext, event: &Event) {
+        self.publish(event, ctx.meta, &ctx.state.read().unwrap());
+    }
+}
+
+/// Start a http server on the given address, which runs on another thread.
+///
+/// - `GET /state`: the current state as json
+/// - `GET /graph.dot`: the task graph in the DOT format
+/// - `GET /gantt.svg`: a Gantt chart of the tasks, which ran so far
+/// - `GET /events`: the most recent events as json array
+/// - `GET /ws`: WebSocket, which pushes every received event as json
+/// - `POST /break`, `/continue`, `/step?n=<n>`, `/break-at-task?task=<id>`: send a request to Ayudame
+///
+/// POST requests from another origin are rejected, so other websites opened in a browser cannot control the application.
+/// If the server is bound to loopback, POST requests must also name a loopback address in the `Host` header,
+/// otherwise a website could resolve its own name to 127.0.0.1 (DNS rebinding) and pass the origin check.
+/// They are executed like commands of the command line, with the breakpoints of the session.
+pub fn serve(address: &str, bridge: Arc<Bridge>, state: Arc<RwLock<AppState>>, breakpoints: Arc<Mutex<Breakpoints>>, stream: TcpStream) -> Result<(), String> {
+    let server = Server::http(address).map_err(|e| format!("Unable to start http server on {}: {}", address, e))?;
+    let is_loopback = address.to_socket_addrs().is_ok_and(|mut addrs| addrs.all(|a| a.ip().is_loopback()));
+    if !is_loopback {
+        eprintln!("Warning: the http server on {} is reachable from other hosts, which can pause and step the application.", address);
+    }
+    let loopback_port = server.server_addr().to_ip().filter(|_| is_loopback).map(|a| a.port());
+
+    thread::spawn(move || {
+        for request in server.incoming_requests() {
+            handle_request(request, &bridge, &state, &breakpoints, loopback_port, &stream);
+        }
+    });
+
+    Ok(())
+}
+
+/// Respond to a single http request. `loopback_port` is set, if the server is bound to loopback.
+fn handle_request(request: HttpRequest, bridge: &Bridge, state: &Arc<RwLock<AppState>>, breakpoints: &Mutex<Breakpoints>, loopback_port: Option<u16>, stream: &TcpStream) {
+    let url = request.url().to_string();
+    let (path, query) = url.split_once('?').unwrap_or((&url, ""));
+
+    let response = match (request.method(), path) {
+        (Method::Get, "/state") => {
+            let snapshot = StateSnapshot::new(&state.read().unwrap());
+            json(200, serde_json::to_string(&snapshot).unwrap_or_default()).with_header(allow_any_origin())
+        },
+        (Method::Get, "/graph.dot") => Response::from_string(state.read().unwrap().to_dot())
+            .with_header(header("Content-Type", "text/vnd.graphviz"))
+            .with_header(allow_any_origin()),
+        (Method::Get, "/gantt.svg") => Response::from_string(export::to_svg(&Recording::from_state(&state.read().unwrap())))
+            .with_header(header("Content-Type", "image/svg+xml"))
+            .with_header(allow_any_origin()),
+        (Method::Get, "/events") => json(200, bridge.recent_events()).with_header(allow_any_origin()),
+        (Method::Get, "/ws") => return upgrade(request, bridge),
+        (Method::Post, _) if !is_same_origin(&request) => error(403, "Requests from another origin are not allowed"),
+        (Method::Post, _) if loopback_port.is_some_and(|port| !has_loopback_host(&request, port)) => error(403, "Requests for another host are not allowed"),
+        (Method::Post, path) => post(path, query, state, breakpoints, stream),
+        _ => error(404, "Not found"),
+    };
+
+    let _ = request.respond(response);
+}
+
+/// Returns true, if the request has no `Origin` header (e.g. curl) or was sent by a page served from the same host.
+fn is_same_origin(request: &HttpRequest) -> bool {
+    let field = |name: &'static str| request.headers().iter().find(|h| h.field.equiv(name)).map(|h| h.value.as_str());
+    match (field("Origin"), field("Host")) {
+        (None, _) => true,
+        (Some(origin), Some(host)) => origin_matches_host(origin, host),
+        (Some(_), None) => false,
+    }
+}
+
+/// Returns true, if the `Host` header is `localhost`, `127.0.0.1` or `[::1]` with the port of the server.
+fn has_loopback_host(request: &HttpRequest, port: u16) -> bool {
+    let host = request.headers().iter().find(|h| h.field.equiv("Host")).map(|h| h.value.as_str());
+    host.is_some_and(|host| is_loopback_host(host, port))
+}
+
+fn is_loopback_host(host: &str, port: u16) -> bool {
+    ["localhost", "127.0.0.1", "[::1]"].iter().any(|name| host.eq_ignore_ascii_case(&format!("{}:{}", name, port)))
+}
+
+/// Compare the host and port of an origin like `http://localhost:8080` with a `Host` header.
+fn origin_matches_host(origin: &str, host: &str) -> bool {
+    origin.strip_prefix("http://").is_some_and(|authority| authority.eq_ignore_ascii_case(host))
+}
+
+/// Execute the command of a POST endpoint.
+fn post(path: &str, query: &str, state: &Arc<RwLock<AppState>>, breakpoints: &Mutex<Breakpoints>, stream: &TcpStream) -> HttpResponse {
+    match post_command(path, query) {
+        Ok(command) => match commands::execute(&command, state, breakpoints, stream) {
+            Ok(_) => json(200, String::from(r#"{"ok":true}"#)),
+            Err(e) => error(400, &e.to_string()),
+        },
+        Err((status, msg)) => error(status, &msg),
+    }
+}
+
+/// Get the command for a POST endpoint, e.g. `/step?n=3` or `/break-at-task?task=5`.
+fn post_command(path: &str, query: &str) -> Result<Command, (u16, String)> {
+    let param = |name: &str| query.split('&').find_map(|p| p.strip_prefix(name)?.strip_prefix('='));
+
+    let command = match path {
+        "/break" => Command::Break,
+        "/continue" => Command::Continue,
+        "/step" => match param("n") {
+            Some(n) => Command::Step(n.parse().map_err(|_| (400, format!("Invalid number of steps: {}", n)))?),
+            None => Command::Step(1),
+        },
+        "/break-at-task" => {
+            let task_id = param("task").ok_or((400, String::from("Missing parameter: task")))?;
+            Command::BreakAtTask(task_id.parse().map_err(|_| (400, format!("Invalid task id: {}", task_id)))?)
+        },
+        _ => return Err((404, String::from("Not found"))),
+    };
+
+    Ok(command)
+}
+
+/// Complete the WebSocket handshake and push all events to the client on another thread.
+fn upgrade(request: HttpRequest, bridge: &Bridge) {
+    let key = request.headers()
+        .iter()
+        .find(|h| h.field.equiv("Sec-WebSocket-Key"))
+        .map(|h| h.value.to_string());
+    let Some(key) = key else {
+        let _ = request.respond(error(400, "Missing Sec-WebSocket-Key"));
+        return;
+    };
+
+    let response = Response::empty(101).with_header(header("Sec-WebSocket-Accept", &derive_accept_key(key.as_bytes())));
+    let socket = request.upgrade("websocket", response);
+    let events = bridge.subscribe();
+
+    thread::spawn(move || {
+        let mut websocket = WebSocket::from_raw_socket(socket, Role::Server, None);
+        for line in events {
+            if websocket.send(Message::Text(line)).is_err() {
+                break;
+            }
+        }
+    });
+}
+
+/// Create a json response.
+fn json(status: u16, body: String) -> HttpResponse {
+    Response::from_string(body)
+        .with_status_code(status)
+        .with_header(header("Content-Type", "application/json"))
+}
+
+/// Header, which allows reading a GET response from any origin, e.g. a visualisation opened from a file.
+fn allow_any_origin() -> Header {
+    header("Access-Control-Allow-Origin", "*")
+}
+
+/// Create a json response containing an error message.
+fn error(status: u16, msg: &str) -> HttpResponse {
+    json(status, serde_json::json!({ "error": msg }).to_string())
+}
+
+/// Create a header, field and value need to be ascii.
+fn header(field: &str, value: &str) -> Header {
+    Header::from_bytes(field, value).expect("header needs to be ascii")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use super::*;
+
+    #[test]
+    fn post_commands() {
+        assert_eq!(post_command("/break", ""), Ok(Command::Break));
+        assert_eq!(post_command("/step", ""), Ok(Command::Step(1)));
+        assert_eq!(post_command("/step", "n=3"), Ok(Command::Step(3)));
+        assert_eq!(post_command("/break-at-task", "foo=1&task=5"), Ok(Command::BreakAtTask(5)));
+        assert_eq!(post_command("/break-at-task", "").unwrap_err().0, 400);
+        assert_eq!(post_command("/jump", "").unwrap_err().0, 404);
+    }
+
+    #[test]
+    fn origin_of_post_requests() {
+        assert!(origin_matches_host("http://localhost:8080", "localhost:8080"));
+        assert!(origin_matches_host("http://127.0.0.1:8080", "127.0.0.1:8080"));
+        assert!(!origin_matches_host("http://evil.example", "localhost:8080"));
+        assert!(!origin_matches_host("http://localhost:9000", "localhost:8080"));
+        assert!(!origin_matches_host("null", "localhost:8080"));
+    }
+
+    #[test]
+    fn host_of_post_requests() {
+        assert!(is_loopback_host("localhost:8080", 8080));
+        assert!(is_loopback_host("LocalHost:8080", 8080));
+        assert!(is_loopback_host("127.0.0.1:8080", 8080));
+        assert!(is_loopback_host("[::1]:8080", 8080));
+        assert!(!is_loopback_host("localhost:9000", 8080));
+        assert!(!is_loopback_host("localhost", 8080));
+        assert!(!is_loopback_host("rebind.example:8080", 8080));
+    }
+
+    #[test]
+    fn continue_resumes_breakpoints() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let stream = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+
+        let state = Arc::new(RwLock::new(AppState::new()));
+        state.write().unwrap().create_function(4, "foo".to_string());
+        let _ = state.write().unwrap().create_task(1, false, Some(4), 0);
+        let breakpoints = Mutex::new(Breakpoints::new());
+        breakpoints.lock().unwrap().request_pause_on_function(4, true);
+        let _ = breakpoints.lock().unwrap().missing_pause(&Event::PreRunTask { task_id: 1, thread_id: 0 }, &state.read().unwrap());
+
+        assert_eq!(post("/continue", "", &state, &breakpoints, &stream).status_code().0, 200);
+        assert_eq!(breakpoints.lock().unwrap().missing_pause(&Event::RunTask { task_id: 1 }, &state.read().unwrap()), None);
+    }
+
+    #[test]
+    fn recent_events_and_subscribers() {
+        let bridge = Bridge::new();
+        let state = AppState::new();
+        let meta = EventMeta { rt: 0, timestamp: Duration::ZERO, received: UNIX_EPOCH };
+
+        let events = bridge.subscribe();
+        bridge.publish(&Event::Barrier, &meta, &state);
+        bridge.publish(&Event::Finish, &meta, &state);
+
+        let recent: serde_json::Value = serde_json::from_str(&bridge.recent_events()).unwrap();
+        assert_eq!(recent[1]["event"]["type"], "Finish");
+        assert_eq!(events.try_iter().count(), 2);
+
+        drop(events);
+        bridge.publish(&Event::Barrier, &meta, &state);
+        assert!(bridge.subscribers.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,554 @@
+use std::{io::Write, net::TcpStream, sync::{Arc, Mutex, RwLock}};
+
+use utils::{requests::Request, events::EventType, stats::ParallelismProfile, AppState, TaskState};
+
+use crate::{breakpoints::{Breakpoints, Condition}, request_handlers::{self as requests, UserInputError}, BUF_SIZE};
+
+/// All keywords a command line can start with. Used for help and tab completion.
+pub const KEYWORDS: [&str; 26] = [
+    "break", "unbreak", "continue", "step", "pause", "breakpoint", "block", "prioritise", "threads",
+    "null", "norequest", "tasks", "show", "deps", "functions", "state", "stats", "timeline", "profile", "sessions", "session", "help", "quit", "exit", "c", "s",
+];
+
+/// A command entered by the user.
+///
+/// Commands either map to a request, which is sent to Ayudame, or only inspect the [AppState].
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Null,
+    NoRequest,
+    PauseOnEvent(EventType, bool),
+    PauseOnTask(u64, bool),
+    PauseOnFunction { function: String, is_on: bool, emulate: bool },
+    Step(i64),
+    Breakpoint(bool),
+    BlockTask(u64, bool),
+    PrioritiseTask(u64, i64),
+    SetNumThreads(i64),
+    Continue,
+    Break,
+    BreakAtTask(u64),
+    UnbreakAtTask(u64),
+    AddBreakpoint(Condition),
+    ListBreakpoints,
+    DeleteBreakpoint(usize),
+    Tasks(Option<TaskState>),
+    ShowTask(u64),
+    ShowFunction(String),
+    Deps(u64),
+    Functions,
+    State,
+    /// Timing statistics of all functions or of a single function.
+    Stats(Option<String>),
+    Timeline,
+    /// Ready and running tasks over time and the critical path.
+    Profile,
+    Sessions,
+    /// Select the session, which receives the commands, by id, `rt:pid` or `all`.
+    SelectSession(String),
+    Help(Option<String>),
+    Quit,
+}
+
+/// Errors which can occur when parsing or encoding a command.
+#[derive(Debug, PartialEq)]
+pub enum CommandError {
+    Empty,
+    UnknownCommand(String),
+    InvalidArgument(String),
+    MissingArgument(&'static str),
+}
+
+impl std::fmt::Display for CommandError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let msg = match self {
+            CommandError::Empty => String::from("No command entered"),
+            CommandError::UnknownCommand(cmd) => format!("Unknown command: {}, type `help` for a list of commands", cmd),
+            CommandError::InvalidArgument(arg) => format!("Invalid argument: {}", arg),
+            CommandError::MissingArgument(arg) => format!("Missing argument: {}", arg),
+        };
+
+        write!(f, "{}", msg)
+    }
+}
+
+impl From<UserInputError> for CommandError {
+    fn from(e: UserInputError) -> Self {
+        CommandError::InvalidArgument(e.to_string())
+    }
+}
+
+impl std::str::FromStr for Command {
+    type Err = CommandError;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let words = line.split_whitespace().collect::<Vec<_>>();
+
+        let command = match &words[..] {
+            [] => return Err(CommandError::Empty),
+            ["null"] => Command::Null,
+            ["norequest"] => Command::NoRequest,
+            ["break"] => Command::Break,
+            ["break", "task", id] => Command::BreakAtTask(parse_number(id)?),
+            ["break", "when", "function", function] => Command::AddBreakpoint(Condition::FunctionStarts(function.to_string())),
+            ["break", "when", "thread", id] => Command::AddBreakpoint(Condition::ThreadRuns(parse_number(id)?)),
+            ["break", "when", "finished", n] => Command::AddBreakpoint(Condition::TasksFinished { n: parse_number(n)?, finished: 0 }),
+            ["break", "when", "parents", k] => Command::AddBreakpoint(Condition::ParentsExceed(parse_number(k)?)),
+            ["break", "when", ..] => return Err(CommandError::MissingArgument("break when (function|thread|finished|parents) <value>")),
+            ["break", "list"] => Command::ListBreakpoints,
+            ["break", "delete", id] => Command::DeleteBreakpoint(parse_number(id)?),
+            ["unbreak", "task", id] => Command::UnbreakAtTask(parse_number(id)?),
+            ["continue"] | ["c"] => Command::Continue,
+            ["step"] | ["s"] => Command::Step(1),
+            ["step", n] | ["s", n] => Command::Step(parse_number(n)?),
+            ["pause", "event", event, value] => Command::PauseOnEvent(parse_event_type(event)?, requests::parse_pause_value(value)?),
+            ["pause", "task", id, value] => Command::PauseOnTask(parse_number(id)?, requests::parse_pause_value(value)?),
+            ["pause", "function"] => Command::Functions,
+            ["pause", "function", function, value] => Command::PauseOnFunction { function: function.to_string(), is_on: requests::parse_pause_value(value)?, emulate: false },
+            ["pause", "function", function, value, "emulate"] => Command::PauseOnFunction { function: function.to_string(), is_on: requests::parse_pause_value(value)?, emulate: true },
+            ["pause", ..] => return Err(CommandError::MissingArgument("pause (event|task|function) <id> (on|off)")),
+            ["breakpoint", value] => Command::Breakpoint(requests::parse_pause_value(value)?),
+            ["block", "task", id, value] => Command::BlockTask(parse_number(id)?, requests::parse_pause_value(value)?),
+            ["prioritise", "task", id, priority] => Command::PrioritiseTask(parse_number(id)?, parse_number(priority)?),
+            ["threads", n] => Command::SetNumThreads(parse_number(n)?),
+            ["tasks"] => Command::Tasks(None),
+            ["tasks", filter] => Command::Tasks(Some(parse_task_state(filter)?)),
+            ["show", "task", id] | ["show", id] => Command::ShowTask(parse_number(id)?),
+            ["show", "function", function] => Command::ShowFunction(function.to_string()),
+            ["deps", id] => Command::Deps(parse_number(id)?),
+            ["functions"] => Command::Functions,
+            ["state"] => Command::State,
+            ["stats"] => Command::Stats(None),
+            ["stats", function] => Command::Stats(Some(function.to_string())),
+            ["timeline"] => Command::Timeline,
+            ["profile"] => Command::Profile,
+            ["sessions"] => Command::Sessions,
+            ["session", target] => Command::SelectSession(target.to_string()),
+            ["help"] => Command::Help(None),
+            ["help", cmd] => Command::Help(Some(cmd.to_string())),
+            ["quit"] | ["exit"] => Command::Quit,
+            [cmd, ..] if KEYWORDS.contains(cmd) => return Err(CommandError::InvalidArgument(line.trim().to_string())),
+            [cmd, ..] => return Err(CommandError::UnknownCommand(cmd.to_string())),
+        };
+
+        Ok(command)
+    }
+}
+
+impl Command {
+    /// Returns the request type of the command, or None if the command does not send a request.
+    pub fn request(&self) -> Option<Request> {
+        let request = match self {
+            Command::Null => Request::Null,
+            Command::NoRequest => Request::NoRequest,
+            Command::PauseOnEvent(..) => Request::PauseOnEvent,
+            Command::PauseOnTask(..) => Request::PauseOnTask,
+            Command::PauseOnFunction { emulate: false, .. } => Request::PauseOnFunction,
+            Command::Step(_) => Request::Step,
+            Command::Breakpoint(_) => Request::Breakpoint,
+            Command::BlockTask(..) => Request::BlockTask,
+            Command::PrioritiseTask(..) => Request::PrioritiseTask,
+            Command::SetNumThreads(_) => Request::SetNumThreads,
+            Command::Continue => Request::Continue,
+            Command::Break => Request::Break,
+            Command::BreakAtTask(_) => Request::BreakAtTask,
+            Command::UnbreakAtTask(_) => Request::UnbreakAtTask,
+            _ => return None,
+        };
+
+        Some(request)
+    }
+
+    /// Encode the command into a request buffer, which can be sent to Ayudame.
+    ///
+    /// Returns Ok(false) if the command does not send a request.
+    pub fn encode(&self, buf: &mut [u8], state: &Arc<RwLock<AppState>>) -> Result<bool, CommandError> {
+        let request = match self.request() {
+            Some(request) => request,
+            None => return Ok(false),
+        };
+        requests::write_request(buf, &request);
+
+        match self {
+            Command::Null => requests::prepare_null(),
+            Command::NoRequest => requests::prepare_no_request(),
+            Command::PauseOnEvent(event, is_on) => requests::prepare_pause_on_event(buf, *event, *is_on),
+            Command::PauseOnTask(task_id, is_on) => requests::prepare_pause_on_task(buf, *task_id, *is_on, state),
+            Command::PauseOnFunction { function, is_on, .. } => requests::prepare_pause_on_function(buf, function, *is_on, state),
+            Command::Step(n) => requests::prepare_step(buf, *n),
+            Command::Breakpoint(is_on) => requests::prepare_breakpoint(buf, *is_on),
+            Command::BlockTask(task_id, is_blocked) => requests::prepare_block_task(buf, *task_id, *is_blocked, state),
+            Command::PrioritiseTask(task_id, priority) => requests::prepare_prioritise_task(buf, *task_id, *priority, state),
+            Command::SetNumThreads(n) => requests::prepare_set_num_threads(buf, *n),
+            Command::Continue => requests::prepare_continue(buf),
+            Command::Break => requests::prepare_break(buf),
+            Command::BreakAtTask(task_id) => requests::prepare_break_at_task(buf, *task_id, state),
+            Command::UnbreakAtTask(task_id) => requests::prepare_unbreak_at_task(buf, *task_id, state),
+            _ => unreachable!("command without request"),
+        }?;
+
+        Ok(true)
+    }
+}
+
+/// Execute a command and return its output, which may be empty.
+/// 
+/// Requests are sent to Ayudame, breakpoint commands update the breakpoints and all other commands describe the state.
+pub fn execute(command: &Command, state: &Arc<RwLock<AppState>>, breakpoints: &Mutex<Breakpoints>, stream: &TcpStream) -> Result<String, CommandError> {
+    let output = match command {
+        Command::AddBreakpoint(condition) => {
+            let id = breakpoints.lock().unwrap().add(condition.clone());
+            format!("Added conditional breakpoint {}\n", id)
+        },
+        Command::ListBreakpoints => breakpoints.lock().unwrap().to_string(),
+        Command::DeleteBreakpoint(id) => {
+            let condition = breakpoints.lock().unwrap().delete(*id).ok_or(CommandError::InvalidArgument(format!("No breakpoint found for id: {}", id)))?;
+            format!("Deleted breakpoint {}: break {}\n", id, condition)
+        },
+        Command::PauseOnFunction { function, is_on, emulate: true } => emulate_pause_on_function(function, *is_on, state, breakpoints, stream)?,
+        Command::PauseOnFunction { function, is_on, emulate: false } => {
+            send(command, state, stream)?;
+            let function_id = requests::get_function_id(function, state)?;
+            breakpoints.lock().unwrap().request_pause_on_function(function_id, *is_on);
+            // a fallback to emulation may be active already
+            let is_emulated = breakpoints.lock().unwrap().is_emulated(function_id);
+            match !is_on && is_emulated {
+                true => emulate_pause_on_function(function, false, state, breakpoints, stream)?,
+                false => String::new(),
+            }
+        },
+        Command::Continue | Command::Step(_) => {
+            // resume first, the task may run on before send returns
+            let expected_pause = breakpoints.lock().unwrap().resume();
+            if let Err(e) = send(command, state, stream) {
+                breakpoints.lock().unwrap().undo_resume(expected_pause);
+                return Err(e);
+            }
+            String::new()
+        },
+        command => match send(command, state, stream)? {
+            true => String::new(),
+            false => info(command, &state.read().unwrap())?,
+        },
+    };
+
+    Ok(output)
+}
+
+/// Emulate a pause on function by sending break at task requests for all tasks of the function.
+/// 
+/// Tasks of the function, which are added later on, are handled by the event receiver.
+fn emulate_pause_on_function(function: &str, is_on: bool, state: &Arc<RwLock<AppState>>, breakpoints: &Mutex<Breakpoints>, stream: &TcpStream) -> Result<String, CommandError> {
+    let function_id = requests::get_function_id(function, state)?;
+
+    let (output, tasks) = if is_on {
+        let tasks = breakpoints.lock().unwrap().emulate_pause_on_function(function_id, &state.read().unwrap());
+        let output = format!("Emulating pause on function {}, sending break at task for {} tasks\n", function_id, tasks.len());
+        (output, tasks.into_iter().map(Command::BreakAtTask).collect::<Vec<_>>())
+    } else {
+        let tasks = breakpoints.lock().unwrap().stop_emulating_pause_on_function(function_id);
+        let output = format!("Stopped emulating pause on function {}, sending unbreak at task for {} tasks\n", function_id, tasks.len());
+        (output, tasks.into_iter().map(Command::UnbreakAtTask).collect::<Vec<_>>())
+    };
+
+    for command in tasks {
+        // tasks may already be removed, so errors can be ignored
+        let _ = send(&command, state, stream);
+    }
+
+    Ok(output)
+}
+
+/// Encode the command and send the resulting request to Ayudame.
+/// 
+/// Returns Ok(false) if the command does not send a request.
+pub fn send(command: &Command, state: &Arc<RwLock<AppState>>, mut stream: &TcpStream) -> Result<bool, CommandError> {
+    let mut buf = [0u8; BUF_SIZE];
+    if !command.encode(&mut buf, state)? {
+        return Ok(false);
+    }
+    let _ = stream.write_all(&buf);
+
+    Ok(true)
+}
+
+/// Describes the tasks or functions in the state, for commands that don't send a request.
+pub fn info(command: &Command, state: &AppState) -> Result<String, CommandError> {
+    let task_not_found = |id: &u64| CommandError::InvalidArgument(format!("No Task found for id: {}", id));
+    let lines = match command {
+        Command::Tasks(filter) => state.get_tasks()
+            .iter()
+            .filter(|t| filter.is_none_or(|s| t.get_state() == s))
+            .map(|t| t.to_string())
+            .collect(),
+        Command::ShowTask(id) => {
+            let t = state.get_task(*id).ok_or_else(|| task_not_found(id))?;
+            let mut lines = vec![format!("{}\n\tparents: {:?}\n\tchildren: {:?}", t, t.get_parent_ids(), t.get_child_ids())];
+            // the thread reported by the runtime, which may differ from the thread id of the task
+            if let Some(timing) = state.get_task_timing(*id).filter(|t| t.start().is_some()) {
+                let thread = timing.thread_id.map_or(String::from("unknown"), |id| id.to_string());
+                let end = timing.ended.map_or(String::from("-"), |t| format!("{:?}", t));
+                lines.push(format!("\tran on thread: {}, start: {:?}, end: {}", thread, timing.start().unwrap_or_default(), end));
+            }
+            lines
+        },
+        Command::ShowFunction(function) => {
+            let f = state.find_function(function).ok_or_else(|| CommandError::InvalidArgument(format!("No Function found for: {}", function)))?;
+            let tasks = state.get_tasks()
+                .iter()
+                .filter(|t| t.get_function().is_some_and(|tf| tf.id == f.id))
+                .map(|t| format!("\t{}", t));
+            std::iter::once(f.to_string()).chain(tasks).collect()
+        },
+        Command::Deps(id) => {
+            let t = state.get_task(*id).ok_or_else(|| task_not_found(id))?;
+            vec![
+                format!("Task {} depends on: {:?}", id, t.get_parent_ids()),
+                format!("Tasks depending on {}: {:?}", id, t.get_child_ids()),
+            ]
+        },
+        Command::Functions => state.get_functions().iter().map(|f| f.to_string()).collect(),
+        Command::State => vec![state.to_string()],
+        Command::Help(cmd) => return Ok(help(cmd.as_deref())),
+        Command::Stats(function) => return stats(state, function.as_deref()),
+        Command::Timeline => return timeline(state),
+        Command::Profile => return profile(state),
+        Command::Sessions | Command::SelectSession(_) => return Err(CommandError::InvalidArgument(String::from("sessions can only be used on the command line"))),
+        _ => Vec::new(),
+    };
+
+    Ok(lines.into_iter().map(|l| l + "\n").collect())
+}
+
+/// Timing statistics of the finished tasks of all functions, or of a single function including a histogram of the durations.
+pub fn stats(state: &AppState, function: Option<&str>) -> Result<String, CommandError> {
+    let stats = state.function_stats();
+    let Some(function) = function else {
+        return Ok(stats.iter().map(|s| format!("{}\n", s)).collect());
+    };
+
+    let f = state.find_function(function).ok_or_else(|| CommandError::InvalidArgument(format!("No Function found for: {}", function)))?;
+    let s = stats.iter()
+        .find(|s| s.function_id == Some(f.id))
+        .ok_or_else(|| CommandError::InvalidArgument(format!("No finished tasks for function: {}", function)))?;
+
+    Ok(format!("{}\n{}", s, s.histogram))
+}
+
+/// Busy and idle time of every thread and the load imbalance, with a bar showing when each thread was busy.
+pub fn timeline(state: &AppState) -> Result<String, CommandError> {
+    let timeline = state.thread_timeline().ok_or(CommandError::InvalidArgument(String::from("No task finished running yet")))?;
+    let span = timeline.end - timeline.start;
+
+    let mut lines = vec![format!("{} threads over {:?}, load imbalance: {:.1}%", timeline.threads.len(), span, timeline.imbalance() * 100.0)];
+    for thread in &timeline.threads {
+        // a column is busy, if the thread ran a task at any time of the column
+        let bar = (0..TIMELINE_WIDTH as u32)
+            .map(|i| (timeline.start + span * i / TIMELINE_WIDTH as u32, timeline.start + span * (i + 1) / TIMELINE_WIDTH as u32))
+            .map(|(start, end)| match thread.tasks.iter().any(|t| t.start < end && t.end > start) {
+                true => '#',
+                false => '.',
+            })
+            .collect::<String>();
+        lines.push(format!(
+            "thread {:<3} |{}| busy {:?} ({:.1}%), idle {:?}, {} tasks",
+            thread.thread_id, bar, thread.busy_time, thread.utilization() * 100.0, thread.idle_time, thread.tasks.len()
+        ));
+    }
+
+    Ok(lines.into_iter().map(|l| l + "\n").collect())
+}
+
+/// Number of columns of the bars of the timeline.
+const TIMELINE_WIDTH: usize = 40;
+
+/// Number of rows of the parallelism profile.
+pub const PROFILE_SAMPLES: usize = 20;
+
+/// Ready and running tasks over time, the critical path and whether more threads could help.
+pub fn profile(state: &AppState) -> Result<String, CommandError> {
+    let profile = state.parallelism_profile(PROFILE_SAMPLES).ok_or(CommandError::InvalidArgument(String::from("No task finished running yet")))?;
+    Ok(format_profile(&profile, |id| state.get_functions().iter().find(|f| f.id == id).map(|f| f.get_name().to_string())))
+}
+
+/// Format a parallelism profile, with one row per sample, e.g. `  1.2ms  ready  3.0  running  2.0 |##+++|`.
+///
+/// In the bars, `#` is a running task and `+` a ready task.
+///
+/// Tasks on the critical path are labelled with the name of their function, if `function_name` finds one.
+pub fn format_profile(profile: &ParallelismProfile, function_name: impl Fn(u64) -> Option<String>) -> String {
+    let runtime = profile.end - profile.start;
+    let share = |d: std::time::Duration| d.as_secs_f64() / runtime.as_secs_f64().max(f64::MIN_POSITIVE) * 100.0;
+
+    let mut lines = vec![
+        format!("{} threads over {:?}, work {:?}, critical path {:?} ({:.1}% of the runtime)", profile.threads, runtime, profile.work, profile.critical_path_length, share(profile.critical_path_length)),
+        format!("available parallelism (work / critical path): {:.2}", profile.available_parallelism()),
+        format!("all threads busy while tasks were ready: {:?} ({:.1}%)", profile.saturated, share(profile.saturated)),
+    ];
+    lines.push(match profile.available_parallelism() > profile.threads as f64 {
+        true => String::from("the dependencies allow more parallelism than threads were used, more threads could help"),
+        false => String::from("the critical path limits the runtime, more threads will not help"),
+    });
+
+    let thread_ids = profile.thread_ids.iter().map(|id| id.to_string()).collect::<Vec<_>>().join(", ");
+    lines.push(format!("occupancy of threads {}: # busy, : partly busy, . idle; bar: # running, + ready", thread_ids));
+    for sample in &profile.samples {
+        let (running, ready) = (sample.running.round() as usize, sample.ready.round() as usize);
+        let bar = "#".repeat(running.min(TIMELINE_WIDTH)) + &"+".repeat(ready.min(TIMELINE_WIDTH - running.min(TIMELINE_WIDTH)));
+        let threads = sample.occupancy.iter()
+            .map(|o| match o {
+                o if *o >= 0.9 => '#',
+                o if *o > 0.1 => ':',
+                _ => '.',
+            })
+            .collect::<String>();
+        lines.push(format!("{:>10?}  ready {:>5.1}  running {:>5.1}  {} |{}", sample.start - profile.start, sample.ready, sample.running, threads, bar));
+    }
+
+    lines.push(format!("critical path ({} tasks):", profile.critical_path.len()));
+    for t in &profile.critical_path {
+        let thread = t.thread_id.map_or(String::from("-"), |id| id.to_string());
+        let function = t.function_id.and_then(&function_name).unwrap_or_else(|| String::from("no function"));
+        lines.push(format!("\ttask {} ({}) on thread {}: {:?}", t.task_id, function, thread, t.duration().unwrap_or_default()));
+    }
+
+    lines.into_iter().map(|l| l + "\n").collect()
+}
+
+/// The help text, either for all commands or for the given command.
+pub fn help(command: Option<&str>) -> String {
+    let lines = HELP.lines().filter(|l| command.is_none_or(|c| l.trim_start().starts_with(c)));
+    std::iter::once("Commands:\n".to_string())
+        .chain(lines.map(|l| format!("  {}\n", l)))
+        .collect()
+}
+
+/// Help text, one command per line.
+const HELP: &str = "\
+break                             pause the application (Rust Ayudame)
+break task <id>                   break when the task is about to run (Rust Ayudame)
+unbreak task <id>                 remove a break at a task (Rust Ayudame)
+break when function <id|name>     break when a task of the function starts (evaluated by the frontend)
+break when thread <id>            break when the thread runs a task (evaluated by the frontend)
+break when finished <n>           break once n tasks have finished (evaluated by the frontend)
+break when parents <k>            break when a task has more than k parents (evaluated by the frontend)
+break list                        list all conditional breakpoints
+break delete <id>                 delete a conditional breakpoint
+continue | c                      continue the application (Rust Ayudame)
+step [n] | s [n]                  run until the next n pause conditions are reached
+pause event <event> on|off        pause on the given event type, e.g. PreRunTask (Cpp Ayudame)
+pause task <id> on|off            pause on the given task (Cpp Ayudame)
+pause function                    list the functions, which can be paused on
+pause function <id|name> on|off   pause on the tasks of the given function (Cpp Ayudame)
+pause function <f> on|off emulate always emulate pause on function with break at task (Rust Ayudame)
+breakpoint on|off                 don't assign new tasks (Cpp Ayudame)
+block task <id> on|off            block a task (Cpp Ayudame)
+prioritise task <id> <priority>   set the priority of a task (Cpp Ayudame)
+threads <n>                       set the number of threads (Cpp Ayudame)
+null | norequest                  send an empty request
+tasks [--<state>]                 list tasks, optionally filtered by state, e.g. --running
+show task <id>                    show a task and its dependencies
+show function <id|name>           show a function and its tasks
+deps <id>                         show the dependencies of a task
+functions                         list all registered functions
+state                             print the current state
+stats [function]                  timing statistics of the finished tasks per function
+timeline                          busy and idle time of the threads, which ran tasks, and the load imbalance
+profile                           ready and running tasks over time and the critical path
+sessions                          list all sessions, if several Ayudame instances are connected
+session <id|rt:pid|all>           send the following commands to one session or to all sessions
+help [command]                    print this help
+quit | exit                       quit the frontend";
+
+/// Parse a number, which was entered as an argument of a command.
+fn parse_number<T: std::str::FromStr>(arg: &str) -> Result<T, CommandError> {
+    arg.parse::<T>().map_err(|_| CommandError::InvalidArgument(arg.to_string()))
+}
+
+/// Parse an event type, either by name or by id.
+fn parse_event_type(arg: &str) -> Result<EventType, CommandError> {
+    match arg.parse::<u64>() {
+        Ok(id) => EventType::try_from(id).map_err(|_| UserInputError::InvalidEventId(id).into()),
+        Err(_) => arg.parse::<EventType>().map_err(CommandError::InvalidArgument),
+    }
+}
+
+/// Parse a filter for the tasks command, e.g. `--running`.
+fn parse_task_state(arg: &str) -> Result<TaskState, CommandError> {
+    arg.trim_start_matches("--").parse::<TaskState>().map_err(CommandError::InvalidArgument)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_request_commands() {
+        assert_eq!("break task 12".parse::<Command>(), Ok(Command::BreakAtTask(12)));
+        assert_eq!("pause event PreRunTask on".parse::<Command>(), Ok(Command::PauseOnEvent(EventType::PreRunTask, true)));
+        assert_eq!("pause event 16 off".parse::<Command>(), Ok(Command::PauseOnEvent(EventType::Barrier, false)));
+        assert_eq!("pause function foo on".parse::<Command>(), Ok(Command::PauseOnFunction { function: "foo".to_string(), is_on: true, emulate: false }));
+        assert_eq!("pause function 2 off emulate".parse::<Command>(), Ok(Command::PauseOnFunction { function: "2".to_string(), is_on: false, emulate: true }));
+        assert_eq!("step 5".parse::<Command>(), Ok(Command::Step(5)));
+        assert_eq!("step".parse::<Command>(), Ok(Command::Step(1)));
+        assert_eq!("continue".parse::<Command>(), Ok(Command::Continue));
+    }
+
+    #[test]
+    fn parse_breakpoint_commands() {
+        assert_eq!("break when function foo".parse::<Command>(), Ok(Command::AddBreakpoint(Condition::FunctionStarts("foo".to_string()))));
+        assert_eq!("break when thread 3".parse::<Command>(), Ok(Command::AddBreakpoint(Condition::ThreadRuns(3))));
+        assert_eq!("break when finished 10".parse::<Command>(), Ok(Command::AddBreakpoint(Condition::TasksFinished { n: 10, finished: 0 })));
+        assert_eq!("break when parents 2".parse::<Command>(), Ok(Command::AddBreakpoint(Condition::ParentsExceed(2))));
+        assert_eq!("break delete 1".parse::<Command>(), Ok(Command::DeleteBreakpoint(1)));
+        assert!(matches!("break when".parse::<Command>(), Err(CommandError::MissingArgument(_))));
+    }
+
+    #[test]
+    fn parse_info_commands() {
+        assert_eq!("tasks --running".parse::<Command>(), Ok(Command::Tasks(Some(TaskState::Running))));
+        assert_eq!("show task 7".parse::<Command>(), Ok(Command::ShowTask(7)));
+        assert_eq!("deps 7".parse::<Command>(), Ok(Command::Deps(7)));
+        assert_eq!("show function foo".parse::<Command>(), Ok(Command::ShowFunction("foo".to_string())));
+        assert_eq!("timeline".parse::<Command>(), Ok(Command::Timeline));
+        assert_eq!("profile".parse::<Command>(), Ok(Command::Profile));
+        assert_eq!("stats foo".parse::<Command>(), Ok(Command::Stats(Some("foo".to_string()))));
+        assert_eq!("session 0:4242".parse::<Command>(), Ok(Command::SelectSession("0:4242".to_string())));
+    }
+
+    #[test]
+    fn parse_invalid_commands() {
+        assert_eq!("".parse::<Command>(), Err(CommandError::Empty));
+        assert!(matches!("jump 3".parse::<Command>(), Err(CommandError::UnknownCommand(_))));
+        assert!(matches!("step x".parse::<Command>(), Err(CommandError::InvalidArgument(_))));
+        assert!(matches!("pause task 3 maybe".parse::<Command>(), Err(CommandError::InvalidArgument(_))));
+    }
+
+    #[test]
+    fn encode_break_at_task() {
+        let state = Arc::new(RwLock::new(AppState::new()));
+        let mut buf = [0u8; 64];
+        assert!(Command::BreakAtTask(3).encode(&mut buf, &state).is_err());
+
+        let _ = state.write().unwrap().create_task(3, false, None, 0);
+        assert_eq!(Command::BreakAtTask(3).encode(&mut buf, &state), Ok(true));
+        assert_eq!(buf[8..16], (Request::BreakAtTask as u64).to_be_bytes());
+        assert_eq!(buf[16..24], 3u64.to_be_bytes());
+
+        assert_eq!(Command::Deps(3).encode(&mut buf, &state), Ok(false));
+    }
+
+    #[test]
+    fn encode_pause_on_function() {
+        let state = Arc::new(RwLock::new(AppState::new()));
+        let command = Command::PauseOnFunction { function: "foo".to_string(), is_on: true, emulate: false };
+        let mut buf = [0u8; 64];
+        assert!(command.encode(&mut buf, &state).is_err());
+
+        state.write().unwrap().create_function(7, "foo".to_string());
+        assert_eq!(command.encode(&mut buf, &state), Ok(true));
+        assert_eq!(buf[8..16], (Request::PauseOnFunction as u64).to_be_bytes());
+        assert_eq!(buf[16..24], 7u64.to_be_bytes());
+        assert_eq!(buf[24..32], 1u64.to_be_bytes());
+    }
+}
@@ -0,0 +1,377 @@
+use std::{collections::{BTreeMap, BTreeSet, hash_map::DefaultHasher}, fmt::Write, fs, hash::{Hash, Hasher}};
+
+use clap::ValueEnum;
+use utils::{events::{self, Event, EventRecord, EventType}, stats::{FunctionStats, TaskTiming}};
+
+use crate::{bridge::StateSnapshot, export::Recording};
+
+/// Events of a task in the order they need to happen.
+const LIFECYCLE: [EventType; 6] = [
+    EventType::AddTask,
+    EventType::AddTaskToQueue,
+    EventType::PreRunTask,
+    EventType::RunTask,
+    EventType::PostRunTask,
+    EventType::RemoveTask,
+];
+
+/// How tasks and functions of two sessions are matched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum MatchBy {
+    /// Same task and function ids.
+    Id,
+    /// Same function name and the same parents, for runs which assign other ids.
+    Structure,
+}
+
+/// An invariant of the event order, which was violated in a session.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Violation {
+    pub task_id: Option<u64>,
+    /// Parent of the task, for violated dependencies.
+    pub parent_id: Option<u64>,
+    /// What happened, without the ids, e.g. `RunTask before PreRunTask`.
+    pub description: String,
+}
+
+/// The parts of a session, which are compared: functions, tasks with their dependencies and timings and the event order.
+#[derive(Debug, Default)]
+pub struct SessionSummary {
+    pub functions: BTreeMap<u64, String>,
+    /// All tasks by id, including removed tasks for recorded sessions.
+    pub tasks: BTreeMap<u64, TaskTiming>,
+    /// None for snapshots, which contain no events.
+    pub violations: Option<Vec<Violation>>,
+}
+
+impl SessionSummary {
+    /// Summarize recorded events.
+    pub fn from_records(records: &[EventRecord]) -> Self {
+        let recording = Recording::new(records);
+        let events = records.iter().map(|r| r.event).collect::<Vec<_>>();
+
+        Self { functions: recording.functions, tasks: recording.timings, violations: Some(check_invariants(&events)) }
+    }
+
+    /// Summarize a snapshot of the state, as returned by `GET /state`.
+    pub fn from_snapshot(snapshot: StateSnapshot) -> Self {
+        let mut tasks = snapshot.timings.into_iter().map(|t| (t.task_id, t)).collect::<BTreeMap<_, _>>();
+        for task in snapshot.tasks {
+            let timing = tasks.entry(task.id).or_insert_with(|| TaskTiming { task_id: task.id, ..Default::default() });
+            timing.function_id = task.function.or(timing.function_id);
+            timing.parents = task.parents;
+        }
+
+        Self {
+            functions: snapshot.functions.into_iter().map(|f| (f.id, f.name)).collect(),
+            tasks,
+            violations: None,
+        }
+    }
+
+    /// Read a session recorded with `--output jsonl` or a snapshot saved from `GET /state`.
+    pub fn from_file(path: &str) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("Unable to read {}: {}", path, e))?;
+        if let Ok(snapshot) = serde_json::from_str::<StateSnapshot>(&text) {
+            return Ok(Self::from_snapshot(snapshot));
+        }
+        let records = events::read_json_lines(&text).map_err(|e| format!("{}: {}", path, e))?;
+        Ok(Self::from_records(&records))
+    }
+
+    /// Name of the function, falls back to its id.
+    fn function_name(&self, id: u64) -> String {
+        self.functions.get(&id).filter(|n| !n.is_empty()).cloned().unwrap_or_else(|| id.to_string())
+    }
+
+    /// Key of every task, which is equal for matching tasks of two sessions.
+    fn task_keys(&self, match_by: MatchBy) -> BTreeMap<u64, String> {
+        match match_by {
+            MatchBy::Id => self.tasks.keys().map(|id| (*id, id.to_string())).collect(),
+            MatchBy::Structure => {
+                // tasks with the same signature are matched in the order of their ids
+                let mut signatures = BTreeMap::new();
+                let mut occurrences = BTreeMap::<u64, usize>::new();
+                self.tasks.keys()
+                    .map(|id| {
+                        let signature = self.signature(*id, &mut signatures, &mut BTreeSet::new());
+                        let n = occurrences.entry(signature).or_default();
+                        *n += 1;
+                        (*id, format!("{:x}#{}", signature, n))
+                    })
+                    .collect()
+            },
+        }
+    }
+
+    /// Hash of the function name of a task and the signatures of its parents. Parents in a cycle are ignored.
+    fn signature(&self, task_id: u64, signatures: &mut BTreeMap<u64, u64>, visiting: &mut BTreeSet<u64>) -> u64 {
+        if let Some(signature) = signatures.get(&task_id) {
+            return *signature;
+        }
+        let Some(task) = self.tasks.get(&task_id) else {
+            return 0;
+        };
+
+        visiting.insert(task_id);
+        let mut parents = Vec::new();
+        for parent in &task.parents {
+            if !visiting.contains(parent) {
+                parents.push(self.signature(*parent, signatures, visiting));
+            }
+        }
+        parents.sort();
+        visiting.remove(&task_id);
+
+        let mut hasher = DefaultHasher::new();
+        (task.function_id.map(|id| self.function_name(id)), parents).hash(&mut hasher);
+        let signature = hasher.finish();
+        signatures.insert(task_id, signature);
+        signature
+    }
+
+    /// Key of every function, which is equal for matching functions of two sessions.
+    fn function_key(&self, id: u64, match_by: MatchBy) -> String {
+        match match_by {
+            MatchBy::Id => id.to_string(),
+            MatchBy::Structure => self.function_name(id),
+        }
+    }
+
+    /// Timing statistics of every function by its key.
+    fn function_stats(&self, match_by: MatchBy) -> BTreeMap<String, FunctionStats> {
+        let mut by_function = BTreeMap::<Option<u64>, Vec<&TaskTiming>>::new();
+        for task in self.tasks.values() {
+            by_function.entry(task.function_id).or_default().push(task);
+        }
+
+        by_function.into_iter()
+            .filter_map(|(id, timings)| {
+                let key = id.map_or(String::from("no function"), |id| self.function_key(id, match_by));
+                let name = id.map_or(String::from("no function"), |id| self.function_name(id));
+                Some((key, FunctionStats::new(id, name, &timings)?))
+            })
+            .collect()
+    }
+
+    /// Violations with the ids replaced by the keys of the tasks.
+    fn violation_keys(&self, keys: &BTreeMap<u64, String>) -> Option<BTreeMap<String, String>> {
+        let key = |id: Option<u64>| id.map(|id| keys.get(&id).cloned().unwrap_or_else(|| id.to_string())).unwrap_or_default();
+        let violations = self.violations.as_ref()?.iter()
+            .map(|v| (format!("{}|{}|{}", key(v.task_id), key(v.parent_id), v.description), self.describe_violation(v)))
+            .collect();
+        Some(violations)
+    }
+
+    /// A violation as readable line, e.g. `task 3 (foo): RunTask before PreRunTask`.
+    fn describe_violation(&self, violation: &Violation) -> String {
+        let mut line = match violation.task_id {
+            Some(id) => format!("{}: {}", self.describe_task(id), violation.description),
+            None => violation.description.clone(),
+        };
+        if let Some(parent) = violation.parent_id {
+            let _ = write!(line, " {}", self.describe_task(parent));
+        }
+        line
+    }
+
+    /// A task with its function, e.g. `task 3 (foo)`.
+    fn describe_task(&self, task_id: u64) -> String {
+        match self.tasks.get(&task_id) {
+            Some(task) => format!("task {} ({})", task_id, task.function_id.map_or(String::from("-"), |id| self.function_name(id))),
+            None => format!("task {}", task_id),
+        }
+    }
+}
+
+/// Check the invariants of the event order: the events of every task follow its lifecycle,
+/// tasks start after their parents finished and no events follow Finish.
+pub fn check_invariants(events: &[Event]) -> Vec<Violation> {
+    let mut violations = Vec::new();
+    let mut last_step = BTreeMap::<u64, usize>::new();
+    let mut parents = BTreeMap::<u64, Vec<u64>>::new();
+    let mut finished = BTreeSet::new();
+    let mut is_finished = false;
+
+    for event in events {
+        let violation = |task_id: Option<u64>, parent_id: Option<u64>, description: String| Violation { task_id, parent_id, description };
+        if is_finished {
+            violations.push(violation(event.task_id(), None, format!("{:?} after Finish", event.event_type())));
+            continue;
+        }
+
+        match *event {
+            Event::Finish => is_finished = true,
+            Event::AddDependency { to_id, from_id, .. } => parents.entry(to_id).or_default().push(from_id),
+            _ => {},
+        }
+
+        let (Some(task_id), Some(step)) = (event.task_id(), LIFECYCLE.iter().position(|t| *t == event.event_type())) else {
+            continue;
+        };
+        match last_step.get(&task_id) {
+            Some(last) if step < *last => violations.push(violation(Some(task_id), None, format!("{:?} after {:?}", LIFECYCLE[step], LIFECYCLE[*last]))),
+            None if step > 0 => violations.push(violation(Some(task_id), None, format!("{:?} before AddTask", LIFECYCLE[step]))),
+            _ => {},
+        }
+
+        // the dependencies are checked when the task starts, which is PreRunTask or RunTask, if PreRunTask is missing
+        let is_start = matches!(event.event_type(), EventType::PreRunTask | EventType::RunTask) && last_step.get(&task_id).is_none_or(|last| *last < 2);
+        if is_start {
+            for parent in parents.get(&task_id).into_iter().flatten().filter(|p| !finished.contains(*p)) {
+                violations.push(violation(Some(task_id), Some(*parent), String::from("started before its parent finished:")));
+            }
+        }
+        if event.event_type() == EventType::PostRunTask {
+            finished.insert(task_id);
+        }
+
+        let last = last_step.entry(task_id).or_insert(step);
+        *last = step.max(*last);
+    }
+
+    violations
+}
+
+/// Differences between two sessions.
+#[derive(Debug, Default)]
+pub struct SessionDiff {
+    /// Readable report of all differences.
+    pub report: String,
+    /// Number of added or removed functions, tasks, dependencies and violations. Timing changes are not counted.
+    pub changes: usize,
+}
+
+/// Compare an old with a new session.
+pub fn diff(old: &SessionSummary, new: &SessionSummary, match_by: MatchBy) -> SessionDiff {
+    let mut diff = SessionDiff::default();
+
+    // functions
+    let old_functions = old.functions.iter().map(|(id, name)| (old.function_key(*id, match_by), format!("{}: {}", id, name))).collect();
+    let new_functions = new.functions.iter().map(|(id, name)| (new.function_key(*id, match_by), format!("{}: {}", id, name))).collect();
+    diff.section("functions", &old_functions, &new_functions);
+
+    // tasks and dependencies
+    let (old_keys, new_keys) = (old.task_keys(match_by), new.task_keys(match_by));
+    let old_tasks = old.tasks.keys().map(|id| (old_keys[id].clone(), old.describe_task(*id))).collect();
+    let new_tasks = new.tasks.keys().map(|id| (new_keys[id].clone(), new.describe_task(*id))).collect();
+    diff.section("tasks", &old_tasks, &new_tasks);
+
+    let edges = |session: &SessionSummary, keys: &BTreeMap<u64, String>| session.tasks.values()
+        .flat_map(|t| t.parents.iter().map(move |p| (*p, t.task_id)))
+        .map(|(p, c)| {
+            let key = |id: u64| keys.get(&id).cloned().unwrap_or_else(|| id.to_string());
+            (format!("{} -> {}", key(p), key(c)), format!("{} -> {}", p, c))
+        })
+        .collect::<BTreeMap<_, _>>();
+    diff.section("dependencies", &edges(old, &old_keys), &edges(new, &new_keys));
+
+    // timing
+    let (old_stats, new_stats) = (old.function_stats(match_by), new.function_stats(match_by));
+    let mut timing = Vec::new();
+    for (key, o) in &old_stats {
+        let Some(n) = new_stats.get(key) else {
+            continue;
+        };
+        let change = |o: std::time::Duration, n: std::time::Duration| match o.is_zero() {
+            true => format!("{:?} -> {:?}", o, n),
+            false => format!("{:?} -> {:?} ({:+.1}%)", o, n, (n.as_secs_f64() / o.as_secs_f64() - 1.0) * 100.0),
+        };
+        timing.push(format!(
+            "\t{}: n {} -> {}, mean {}, p90 {}, max {}",
+            n.name, o.duration.count, n.duration.count, change(o.duration.mean, n.duration.mean), change(o.duration.p90, n.duration.p90), change(o.duration.max, n.duration.max)
+        ));
+    }
+    let _ = writeln!(diff.report, "timing of functions with finished tasks in both sessions: {}", timing.len());
+    for line in timing {
+        let _ = writeln!(diff.report, "{}", line);
+    }
+
+    // event order
+    match (old.violation_keys(&old_keys), new.violation_keys(&new_keys)) {
+        (Some(old_violations), Some(new_violations)) => diff.section("event order violations", &old_violations, &new_violations),
+        _ => { let _ = writeln!(diff.report, "event order violations: not compared, snapshots contain no events"); },
+    }
+
+    diff
+}
+
+impl SessionDiff {
+    /// Add a section with the removed (`-`) and added (`+`) entries, which are compared by their keys.
+    fn section(&mut self, title: &str, old: &BTreeMap<String, String>, new: &BTreeMap<String, String>) {
+        let removed = old.iter().filter(|(k, _)| !new.contains_key(*k)).map(|(_, v)| v).collect::<Vec<_>>();
+        let added = new.iter().filter(|(k, _)| !old.contains_key(*k)).map(|(_, v)| v).collect::<Vec<_>>();
+        self.changes += removed.len() + added.len();
+
+        let _ = writeln!(self.report, "{}: {} -> {}, +{} -{}", title, old.len(), new.len(), added.len(), removed.len());
+        for line in removed {
+            let _ = writeln!(self.report, "\t- {}", line);
+        }
+        for line in added {
+            let _ = writeln!(self.report, "\t+ {}", line);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn records(events: &[Event]) -> Vec<EventRecord> {
+        events.iter()
+            .enumerate()
+            .map(|(i, e)| {
+                let name = matches!(e, Event::RegisterFunction { .. }).then(|| String::from("foo"));
+                EventRecord { rt: 0, timestamp: i as u64 * 1000, received: 0, name, event: *e }
+            })
+            .collect()
+    }
+
+    fn run(task_id: u64) -> [Event; 4] {
+        [
+            Event::AddTaskToQueue { task_id, thread_id: 0 },
+            Event::PreRunTask { task_id, thread_id: 0 },
+            Event::RunTask { task_id },
+            Event::PostRunTask { task_id },
+        ]
+    }
+
+    #[test]
+    fn invariants() {
+        let events = [
+            vec![Event::AddTask { task_id: 1, func_id: 0, priority: 0, scope_id: 0 }, Event::AddTask { task_id: 2, func_id: 0, priority: 0, scope_id: 0 }],
+            vec![Event::AddDependency { to_id: 2, from_id: 1, memaddr: 0, orig_memaddr: 0 }],
+            run(2).to_vec(),
+            vec![Event::PostRunTask { task_id: 1 }, Event::AddTaskToQueue { task_id: 1, thread_id: 0 }, Event::Finish, Event::Barrier],
+        ].concat();
+
+        let violations = check_invariants(&events).into_iter().map(|v| (v.task_id, v.parent_id, v.description)).collect::<Vec<_>>();
+        assert_eq!(violations, [
+            (Some(2), Some(1), String::from("started before its parent finished:")),
+            (Some(1), None, String::from("AddTaskToQueue after PostRunTask")),
+            (None, None, String::from("Barrier after Finish")),
+        ]);
+    }
+
+    #[test]
+    fn diff_by_id_and_structure() {
+        let add = |task_id| Event::AddTask { task_id, func_id: 1, priority: 0, scope_id: 0 };
+        let function = Event::RegisterFunction { func_id: 1, string_len: 3 };
+        let dependency = |from_id, to_id| Event::AddDependency { to_id, from_id, memaddr: 0, orig_memaddr: 0 };
+
+        let old = [vec![function, add(1), add(2), dependency(1, 2)], run(1).to_vec(), run(2).to_vec()].concat();
+        // same graph with other task ids, and one more task depending on the first one
+        let new = [vec![function, add(11), add(12), add(13), dependency(11, 12), dependency(11, 13)], run(11).to_vec(), run(12).to_vec(), run(13).to_vec()].concat();
+        let (old, new) = (SessionSummary::from_records(&records(&old)), SessionSummary::from_records(&records(&new)));
+
+        let by_structure = diff(&old, &new, MatchBy::Structure);
+        assert_eq!(by_structure.changes, 2, "{}", by_structure.report);
+        assert!(by_structure.report.contains("tasks: 2 -> 3, +1 -0\n\t+ task 13 (foo)\n"), "{}", by_structure.report);
+        assert!(by_structure.report.contains("dependencies: 1 -> 2, +1 -0\n\t+ 11 -> 13\n"), "{}", by_structure.report);
+        assert!(by_structure.report.contains("\tfoo: n 2 -> 3, mean 1µs -> 1µs (+0.0%)"), "{}", by_structure.report);
+        assert!(by_structure.report.contains("event order violations: 0 -> 0, +0 -0"), "{}", by_structure.report);
+
+        let by_id = diff(&old, &new, MatchBy::Id);
+        assert_eq!(by_id.changes, 8, "{}", by_id.report);
+    }
+}
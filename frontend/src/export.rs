@@ -0,0 +1,347 @@
+use std::{collections::BTreeMap, fmt::Write, fs, time::Duration};
+
+use clap::ValueEnum;
+use serde_json::{json, Value};
+use utils::{events::{self, Event, EventRecord}, stats::TaskTiming, AppState, TaskState};
+
+/// Formats a recorded session can be exported to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ExportFormat {
+    /// Chrome Trace Event json, which can be opened in Perfetto or chrome://tracing.
+    Chrome,
+    /// Standalone SVG Gantt chart with one lane per thread, which can be opened in a browser.
+    Svg,
+}
+
+/// Colors of the task bars, one per function.
+const COLORS: [&str; 10] = ["#4e79a7", "#f28e2b", "#e15759", "#76b7b2", "#59a14f", "#edc948", "#b07aa1", "#ff9da7", "#9c755f", "#bab0ac"];
+
+/// Width of the time axis of the SVG in pixels.
+const SVG_CHART_WIDTH: f64 = 1000.0;
+
+/// Width of the thread labels left of the lanes in pixels.
+const SVG_LABEL_WIDTH: f64 = 90.0;
+
+/// Height of a thread lane in pixels.
+const SVG_LANE_HEIGHT: f64 = 24.0;
+
+/// Number of ticks on the time axis.
+const SVG_TICKS: u32 = 5;
+
+/// A recorded session, replayed from its events.
+///
+/// In contrast to the [utils::AppState], removed tasks and their dependencies are kept.
+#[derive(Debug, Default)]
+pub struct Recording {
+    pub rt: u64,
+    pub pid: u64,
+    /// Names of the registered functions.
+    pub functions: BTreeMap<u64, String>,
+    pub timings: BTreeMap<u64, TaskTiming>,
+    /// Pairs of (parent, child).
+    pub dependencies: Vec<(u64, u64)>,
+    /// Barrier and WaitOn events.
+    pub instants: Vec<(Duration, Event)>,
+    /// Timestamp of the first event.
+    pub start: Duration,
+}
+
+impl Recording {
+    /// Replay the recorded events.
+    pub fn new(records: &[EventRecord]) -> Self {
+        let mut recording = Recording {
+            start: records.iter().map(|r| Duration::from_nanos(r.timestamp)).min().unwrap_or_default(),
+            ..Default::default()
+        };
+
+        for record in records {
+            let timestamp = Duration::from_nanos(record.timestamp);
+            let (task_id, task_state, thread_id) = match record.event {
+                Event::PreInit { rt, pid } => {
+                    (recording.rt, recording.pid) = (rt, pid);
+                    continue;
+                },
+                Event::RegisterFunction { func_id, .. } => {
+                    recording.functions.insert(func_id, record.name.clone().unwrap_or_default());
+                    continue;
+                },
+                Event::AddTask { task_id, func_id, .. } => {
+                    recording.timings.insert(task_id, TaskTiming { task_id, function_id: Some(func_id), ..Default::default() });
+                    (task_id, TaskState::Created, None)
+                },
+                Event::AddDependency { to_id, from_id, .. } => {
+                    // to_id depends on from_id, so from_id is the parent
+                    recording.dependencies.push((from_id, to_id));
+                    recording.timings.entry(to_id).or_insert_with(|| TaskTiming { task_id: to_id, ..Default::default() }).parents.push(from_id);
+                    continue;
+                },
+                Event::AddTaskToQueue { task_id, thread_id } => (task_id, TaskState::Queued, Some(thread_id)),
+                Event::PreRunTask { task_id, thread_id } => (task_id, TaskState::PreRun, Some(thread_id)),
+                Event::RunTask { task_id } => (task_id, TaskState::Running, None),
+                Event::PostRunTask { task_id } => (task_id, TaskState::Finished, None),
+                Event::Barrier | Event::WaitOn { .. } => {
+                    recording.instants.push((timestamp, record.event));
+                    continue;
+                },
+                Event::Init { .. } | Event::RemoveTask { .. } | Event::Finish => continue,
+            };
+
+            let timing = recording.timings.entry(task_id).or_insert_with(|| TaskTiming { task_id, ..Default::default() });
+            timing.record(task_state, timestamp);
+            timing.thread_id = thread_id.or(timing.thread_id);
+        }
+
+        recording
+    }
+
+    /// Create a recording from the final state, which only contains the dependencies of tasks, which were not removed,
+    /// and no barriers or waits.
+    pub fn from_state(state: &AppState) -> Self {
+        let timings = state.get_task_timings().map(|t| (t.task_id, t.clone())).collect::<BTreeMap<_, _>>();
+        let start = timings.values().flat_map(|t| [t.created, t.queued, t.pre_run, t.started, t.ended]).flatten().min().unwrap_or_default();
+
+        Recording {
+            functions: state.get_functions().iter().map(|f| (f.id, f.get_name().to_string())).collect(),
+            timings,
+            dependencies: state.get_dependencies(),
+            start,
+            ..Default::default()
+        }
+    }
+
+    /// Read a session, which was recorded with `--output jsonl`.
+    pub fn from_file(path: &str) -> Result<Self, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("Unable to read {}: {}", path, e))?;
+        Ok(Self::new(&events::read_json_lines(&text).map_err(|e| e.to_string())?))
+    }
+
+    /// Name of the function of a task, falls back to the function id or `task <id>`.
+    pub fn function_name(&self, timing: &TaskTiming) -> String {
+        match timing.function_id {
+            Some(id) => self.functions.get(&id).filter(|n| !n.is_empty()).cloned().unwrap_or_else(|| format!("function {}", id)),
+            None => format!("task {}", timing.task_id),
+        }
+    }
+
+    /// Microseconds since the start of the recording, as used by the Chrome trace format.
+    fn micros(&self, timestamp: Duration) -> f64 {
+        timestamp.saturating_sub(self.start).as_nanos() as f64 / 1000.0
+    }
+}
+
+/// Convert a recorded session into the given format.
+pub fn export(recording: &Recording, format: ExportFormat) -> String {
+    match format {
+        ExportFormat::Chrome => to_chrome_trace(recording),
+        ExportFormat::Svg => to_svg(recording),
+    }
+}
+
+/// Convert a recording into the Chrome Trace Event format.
+///
+/// Every thread is a track, on which every task run is a slice named after its function.
+/// Dependencies between tasks, which both ran, are flow arrows. Barriers and waits are instant events.
+pub fn to_chrome_trace(recording: &Recording) -> String {
+    let pid = recording.pid;
+    let mut events = vec![json!({ "name": "process_name", "ph": "M", "pid": pid, "args": { "name": format!("Ayudame rt {} pid {}", recording.rt, pid) } })];
+
+    let ran = recording.timings.values()
+        .filter_map(|t| Some((t, t.thread_id?, t.start()?, t.ended?)))
+        .collect::<Vec<_>>();
+
+    let mut threads = ran.iter().map(|(_, thread_id, _, _)| *thread_id).collect::<Vec<_>>();
+    threads.sort();
+    threads.dedup();
+    for thread_id in threads {
+        events.push(json!({ "name": "thread_name", "ph": "M", "pid": pid, "tid": thread_id, "args": { "name": format!("thread {}", thread_id) } }));
+    }
+
+    for (timing, thread_id, start, end) in &ran {
+        let mut args = json!({ "task_id": timing.task_id });
+        if let Some(queue_wait) = timing.queue_wait() {
+            args["queue_wait_us"] = Value::from(queue_wait.as_nanos() as f64 / 1000.0);
+        }
+        events.push(json!({
+            "name": recording.function_name(timing),
+            "cat": "task",
+            "ph": "X",
+            "ts": recording.micros(*start),
+            "dur": recording.micros(*end) - recording.micros(*start),
+            "pid": pid,
+            "tid": thread_id,
+            "args": args,
+        }));
+    }
+
+    // flow events bind to the enclosing slice, so the arrow starts at the start of the parent and ends at the start of the child
+    let slice = |task_id: &u64| ran.iter().find(|(t, ..)| t.task_id == *task_id);
+    for (id, (parent, child)) in recording.dependencies.iter().enumerate() {
+        if let (Some((_, parent_thread, parent_start, _)), Some((_, child_thread, child_start, _))) = (slice(parent), slice(child)) {
+            events.push(json!({ "name": "dependency", "cat": "dependency", "ph": "s", "id": id, "ts": recording.micros(*parent_start), "pid": pid, "tid": parent_thread }));
+            events.push(json!({ "name": "dependency", "cat": "dependency", "ph": "f", "bp": "e", "id": id, "ts": recording.micros(*child_start), "pid": pid, "tid": child_thread }));
+        }
+    }
+
+    for (timestamp, event) in &recording.instants {
+        let mut instant = json!({ "name": format!("{:?}", event.event_type()), "cat": "runtime", "ph": "i", "s": "p", "ts": recording.micros(*timestamp), "pid": pid, "tid": 0 });
+        if let Event::WaitOn { task_id } = event {
+            instant["args"] = json!({ "task_id": task_id });
+        }
+        events.push(instant);
+    }
+
+    json!({ "traceEvents": events, "displayTimeUnit": "ns" }).to_string()
+}
+
+/// Render a recording as standalone SVG Gantt chart.
+///
+/// Every thread is a lane, on which every task run is a bar colored by its function. Hovering a bar shows the task id,
+/// its duration and dependencies. Barriers and waits are marked on the time axis.
+pub fn to_svg(recording: &Recording) -> String {
+    let ran = recording.timings.values()
+        .filter_map(|t| Some((t, t.thread_id?, t.start()?, t.ended?)))
+        .collect::<Vec<_>>();
+
+    let mut threads = ran.iter().map(|(_, thread_id, _, _)| *thread_id).collect::<Vec<_>>();
+    threads.sort();
+    threads.dedup();
+
+    let mut functions = ran.iter().map(|(t, ..)| t.function_id).collect::<Vec<_>>();
+    functions.sort();
+    functions.dedup();
+    let color = |function_id: Option<u64>| COLORS[functions.iter().position(|f| *f == function_id).unwrap_or_default() % COLORS.len()];
+
+    let end = ran.iter().map(|(.., end)| *end)
+        .chain(recording.instants.iter().map(|(t, _)| *t))
+        .max()
+        .unwrap_or_default()
+        .max(recording.start);
+    let span = (end - recording.start).max(Duration::from_nanos(1));
+    let x = |t: Duration| SVG_LABEL_WIDTH + t.saturating_sub(recording.start).as_secs_f64() / span.as_secs_f64() * SVG_CHART_WIDTH;
+
+    let axis_y = threads.len() as f64 * SVG_LANE_HEIGHT + 10.0;
+    let legend_y = axis_y + 40.0;
+    let width = SVG_LABEL_WIDTH + SVG_CHART_WIDTH + 20.0;
+    let height = legend_y + functions.len() as f64 * 18.0 + 10.0;
+
+    let mut svg = String::new();
+    let _ = writeln!(svg, r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" font-family="sans-serif" font-size="12">"#, width, height);
+    let _ = writeln!(svg, r#"<rect width="100%" height="100%" fill="white"/>"#);
+
+    for (lane, thread_id) in threads.iter().enumerate() {
+        let y = lane as f64 * SVG_LANE_HEIGHT;
+        let _ = writeln!(svg, r#"<text x="5" y="{:.1}">thread {}</text>"#, y + SVG_LANE_HEIGHT * 0.65, thread_id);
+        let _ = writeln!(svg, r##"<rect x="{}" y="{}" width="{}" height="{}" fill="#f4f4f4"/>"##, SVG_LABEL_WIDTH, y + 1.0, SVG_CHART_WIDTH, SVG_LANE_HEIGHT - 2.0);
+    }
+
+    for (timing, thread_id, start, end) in &ran {
+        let lane = threads.iter().position(|t| t == thread_id).unwrap_or_default();
+        let parents = recording.dependencies.iter().filter(|(_, c)| *c == timing.task_id).map(|(p, _)| *p).collect::<Vec<_>>();
+        let children = recording.dependencies.iter().filter(|(p, _)| *p == timing.task_id).map(|(_, c)| *c).collect::<Vec<_>>();
+        let title = format!("task {} ({})
+duration: {:?}
+parents: {:?}
+children: {:?}", timing.task_id, recording.function_name(timing), *end - *start, parents, children);
+        let _ = writeln!(
+            svg,
+            r#"<rect x="{:.2}" y="{}" width="{:.2}" height="{}" fill="{}" stroke="white" stroke-width="0.5"><title>{}</title></rect>"#,
+            x(*start), lane as f64 * SVG_LANE_HEIGHT + 3.0, (x(*end) - x(*start)).max(1.0), SVG_LANE_HEIGHT - 6.0, color(timing.function_id), escape(&title)
+        );
+    }
+
+    // time axis with ticks and the barrier and wait markers
+    let _ = writeln!(svg, r#"<line x1="{}" y1="{}" x2="{}" y2="{}" stroke="black"/>"#, SVG_LABEL_WIDTH, axis_y, SVG_LABEL_WIDTH + SVG_CHART_WIDTH, axis_y);
+    for i in 0..=SVG_TICKS {
+        let t = recording.start + span * i / SVG_TICKS;
+        let _ = writeln!(svg, r#"<line x1="{:.2}" y1="{}" x2="{:.2}" y2="{}" stroke="black"/>"#, x(t), axis_y, x(t), axis_y + 5.0);
+        let _ = writeln!(svg, r#"<text x="{:.2}" y="{}" text-anchor="middle">{:?}</text>"#, x(t), axis_y + 18.0, t - recording.start);
+    }
+    for (timestamp, event) in &recording.instants {
+        let title = match event {
+            Event::WaitOn { task_id } => format!("WaitOn task {} at {:?}", task_id, *timestamp - recording.start),
+            _ => format!("{:?} at {:?}", event.event_type(), *timestamp - recording.start),
+        };
+        let _ = writeln!(
+            svg,
+            r#"<g><title>{}</title><line x1="{:.2}" y1="0" x2="{:.2}" y2="{}" stroke="black" stroke-dasharray="3,3"/><path d="M{:.2} {} l-4 -7 h8 z" fill="black"/></g>"#,
+            escape(&title), x(*timestamp), x(*timestamp), axis_y, x(*timestamp), axis_y
+        );
+    }
+
+    for (idx, function_id) in functions.iter().enumerate() {
+        let y = legend_y + idx as f64 * 18.0;
+        let name = match function_id {
+            Some(id) => recording.functions.get(id).filter(|n| !n.is_empty()).cloned().unwrap_or_else(|| format!("function {}", id)),
+            None => String::from("no function"),
+        };
+        let _ = writeln!(svg, r#"<rect x="{}" y="{}" width="12" height="12" fill="{}"/>"#, SVG_LABEL_WIDTH, y, color(*function_id));
+        let _ = writeln!(svg, r#"<text x="{}" y="{}">{}</text>"#, SVG_LABEL_WIDTH + 18.0, y + 10.0, escape(&name));
+    }
+
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// Escape text for xml.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(timestamp: u64, event: Event) -> EventRecord {
+        EventRecord { rt: 0, timestamp, received: 0, name: None, event }
+    }
+
+    #[test]
+    fn svg_gantt_chart() {
+        let mut state = AppState::new();
+        state.create_function(1, String::from("a<b"));
+        for (task_id, thread_id) in [(1, 0), (2, 1)] {
+            let _ = state.create_task(task_id, false, Some(1), 0);
+            state.record_task_time(task_id, TaskState::PreRun, Duration::from_millis(task_id), Some(thread_id));
+            state.record_task_time(task_id, TaskState::Finished, Duration::from_millis(task_id + 2), None);
+        }
+        state.add_dependency(1, 2);
+
+        let svg = to_svg(&Recording::from_state(&state));
+        assert!(svg.starts_with("<svg") && svg.ends_with("</svg>\n"));
+        assert_eq!(svg.matches("<text x=\"5\"").count(), 2);
+        assert!(svg.contains("<title>task 2 (a&lt;b)\nduration: 2ms\nparents: [1]\nchildren: []</title>"));
+        assert!(svg.contains(">3ms</text>"));
+    }
+
+    #[test]
+    fn chrome_trace() {
+        let mut register = record(1000, Event::RegisterFunction { func_id: 1, string_len: 3 });
+        register.name = Some(String::from("foo"));
+        let records = [
+            record(1000, Event::PreInit { rt: 0, pid: 42 }),
+            register,
+            record(1000, Event::AddTask { task_id: 1, func_id: 1, priority: 0, scope_id: 0 }),
+            record(1000, Event::AddTask { task_id: 2, func_id: 9, priority: 0, scope_id: 0 }),
+            record(1000, Event::AddDependency { to_id: 2, from_id: 1, memaddr: 0, orig_memaddr: 0 }),
+            record(2000, Event::PreRunTask { task_id: 1, thread_id: 3 }),
+            record(4000, Event::PostRunTask { task_id: 1 }),
+            record(5000, Event::PreRunTask { task_id: 2, thread_id: 4 }),
+            record(6000, Event::RemoveTask { task_id: 1 }),
+            record(7000, Event::PostRunTask { task_id: 2 }),
+            record(8000, Event::Barrier),
+        ];
+
+        let trace: Value = serde_json::from_str(&to_chrome_trace(&Recording::new(&records))).unwrap();
+        let events = trace["traceEvents"].as_array().unwrap();
+        let of_phase = |ph: &str| events.iter().filter(|e| e["ph"] == ph).collect::<Vec<_>>();
+
+        let slices = of_phase("X");
+        assert_eq!(slices.len(), 2);
+        assert_eq!((&slices[0]["name"], &slices[0]["ts"], &slices[0]["dur"], &slices[0]["tid"]), (&json!("foo"), &json!(1.0), &json!(2.0), &json!(3)));
+        assert_eq!(slices[1]["name"], "function 9");
+        assert_eq!(of_phase("M").len(), 3);
+        assert_eq!(of_phase("s")[0]["tid"], 3);
+        assert_eq!((&of_phase("f")[0]["tid"], &of_phase("f")[0]["ts"]), (&json!(4), &json!(4.0)));
+        assert_eq!(of_phase("i")[0]["name"], "Barrier");
+    }
+}
@@ -2,9 +2,53 @@
 //! It is possible to send requests to Ayudame, in order to check if the bevahiour is as expected.
 //! When starting the app, a port may be specified via the `AYU_PORT` env. If not specified, it will default to 5555.
 //! After starting the app, it will try to connect to a Ayudame every second.
+//! Once connected, requests are entered as commands, e.g. `break task 12`, `step 5` or `continue`.
+//! Type `help` for a list of all commands.
 //! 
 //! Usage: AYU_PORT=5555 cargo run --release
 //! 
+//! Sessions can also be run without user input, by passing a script (see [script::Script]):
+//! 
+//! Usage: AYU_PORT=5555 cargo run --release -- --script session.ayu --timeout 60
+//! 
+//! The received events can be recorded as a trace and checked against expectations, e.g. a golden trace (see [assertions::Expectations]):
+//! 
+//! Usage: AYU_PORT=5555 cargo run --release -- --record session.trace --expect session.expect
+//! 
+//! For further processing, the events can be written to stdout as json lines (see [output::OutputFormat]):
+//! 
+//! Usage: AYU_PORT=5555 cargo run --release -- --output jsonl | jq .event.type
+//! 
+//! Instead of the command line, a full-screen dashboard can be used to watch and steer a session (see [tui]):
+//! 
+//! Usage: AYU_PORT=5555 cargo run --release -- --tui
+//! 
+//! In every mode, the state and events can also be served via http and WebSocket, e.g. for a browser based visualisation (see [bridge::serve]):
+//! 
+//! Usage: AYU_PORT=5555 cargo run --release -- --http 127.0.0.1:8080
+//! 
+//! For long running sessions, metrics can be exported in the Prometheus format (see [metrics::Metrics]):
+//! 
+//! Usage: AYU_PORT=5555 cargo run --release -- --metrics 127.0.0.1:9100
+//! 
+//! To check whether Ayudame delays events, the timestamps can be analysed, a summary is printed at Finish (see [timing::TimingAnalysis]):
+//! 
+//! Usage: AYU_PORT=5555 cargo run --release -- --timing
+//! 
+//! Several instances of Ayudame, e.g. one per MPI rank, can be debugged on the command line at once (see [sessions::SessionManager]):
+//! 
+//! Usage: cargo run --release -- --connect 127.0.0.1:5555 --connect 127.0.0.1:5556 --listen 127.0.0.1:6000
+//! 
+//! Sessions recorded as json lines can be exported as Chrome trace, which can be opened in Perfetto, or as SVG Gantt chart (see [export]):
+//! 
+//! Usage: cargo run --release -- export --format chrome session.jsonl -o session.trace.json
+//! Usage: cargo run --release -- export --format svg session.jsonl -o session.svg
+//! Usage: cargo run --release -- profile session.jsonl
+//! 
+//! Two sessions, e.g. of an old and a new build of Ayudame, can be compared (see [diff]):
+//! 
+//! Usage: cargo run --release -- diff --match structure old.jsonl new.jsonl
+//! 
 //! Depending on the implementation of Ayudame (Rust or Cpp) some requests may not do anything.
 
 /// Contains all handlers in order to create a request.
@@ -13,13 +57,52 @@ pub mod request_handlers;
 /// Contains all handlers for incoming events.
 pub mod ayu_event_handlers;
 
-use std::{net::{TcpStream, SocketAddrV4, Ipv4Addr}, env::VarError, time::Duration, sync::{Arc, RwLock}, io::{Read, Write}};
+/// Contains the conditional breakpoints, which are evaluated by the frontend.
+pub mod breakpoints;
+
+/// Contains the command language, which is used to create requests.
+pub mod commands;
+
+/// Contains the interactive command line for sending requests.
+pub mod repl;
+
+/// Contains the scripts, which drive a session without user input.
+pub mod script;
+
+/// Contains the expectations, which are checked against the events and the final state of a session.
+pub mod assertions;
+
+/// Contains the machine readable output of received events.
+pub mod output;
+
+/// Contains the terminal ui, which shows the state of a session and sends requests.
+pub mod tui;
+
+/// Contains the http server, which serves the state and events and sends requests.
+pub mod bridge;
+
+/// Contains the metrics of a session, which can be scraped by Prometheus.
+pub mod metrics;
+
+/// Contains the sessions, if several instances of Ayudame are connected.
+pub mod sessions;
+
+/// Contains the analysis of the event timestamps, e.g. the latency between Ayudame and the frontend.
+pub mod timing;
+
+/// Contains the export of recorded sessions into other formats, e.g. for Perfetto.
+pub mod export;
+
+/// Contains the comparison of two recorded sessions or state snapshots.
+pub mod diff;
+
+use std::{net::TcpStream, env::VarError, time::Duration, sync::{Arc, Mutex, RwLock}, io::Read, process::ExitCode, thread::JoinHandle};
 
-use io_utils::match_or_continue;
-use utils::AppState;
+use clap::{Parser, Subcommand};
+use utils::{AppState, events::{Event, EventMeta}, stats::ParallelismProfile};
 
-use crate::{request_handlers as requests, ayu_event_handlers::EventResult, requests::{prepare_break_at_task, prepare_unbreak_at_task, prepare_null, prepare_no_request, prepare_pause_on_event, prepare_pause_on_task, prepare_pause_on_function, prepare_step, prepare_breakpoint, prepare_block_task, prepare_prioritise_task, prepare_set_num_threads, prepare_continue, prepare_break}};
-use crate::ayu_event_handlers as events;
+use crate::{assertions::{ExpectationChecker, Expectations}, breakpoints::{BreakpointHandler, Breakpoints}, bridge::Bridge, diff::{MatchBy, SessionSummary}, export::{ExportFormat, Recording}, metrics::Metrics, output::{JsonLines, OutputFormat, TraceRecorder}, script::{Script, ScriptRunner}, sessions::{Session, SessionHandler, SessionManager}, timing::TimingHandler, tui::Dashboard};
+use crate::ayu_event_handlers::{EventContext, EventResult, HandlerChain, PrintHandler, StateHandler};
 
 /// Default Port, if none is specified.
 const AYU_PORT: u16 = 5555;
@@ -27,92 +110,435 @@ const AYU_PORT: u16 = 5555;
 /// Size of the Buffer in bytes sent via the socket.
 const BUF_SIZE: usize = 8 * 8;
 
+/// Exit code, if a script or the expectations could not be read.
+const EXIT_INVALID_INPUT: u8 = 2;
+
+/// Exit code, if a script did not finish in time.
+const EXIT_TIMEOUT: u8 = 3;
+
+/// A small frontend for debugging Ayudame.
+#[derive(Parser)]
+struct Args {
+    #[command(subcommand)]
+    tool: Option<Tool>,
+    /// Run the session headless, driven by the given script instead of user input.
+    #[arg(long)]
+    script: Option<String>,
+    /// Abort a scripted session after the given number of seconds.
+    #[arg(long)]
+    timeout: Option<u64>,
+    /// Run the session headless and check the received events and the final state against the given expectations.
+    #[arg(long)]
+    expect: Option<String>,
+    /// Record all received events to the given file, which can be used as a golden trace.
+    #[arg(long)]
+    record: Option<String>,
+    /// Format of the received events on stdout, jsonl runs the session headless.
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    output: OutputFormat,
+    /// Use a full-screen terminal ui instead of the command line.
+    #[arg(long, conflicts_with_all = ["script", "expect", "output"])]
+    tui: bool,
+    /// Serve the state and events via http and WebSocket on the given address, e.g. 127.0.0.1:8080.
+    #[arg(long)]
+    http: Option<String>,
+    /// Serve Prometheus metrics on the given address, e.g. 127.0.0.1:9100.
+    #[arg(long)]
+    metrics: Option<String>,
+    /// Analyse the timestamps of the events and print a summary of the latency, gaps and clock jumps at Finish.
+    #[arg(long, conflicts_with = "tui")]
+    timing: bool,
+    /// Connect to Ayudame on the given address instead of AYU_PORT, may be repeated for several sessions.
+    #[arg(long)]
+    connect: Vec<String>,
+    /// Accept connections of Ayudame instances on the given address, every connection is a separate session.
+    #[arg(long)]
+    listen: Option<String>,
+}
+
+/// Tools, which work on recorded sessions and don't connect to Ayudame.
+#[derive(Subcommand)]
+enum Tool {
+    /// Convert a session recorded with `--output jsonl`, e.g. into a trace for Perfetto.
+    Export {
+        /// The recorded session.
+        input: String,
+        #[arg(long, value_enum, default_value_t = ExportFormat::Chrome)]
+        format: ExportFormat,
+        /// Write to the given file instead of stdout.
+        #[arg(short, long)]
+        out: Option<String>,
+    },
+    /// Show the ready and running tasks over time and the critical path of a session recorded with `--output jsonl`.
+    Profile {
+        /// The recorded session.
+        input: String,
+    },
+    /// Compare two sessions recorded with `--output jsonl` or snapshots saved from `GET /state`, exits with 1 if they differ.
+    Diff {
+        old: String,
+        new: String,
+        /// Match tasks by id or by their function and parents, if the ids differ between the runs.
+        #[arg(long = "match", value_enum, default_value_t = MatchBy::Id)]
+        match_by: MatchBy,
+    },
+}
+
 /// Sets up the tcp stream and loops for sending requests and listening for events.
-fn main() -> Result<(), String> {
+fn main() -> ExitCode {
+    let args = Args::parse();
+
+    if let Some(tool) = args.tool {
+        return run_tool(tool);
+    }
+
+    // all other modes use a single state
+    let is_multi_session = args.connect.len() > 1 || args.listen.is_some();
+    if is_multi_session && (args.tui || args.script.is_some() || args.expect.is_some() || args.record.is_some() || args.http.is_some() || args.metrics.is_some() || args.timing || args.output != OutputFormat::Text) {
+        eprintln!("Several sessions can only be used on the interactive command line.");
+        return ExitCode::from(EXIT_INVALID_INPUT);
+    }
+
+    let script = match args.script.as_deref().map(Script::from_file).transpose() {
+        Ok(script) => script,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ExitCode::from(EXIT_INVALID_INPUT);
+        },
+    };
+
+    let expectations = match args.expect.as_deref().map(Expectations::from_file).transpose() {
+        Ok(expectations) => expectations,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ExitCode::from(EXIT_INVALID_INPUT);
+        },
+    };
+
+    let recorder = match args.record.as_deref().map(TraceRecorder::create).transpose() {
+        Ok(recorder) => recorder,
+        Err(e) => {
+            eprintln!("Unable to create trace file: {}", e);
+            return ExitCode::from(EXIT_INVALID_INPUT);
+        },
+    };
+
+    if let Some(timeout) = args.timeout {
+        std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_secs(timeout));
+            eprintln!("Session did not finish after {} seconds, aborting...", timeout);
+            std::process::exit(EXIT_TIMEOUT as i32);
+        });
+    }
+
+    if is_multi_session {
+        return run_sessions(args.connect, args.listen);
+    }
+
     // tries to connect to a socket, should be read from AYU_PORT env
-    let port = std::env::var("AYU_PORT")
-                .and_then(|p| p.parse::<u16>()
-                .map_err(|_| VarError::NotPresent))
-                .unwrap_or(AYU_PORT);
-    
-    let event_receive_stream = loop {
-        match TcpStream::connect(SocketAddrV4::new(Ipv4Addr::new(127, 0, 0, 1), port)).map_err(|e| format!("Unable to connect to socket: {}, aborting...", e)) {
-            Ok(s) => break s,
-            Err(_) => std::thread::sleep(Duration::from_secs(1)),
+    let address = args.connect.first().cloned().unwrap_or_else(|| {
+        let port = std::env::var("AYU_PORT")
+                    .and_then(|p| p.parse::<u16>()
+                    .map_err(|_| VarError::NotPresent))
+                    .unwrap_or(AYU_PORT);
+        format!("127.0.0.1:{}", port)
+    });
+    let event_receive_stream = connect(&address);
+
+    let state = Arc::new(RwLock::new(AppState::new()));
+    let breakpoints = Arc::new(Mutex::new(Breakpoints::new()));
+
+    // the state needs to be updated first, so all other handlers see the handled event
+    let mut handlers = HandlerChain::new();
+    handlers.push(StateHandler);
+    match args.output {
+        // the handlers would print over the ui
+        _ if args.tui => (),
+        OutputFormat::Text => handlers.push(PrintHandler),
+        OutputFormat::Jsonl => handlers.push(JsonLines),
+    }
+    if let Some(recorder) = recorder {
+        handlers.push(recorder);
+    }
+    if args.timing {
+        let is_jsonl = args.output == OutputFormat::Jsonl;
+        handlers.push(TimingHandler::new(move |message| match is_jsonl {
+            // keep stdout free for the events
+            true => eprintln!("{}", message.trim_end()),
+            false => println!("{}", message.trim_end()),
+        }));
+    }
+
+    if let Some(address) = &args.http {
+        let bridge = Arc::new(Bridge::new());
+        if let Err(e) = bridge::serve(address, Arc::clone(&bridge), Arc::clone(&state), Arc::clone(&breakpoints), event_receive_stream.try_clone().unwrap()) {
+            eprintln!("{}", e);
+            return ExitCode::FAILURE;
+        }
+        handlers.push(bridge);
+    }
+
+    if let Some(address) = &args.metrics {
+        let metrics = Arc::new(Metrics::new());
+        if let Err(e) = metrics::serve(address, Arc::clone(&metrics), Arc::clone(&state)) {
+            eprintln!("{}", e);
+            return ExitCode::FAILURE;
         }
+        handlers.push(metrics);
+    }
+
+    if args.tui {
+        return run_tui(state, breakpoints, handlers, event_receive_stream);
+    }
+
+    if script.is_none() && expectations.is_none() && args.output == OutputFormat::Text {
+        return run_interactive(state, breakpoints, handlers, address, event_receive_stream);
+    }
+
+    run_headless(state, breakpoints, script.unwrap_or_default(), expectations, handlers, args.output, event_receive_stream)
+}
+
+/// Runs a tool on a recorded session.
+fn run_tool(tool: Tool) -> ExitCode {
+    let input = match &tool {
+        Tool::Export { input, .. } | Tool::Profile { input } => input,
+        Tool::Diff { old, new, match_by } => return run_diff(old, new, *match_by),
+    };
+    let recording = match Recording::from_file(input) {
+        Ok(recording) => recording,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ExitCode::from(EXIT_INVALID_INPUT);
+        },
     };
-    let request_stream = event_receive_stream.try_clone().unwrap();
 
-    let event_receive_state = Arc::new(RwLock::new(AppState::new())); 
-    let request_state = Arc::clone(&event_receive_state);
+    match tool {
+        Tool::Export { format, out, .. } => write_output(out.as_deref(), &export::export(&recording, format)),
+        Tool::Profile { .. } => match ParallelismProfile::new(recording.timings.values(), commands::PROFILE_SAMPLES) {
+            Some(profile) => write_output(None, &commands::format_profile(&profile, |id| recording.functions.get(&id).filter(|n| !n.is_empty()).cloned())),
+            None => {
+                eprintln!("No task finished running in {}", input);
+                ExitCode::from(EXIT_INVALID_INPUT)
+            },
+        },
+        Tool::Diff { .. } => unreachable!(),
+    }
+}
+
+/// Prints the differences between two sessions, exits with 1 if they differ.
+fn run_diff(old: &str, new: &str, match_by: MatchBy) -> ExitCode {
+    let (old, new) = match (SessionSummary::from_file(old), SessionSummary::from_file(new)) {
+        (Ok(old), Ok(new)) => (old, new),
+        (Err(e), _) | (_, Err(e)) => {
+            eprintln!("{}", e);
+            return ExitCode::from(EXIT_INVALID_INPUT);
+        },
+    };
 
-    let event_receiver = std::thread::spawn(event_receiver_loop(event_receive_state, event_receive_stream));
-    let request_sender = std::thread::spawn(request_sender_loop(request_state, request_stream));
+    let diff = diff::diff(&old, &new, match_by);
+    print!("{}", diff.report);
+    match diff.changes {
+        0 => ExitCode::SUCCESS,
+        _ => ExitCode::FAILURE,
+    }
+}
+
+/// Write the output of a tool to the given file or to stdout.
+fn write_output(path: Option<&str>, content: &str) -> ExitCode {
+    let Some(path) = path else {
+        println!("{}", content);
+        return ExitCode::SUCCESS;
+    };
+
+    match std::fs::write(path, content) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Unable to write {}: {}", path, e);
+            ExitCode::FAILURE
+        },
+    }
+}
+
+/// Runs a session with user input, where requests are read on one thread and events are received on another.
+fn run_interactive(state: Arc<RwLock<AppState>>, breakpoints: Arc<Mutex<Breakpoints>>, handlers: HandlerChain<'static>, address: String, event_receive_stream: TcpStream) -> ExitCode {
+    let sessions = Arc::new(SessionManager::new());
+
+    let event_receiver = start_session(&sessions, address, state, breakpoints, handlers, event_receive_stream);
+    let request_sender = std::thread::spawn(request_sender_loop(sessions));
 
     println!("Connected to socket. Waiting for threads to finish...");
     let _ = event_receiver.join();
     let _ = request_sender.join();
     
-    Ok(())
+    ExitCode::SUCCESS
+}
+
+/// Runs several sessions with user input, which are connected to the given addresses or accepted on the listen address.
+/// 
+/// Every session has its own state and breakpoints, commands are sent to the selected session or to all sessions.
+fn run_sessions(addresses: Vec<String>, listen: Option<String>) -> ExitCode {
+    let sessions = Arc::new(SessionManager::new());
+
+    if let Some(address) = listen {
+        let listen_sessions = Arc::clone(&sessions);
+        let result = sessions::listen(&address, move |address, stream| {
+            println!("Accepted session from {}", address);
+            start_session(&listen_sessions, address, Arc::new(RwLock::new(AppState::new())), Arc::new(Mutex::new(Breakpoints::new())), session_handlers(), stream);
+        });
+        if let Err(e) = result {
+            eprintln!("{}", e);
+            return ExitCode::FAILURE;
+        }
+        println!("Listening for sessions on {}", address);
+    }
+
+    for address in addresses {
+        let stream = connect(&address);
+        println!("Connected to {}", address);
+        start_session(&sessions, address, Arc::new(RwLock::new(AppState::new())), Arc::new(Mutex::new(Breakpoints::new())), session_handlers(), stream);
+    }
+
+    repl::run(sessions);
+    ExitCode::SUCCESS
+}
+
+/// The handlers of a session, if several sessions are connected.
+fn session_handlers() -> HandlerChain<'static> {
+    let mut handlers = HandlerChain::new();
+    handlers.push(StateHandler);
+    handlers.push(PrintHandler);
+    handlers
+}
+
+/// Add a session for the connection and receive its events on another thread.
+fn start_session(sessions: &Arc<SessionManager>, address: String, state: Arc<RwLock<AppState>>, breakpoints: Arc<Mutex<Breakpoints>>, mut handlers: HandlerChain<'static>, stream: TcpStream) -> JoinHandle<()> {
+    let session = sessions.add(address, state, breakpoints, stream.try_clone().unwrap());
+
+    handlers.push(SessionHandler::new(Arc::clone(sessions), session.id));
+    handlers.push(BreakpointHandler::new(Arc::clone(&session.breakpoints), |message| println!("{}", message)));
+
+    std::thread::spawn(event_receiver_loop(session, handlers, stream))
+}
+
+/// Tries to connect to Ayudame every second, until it succeeds.
+fn connect(address: &str) -> TcpStream {
+    loop {
+        match TcpStream::connect(address) {
+            Ok(s) => break s,
+            Err(_) => std::thread::sleep(Duration::from_secs(1)),
+        }
+    }
 }
 
-/// The main loop of the application, asks the user to input a request, which is then sent to Ayudame.
-fn request_sender_loop(state: Arc<RwLock<AppState>>, mut stream: TcpStream) -> impl FnOnce() -> () {
+/// Runs a session in the terminal ui, where events are received on another thread and shown in the dashboard.
+fn run_tui(state: Arc<RwLock<AppState>>, breakpoints: Arc<Mutex<Breakpoints>>, mut handlers: HandlerChain<'static>, stream: TcpStream) -> ExitCode {
+    let dashboard = Arc::new(Mutex::new(Dashboard::new()));
+
+    handlers.push(Arc::clone(&dashboard));
+    let breakpoint_dashboard = Arc::clone(&dashboard);
+    handlers.push(BreakpointHandler::new(Arc::clone(&breakpoints), move |message| breakpoint_dashboard.lock().unwrap().log(message)));
+
+    let event_receive_stream = stream.try_clone().unwrap();
+    let event_receive_state = Arc::clone(&state);
+    let event_receive_dashboard = Arc::clone(&dashboard);
+    std::thread::spawn(move || {
+        let is_finished = receive_events(&event_receive_state, &event_receive_stream, &mut handlers);
+        let message = if is_finished { "Ayudame finished, type quit to exit." } else { "Connection closed." };
+        event_receive_dashboard.lock().unwrap().log(message);
+    });
+
+    match tui::run(state, breakpoints, dashboard, stream) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Terminal ui failed: {}", e);
+            ExitCode::FAILURE
+        },
+    }
+}
+
+/// Runs a session headless, where the requests are sent by a script.
+/// 
+/// Exits with success, if all steps of the script were executed, Ayudame finished and all expectations hold.
+/// Progress of the script is printed to stderr, so stdout only contains the received events.
+fn run_headless(state: Arc<RwLock<AppState>>, breakpoints: Arc<Mutex<Breakpoints>>, script: Script, expectations: Option<Expectations>, handlers: HandlerChain<'static>, output_format: OutputFormat, stream: TcpStream) -> ExitCode {
+    let mut runner = ScriptRunner::new(script, Arc::clone(&breakpoints), output_format == OutputFormat::Jsonl);
+    let mut checker = expectations.map(ExpectationChecker::new);
+
+    let is_finished = runner.start(&state, &stream) && {
+        // the runner and checker are borrowed, so they can be inspected after the session
+        let mut handlers: HandlerChain = handlers;
+        handlers.push(BreakpointHandler::new(breakpoints, |message| eprintln!("{}", message)));
+        handlers.push(&mut runner);
+        if let Some(checker) = &mut checker {
+            handlers.push(checker);
+        }
+        receive_events(&state, &stream, &mut handlers)
+    };
+
+    if !runner.is_successful() || !(is_finished || runner.has_quit()) {
+        eprintln!("Script did not run successfully.");
+        return ExitCode::FAILURE;
+    }
+
+    if let Some(checker) = checker {
+        let failures = checker.check(&state.read().unwrap());
+        if !failures.is_empty() {
+            eprintln!("{} expectations failed:", failures.len());
+            failures.iter().for_each(|f| eprintln!("{}", f));
+            return ExitCode::FAILURE;
+        }
+        eprintln!("All expectations hold.");
+    }
+
+    ExitCode::SUCCESS
+}
+
+/// The main loop of the application, reads commands from the user, which are then sent to Ayudame.
+fn request_sender_loop(sessions: Arc<SessionManager>) -> impl FnOnce() {
     move || {
         println!("Started AyuRequest Sender thread");
-        loop {
-            let mut buf = [0u8; BUF_SIZE];
-            println!("Tasks:");
-            {
-                let s = state.write().unwrap();
-                s.list_tasks();
-            }
-            requests::print_options();
-            let request = match_or_continue!(requests::get_request_type());
-            requests::write_request(&mut buf, &request);
-            let result = match request {
-                utils::requests::Request::Null => prepare_null(),
-                utils::requests::Request::NoRequest => prepare_no_request(),
-                utils::requests::Request::PauseOnEvent => prepare_pause_on_event(&mut buf),
-                utils::requests::Request::PauseOnTask => prepare_pause_on_task(&mut buf, &state),
-                utils::requests::Request::PauseOnFunction => prepare_pause_on_function(&mut buf),
-                utils::requests::Request::Step => prepare_step(&mut buf),
-                utils::requests::Request::Breakpoint => prepare_breakpoint(&mut buf),
-                utils::requests::Request::BlockTask => prepare_block_task(&mut buf, &state),
-                utils::requests::Request::PrioritiseTask => prepare_prioritise_task(&mut buf, &state),
-                utils::requests::Request::SetNumThreads => prepare_set_num_threads(&mut buf),
-                utils::requests::Request::Continue => prepare_continue(&mut buf),
-                utils::requests::Request::Break => prepare_break(&mut buf),
-                utils::requests::Request::BreakAtTask => prepare_break_at_task(&mut buf, &state),
-                utils::requests::Request::UnbreakAtTask => prepare_unbreak_at_task(&mut buf, &state),
-            };
-            // pretty_print_buf(&buf);
-            match result {
-                Ok(_) => { let _ = stream.write(&mut buf); },
-                Err(e) => eprintln!("{}", e), 
-            }
-        }
+        repl::run(sessions);
     }
 }
 
-/// A passive loop, run on another thread, which listens for events from Ayudame.
-fn event_receiver_loop(mut state: Arc<RwLock<AppState>>, mut stream: TcpStream) -> impl FnOnce() -> () {
+/// A passive loop, run on another thread, which listens for events from Ayudame. The session is closed afterwards.
+fn event_receiver_loop(session: Arc<Session>, mut handlers: HandlerChain<'static>, stream: TcpStream) -> impl FnOnce() {
     move || {
-        let mut buf = [0u8; 64];
         println!("Started AyuEvent Receiver thread");
-        loop {
-            let n = match stream.read(&mut buf){
-                Ok(n) => n,
-                Err(_) => 0,
-            };
-            
-            if n > 0 {
-                // println!("Read: {} bytes", n);
-                match events::handle_event(&buf, &mut state, &mut stream) {
-                    Ok(result) => if let EventResult::Exit = result { break }
-                    Err(e) => eprintln!("Unable to handle received event: {}", e),
-                }
-            }
+        receive_events(&session.state, &stream, &mut handlers);
+        session.close();
+    }
+}
+
+/// Receives events from Ayudame and passes them to the handlers, until Ayudame finishes or the connection is closed.
+/// 
+/// A handler may stop the session, in this case no further events are received.
+/// Returns true, if a finish event was received.
+fn receive_events(state: &Arc<RwLock<AppState>>, mut stream: &TcpStream, handlers: &mut HandlerChain) -> bool {
+    let mut buf = [0u8; 64];
+    loop {
+        // events are always 64 bytes, which may arrive in several parts
+        if stream.read_exact(&mut buf).is_err() {
+            return false; // connection was closed
+        }
+
+        let event = match Event::try_from(buf.as_slice()) {
+            Ok(event) => event,
+            Err(e) => {
+                handlers.decode_error(&e);
+                continue;
+            },
+        };
+
+        // read the meta information first, handling RegisterFunction events reads from the stream again
+        let Some(meta) = EventMeta::from_buffer(&buf) else {
+            continue;
+        };
+        let mut ctx = EventContext::new(&meta, state, stream);
+        match handlers.handle(&event, &mut ctx) {
+            EventResult::Exit => return true,
+            EventResult::Stop => return false,
+            EventResult::Success => (),
         }
     }
 }
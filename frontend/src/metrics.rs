@@ -0,0 +1,224 @@
+use std::{collections::{BTreeMap, HashMap}, fmt::Write, sync::{Arc, Mutex, RwLock}, thread, time::UNIX_EPOCH};
+
+use tiny_http::{Header, Response, Server};
+use utils::{events::{Event, EventError, EventMeta}, AppState, TaskState};
+
+use crate::ayu_event_handlers::{EventContext, EventHandler};
+
+/// Upper bounds of the buckets of the latency histogram in seconds.
+const LATENCY_BUCKETS: [f64; 10] = [0.0001, 0.0005, 0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0];
+
+/// All task states, so every state is exported, even if no task is in it.
+const TASK_STATES: [TaskState; 5] = [TaskState::Created, TaskState::Queued, TaskState::PreRun, TaskState::Running, TaskState::Finished];
+
+/// Counters of a single thread.
+#[derive(Debug, Default)]
+struct ThreadCounters {
+    running: u64,
+    started: u64,
+}
+
+/// Histogram of the event latency, in seconds.
+#[derive(Debug, Default)]
+struct Histogram {
+    /// Number of observations per bucket, not cumulative.
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, value: f64) {
+        if let Some(idx) = LATENCY_BUCKETS.iter().position(|b| value <= *b) {
+            self.buckets[idx] += 1;
+        }
+        self.sum += value;
+        self.count += 1;
+    }
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    events: BTreeMap<String, u64>,
+    decode_errors: u64,
+    threads: BTreeMap<u64, ThreadCounters>,
+    /// Thread of every task, which is currently running.
+    running_tasks: HashMap<u64, u64>,
+    latency: Histogram,
+    /// Smallest difference between receive time and timestamp so far, in nanoseconds.
+    fastest: Option<i128>,
+}
+
+/// Metrics of a session, which are exported in the Prometheus text format.
+///
+/// Counters are derived from the received events, gauges from the [AppState] when the metrics are scraped.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    counters: Mutex<Counters>,
+}
+
+impl Metrics {
+    /// Creates metrics, where all counters are zero.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Update the counters with a received event.
+    ///
+    /// The runtime clock and the local clock don't need to share an origin, so the latency is the delay of an event
+    /// relative to the fastest event so far, as in [TimingAnalysis](crate::timing::TimingAnalysis).
+    pub fn observe(&self, event: &Event, meta: &EventMeta) {
+        let mut counters = self.counters.lock().unwrap();
+        *counters.events.entry(format!("{:?}", event.event_type())).or_default() += 1;
+
+        match *event {
+            Event::PreRunTask { task_id, thread_id } => {
+                let thread = counters.threads.entry(thread_id).or_default();
+                thread.running += 1;
+                thread.started += 1;
+                counters.running_tasks.insert(task_id, thread_id);
+            },
+            Event::PostRunTask { task_id } => {
+                if let Some(thread_id) = counters.running_tasks.remove(&task_id) {
+                    counters.threads.entry(thread_id).or_default().running -= 1;
+                }
+            },
+            _ => (),
+        }
+
+        let received = meta.received.duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos() as i128;
+        let offset = received - meta.timestamp.as_nanos() as i128;
+        let fastest = counters.fastest.map_or(offset, |f| f.min(offset));
+        counters.fastest = Some(fastest);
+        counters.latency.observe((offset - fastest) as f64 / 1e9);
+    }
+
+    /// Count an event, which could not be decoded.
+    pub fn decode_error(&self) {
+        self.counters.lock().unwrap().decode_errors += 1;
+    }
+
+    /// Render all metrics in the Prometheus text format.
+    pub fn render(&self, state: &AppState) -> String {
+        let counters = self.counters.lock().unwrap();
+        let mut out = String::new();
+
+        header(&mut out, "ayu_events_total", "counter", "Number of received events per type.");
+        for (event_type, n) in &counters.events {
+            let _ = writeln!(out, "ayu_events_total{{type=\"{}\"}} {}", event_type, n);
+        }
+
+        header(&mut out, "ayu_decode_errors_total", "counter", "Number of received events, which could not be decoded.");
+        let _ = writeln!(out, "ayu_decode_errors_total {}", counters.decode_errors);
+
+        header(&mut out, "ayu_tasks", "gauge", "Number of tasks per lifecycle state.");
+        for task_state in TASK_STATES {
+            let n = state.get_tasks().iter().filter(|t| t.get_state() == task_state).count();
+            let _ = writeln!(out, "ayu_tasks{{state=\"{}\"}} {}", task_state, n);
+        }
+
+        header(&mut out, "ayu_functions", "gauge", "Number of registered functions.");
+        let _ = writeln!(out, "ayu_functions {}", state.get_functions().len());
+
+        header(&mut out, "ayu_dependencies", "gauge", "Number of dependencies between tasks.");
+        let _ = writeln!(out, "ayu_dependencies {}", state.get_dependencies().len());
+
+        header(&mut out, "ayu_thread_running_tasks", "gauge", "Number of tasks currently running on a thread.");
+        for (thread_id, thread) in &counters.threads {
+            let _ = writeln!(out, "ayu_thread_running_tasks{{thread=\"{}\"}} {}", thread_id, thread.running);
+        }
+
+        header(&mut out, "ayu_thread_started_tasks_total", "counter", "Number of tasks started on a thread.");
+        for (thread_id, thread) in &counters.threads {
+            let _ = writeln!(out, "ayu_thread_started_tasks_total{{thread=\"{}\"}} {}", thread_id, thread.started);
+        }
+
+        header(&mut out, "ayu_event_latency_seconds", "histogram", "Delay of an event relative to the fastest event so far.");
+        let latency = &counters.latency;
+        let mut cumulative = 0;
+        for (bound, n) in LATENCY_BUCKETS.iter().zip(latency.buckets) {
+            cumulative += n;
+            let _ = writeln!(out, "ayu_event_latency_seconds_bucket{{le=\"{}\"}} {}", bound, cumulative);
+        }
+        let _ = writeln!(out, "ayu_event_latency_seconds_bucket{{le=\"+Inf\"}} {}", latency.count);
+        let _ = writeln!(out, "ayu_event_latency_seconds_sum {}", latency.sum);
+        let _ = writeln!(out, "ayu_event_latency_seconds_count {}", latency.count);
+
+        out
+    }
+}
+
+impl EventHandler for Arc<Metrics> {
+    fn on_event(&mut self, ctx: &mut EventContext, event: &Event) {
+        self.observe(event, ctx.meta);
+    }
+
+    fn on_decode_error(&mut self, _error: &EventError) {
+        self.decode_error();
+    }
+}
+
+/// Write the help and type lines of a metric.
+fn header(out: &mut String, name: &str, metric_type: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, metric_type);
+}
+
+/// Start a http server on the given address, which serves the metrics on `GET /metrics`.
+pub fn serve(address: &str, metrics: Arc<Metrics>, state: Arc<RwLock<AppState>>) -> Result<(), String> {
+    let server = Server::http(address).map_err(|e| format!("Unable to start metrics server on {}: {}", address, e))?;
+    let content_type = Header::from_bytes("Content-Type", "text/plain; version=0.0.4").expect("header needs to be ascii");
+
+    thread::spawn(move || {
+        for request in server.incoming_requests() {
+            let response = match request.url() {
+                "/metrics" => Response::from_string(metrics.render(&state.read().unwrap())).with_header(content_type.clone()),
+                _ => Response::from_string("Not found").with_status_code(404),
+            };
+            let _ = request.respond(response);
+        }
+    });
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn render_metrics() {
+        let mut state = AppState::new();
+        let _ = state.create_task(1, false, None, 0);
+        let _ = state.create_task(2, false, None, 0);
+        state.add_dependency(1, 2);
+        state.set_task_state(1, TaskState::Finished);
+
+        let metrics = Metrics::new();
+        // timestamps don't count from the unix epoch, the second event is delayed by 2ms
+        let meta = |timestamp: u64, received: u64| EventMeta {
+            rt: 0,
+            timestamp: Duration::from_micros(timestamp),
+            received: UNIX_EPOCH + Duration::from_secs(1_700_000_000) + Duration::from_micros(received),
+        };
+        metrics.observe(&Event::PreRunTask { task_id: 1, thread_id: 3 }, &meta(1_004_000, 0));
+        metrics.observe(&Event::PreRunTask { task_id: 2, thread_id: 3 }, &meta(1_005_000, 3_000));
+        metrics.observe(&Event::PostRunTask { task_id: 1 }, &meta(1_006_000, 2_000));
+        metrics.decode_error();
+
+        let out = metrics.render(&state);
+        assert!(out.contains("ayu_events_total{type=\"PreRunTask\"} 2\n"));
+        assert!(out.contains("ayu_decode_errors_total 1\n"));
+        assert!(out.contains("ayu_tasks{state=\"finished\"} 1\n"));
+        assert!(out.contains("ayu_tasks{state=\"queued\"} 0\n"));
+        assert!(out.contains("ayu_dependencies 1\n"));
+        assert!(out.contains("ayu_thread_running_tasks{thread=\"3\"} 1\n"));
+        assert!(out.contains("ayu_thread_started_tasks_total{thread=\"3\"} 2\n"));
+        assert!(out.contains("ayu_event_latency_seconds_bucket{le=\"0.0001\"} 2\n"));
+        assert!(out.contains("ayu_event_latency_seconds_bucket{le=\"0.001\"} 2\n"));
+        assert!(out.contains("ayu_event_latency_seconds_bucket{le=\"0.005\"} 3\n"));
+        assert!(out.contains("ayu_event_latency_seconds_count 3\n"));
+    }
+}
@@ -0,0 +1,116 @@
+use std::{fs::File, io::{self, BufWriter, Write}, time::UNIX_EPOCH};
+
+use clap::ValueEnum;
+use utils::{events::{Event, EventError, EventMeta, EventRecord}, AppState};
+
+use crate::{ayu_event_handlers::{EventContext, EventHandler}, commands};
+
+/// Format in which received events are written to stdout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum OutputFormat {
+    /// Human readable messages for every event.
+    #[default]
+    Text,
+    /// One json object per event and line, the session runs headless.
+    Jsonl,
+}
+
+/// Serialize a received event as a single line of json.
+///
+/// The state is used to look up the name of registered functions, so the event needs to be handled already.
+pub fn to_json_line(event: &Event, meta: &EventMeta, state: &AppState) -> String {
+    let name = match *event {
+        Event::RegisterFunction { func_id, .. } => state.find_function(&func_id.to_string()).map(|f| f.get_name().to_string()),
+        _ => None,
+    };
+
+    let record = EventRecord {
+        rt: meta.rt,
+        timestamp: meta.timestamp.as_nanos() as u64,
+        received: meta.received.duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos() as u64,
+        name,
+        event: *event,
+    };
+
+    // serializing only fails for maps with non-string keys, which are not used here
+    serde_json::to_string(&record).unwrap_or_default()
+}
+
+/// Writes every received event to stdout as json line.
+pub struct JsonLines;
+
+impl EventHandler for JsonLines {
+    fn on_event(&mut self, ctx: &mut EventContext, event: &Event) {
+        println!("{}", to_json_line(event, ctx.meta, &ctx.state.read().unwrap()));
+    }
+
+    fn on_finish(&mut self, ctx: &mut EventContext) {
+        // keep stdout free for the events
+        eprint!("{}", commands::stats(&ctx.state.read().unwrap(), None).unwrap_or_default());
+    }
+
+    fn on_decode_error(&mut self, error: &EventError) {
+        eprintln!("Unable to handle received event: {}", error);
+    }
+}
+
+/// Writes received events to a trace file, one event per line.
+pub struct TraceRecorder {
+    writer: Option<BufWriter<File>>,
+}
+
+impl TraceRecorder {
+    /// Create the trace file, an existing file is truncated.
+    pub fn create(path: &str) -> io::Result<Self> {
+        Ok(Self { writer: Some(BufWriter::new(File::create(path)?)) })
+    }
+}
+
+impl EventHandler for TraceRecorder {
+    fn on_event(&mut self, _ctx: &mut EventContext, event: &Event) {
+        if let Some(writer) = &mut self.writer {
+            // flush every event, so the trace is complete even if the frontend is aborted
+            if writeln!(writer, "{}", event).and_then(|_| writer.flush()).is_err() {
+                eprintln!("Unable to record event, stopping recording.");
+                self.writer = None;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn event_to_json_line() {
+        let mut state = AppState::new();
+        state.create_function(4, "foo".to_string());
+        let meta = EventMeta { rt: 1, timestamp: Duration::from_nanos(42), received: UNIX_EPOCH + Duration::from_nanos(100) };
+
+        let event = Event::AddTask { task_id: 3, func_id: 4, priority: 0, scope_id: 0 };
+        assert_eq!(
+            to_json_line(&event, &meta, &state),
+            r#"{"rt":1,"timestamp":42,"received":100,"event":{"type":"AddTask","task_id":3,"func_id":4,"priority":0,"scope_id":0}}"#
+        );
+
+        let event = Event::RegisterFunction { func_id: 4, string_len: 3 };
+        assert!(to_json_line(&event, &meta, &state).contains(r#""name":"foo""#));
+        assert!(to_json_line(&Event::Finish, &meta, &state).ends_with(r#""event":{"type":"Finish"}}"#));
+    }
+
+    #[test]
+    fn read_recorded_events() {
+        let state = AppState::new();
+        let meta = EventMeta { rt: 1, timestamp: Duration::from_nanos(42), received: UNIX_EPOCH };
+        let event = Event::PreRunTask { task_id: 3, thread_id: 1 };
+        let text = format!("{}\n\n{}\n", to_json_line(&event, &meta, &state), to_json_line(&Event::Finish, &meta, &state));
+
+        let records = utils::events::read_json_lines(&text).unwrap();
+        assert_eq!(records.iter().map(|r| r.event).collect::<Vec<_>>(), [event, Event::Finish]);
+        assert_eq!(records[0].timestamp, 42);
+        assert_eq!(utils::events::read_json_lines("{}").unwrap_err().to_string(), "Invalid event in line 1: missing field `rt` at line 1 column 2");
+    }
+}
@@ -0,0 +1,132 @@
+use std::{path::PathBuf, sync::Arc};
+
+use rustyline::{completion::{Completer, Pair}, error::ReadlineError, highlight::Highlighter, hint::Hinter, history::DefaultHistory, validate::Validator, Context, Editor, Helper};
+
+use crate::{commands::{self, Command, CommandError, KEYWORDS}, sessions::SessionManager};
+
+/// Name of the file in the home directory, in which the command history is stored.
+const HISTORY_FILE: &str = ".ayu_frontend_history";
+
+/// Prompt displayed when waiting for a command.
+const PROMPT: &str = "(ayu) ";
+
+/// Helper for rustyline, which completes commands, task ids and function names from the [utils::AppState] of the selected session.
+struct CommandHelper {
+    sessions: Arc<SessionManager>,
+}
+
+impl CommandHelper {
+    /// Get all candidates for the word at the given position in the line.
+    fn candidates(&self, previous: &[&str]) -> Vec<String> {
+        let state = self.sessions.selected_state().unwrap_or_default();
+        let state = state.read().unwrap();
+        match previous {
+            [] => KEYWORDS.iter().map(|k| k.to_string()).collect(),
+            [.., "task"] | ["deps"] => state.get_tasks().iter().map(|t| t.get_id().to_string()).collect(),
+            [.., "function"] | ["stats"] => state.get_functions().iter().map(|f| f.get_name().to_string()).collect(),
+            [.., "event"] => (0..=17)
+                .filter_map(|id| utils::events::EventType::try_from(id).ok())
+                .map(|e| format!("{:?}", e))
+                .collect(),
+            ["pause"] => vec!["event".to_string(), "task".to_string(), "function".to_string()],
+            ["pause", "function", _, _] => vec!["emulate".to_string()],
+            ["break"] => ["task", "when", "list", "delete"].iter().map(|s| s.to_string()).collect(),
+            ["break", "when"] => ["function", "thread", "finished", "parents"].iter().map(|s| s.to_string()).collect(),
+            ["pause", _, _] | ["breakpoint"] | ["block", _, _] => vec!["on".to_string(), "off".to_string()],
+            ["show"] => vec!["task".to_string(), "function".to_string()],
+            ["tasks"] => ["--created", "--queued", "--prerun", "--running", "--finished"].iter().map(|s| s.to_string()).collect(),
+            ["session"] => std::iter::once("all".to_string()).chain((1..=self.sessions.len()).map(|id| id.to_string())).collect(),
+            ["help"] => KEYWORDS.iter().map(|k| k.to_string()).collect(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+impl Completer for CommandHelper {
+    type Candidate = Pair;
+
+    fn complete(&self, line: &str, pos: usize, _ctx: &Context<'_>) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let line = &line[..pos];
+        let start = line.rfind(char::is_whitespace).map_or(0, |i| i + 1);
+        let previous = line[..start].split_whitespace().collect::<Vec<_>>();
+        let word = &line[start..];
+
+        let pairs = self.candidates(&previous)
+            .into_iter()
+            .filter(|c| c.starts_with(word))
+            .map(|c| Pair { display: c.clone(), replacement: c })
+            .collect();
+
+        Ok((start, pairs))
+    }
+}
+
+impl Hinter for CommandHelper {
+    type Hint = String;
+}
+
+impl Highlighter for CommandHelper {}
+
+impl Validator for CommandHelper {}
+
+impl Helper for CommandHelper {}
+
+/// Reads commands from the user and sends the resulting requests to the selected sessions.
+///
+/// Supports a history, which is persisted in the home directory, and tab completion.
+/// If several sessions are connected, the prompt shows the selected session.
+pub fn run(sessions: Arc<SessionManager>) {
+    let mut editor = match Editor::<CommandHelper, DefaultHistory>::new() {
+        Ok(editor) => editor,
+        Err(e) => return eprintln!("Unable to start command line: {}", e),
+    };
+    editor.set_helper(Some(CommandHelper { sessions: Arc::clone(&sessions) }));
+
+    let history = history_path();
+    if let Some(path) = &history {
+        let _ = editor.load_history(path);
+    }
+
+    print!("{}", commands::help(None));
+    loop {
+        let prompt = match sessions.len() {
+            0 | 1 => PROMPT.to_string(),
+            _ => format!("(ayu {}) ", sessions.selected()),
+        };
+        let line = match editor.readline(&prompt) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(e) => {
+                eprintln!("Unable to read command: {}", e);
+                break;
+            },
+        };
+        let _ = editor.add_history_entry(line.as_str());
+
+        let command = match line.parse::<Command>() {
+            Ok(Command::Quit) => break,
+            Ok(command) => command,
+            Err(CommandError::Empty) => continue,
+            Err(e) => {
+                eprintln!("{}", e);
+                continue;
+            },
+        };
+
+        match sessions.execute(&command) {
+            Ok(output) => print!("{}", output),
+            Err(e) => eprintln!("{}", e),
+        }
+    }
+
+    if let Some(path) = &history {
+        let _ = editor.save_history(path);
+    }
+    sessions.shutdown();
+}
+
+/// Path of the history file, if the home directory is known.
+fn history_path() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(HISTORY_FILE))
+}
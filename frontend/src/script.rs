@@ -0,0 +1,260 @@
+use std::{collections::VecDeque, fmt::Display, fs, net::TcpStream, sync::{Arc, Mutex, RwLock}};
+
+use utils::{events::{Event, EventType}, AppState};
+
+use crate::{ayu_event_handlers::{EventContext, EventHandler}, breakpoints::Breakpoints, commands::{self, Command}};
+
+/// Errors which can occur when reading a script.
+#[derive(Debug, PartialEq)]
+pub enum ScriptError {
+    Io(String),
+    Syntax { line: usize, msg: String },
+}
+
+impl Display for ScriptError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ScriptError::Io(msg) => write!(f, "Unable to read script: {}", msg),
+            ScriptError::Syntax { line, msg } => write!(f, "Invalid script, line {}: {}", line, msg),
+        }
+    }
+}
+
+/// Condition, after which the command of a step is executed.
+#[derive(Debug, PartialEq)]
+pub enum Trigger {
+    /// Right away, after the previous step was executed (`start` or `then`).
+    Immediately,
+    /// After an event of the given type was received, optionally for a specific task (`after AddTask 5`).
+    Event(EventType, Option<u64>),
+    /// After the given number of events was received since the previous step (`after 10 events`).
+    Events(u64),
+}
+
+impl Trigger {
+    /// Parse a trigger, e.g. `after PreInit`, `when AddTask 5` or `after 10 events`.
+    fn parse(trigger: &str) -> Result<Self, String> {
+        let words = trigger.split_whitespace().collect::<Vec<_>>();
+        let trigger = match &words[..] {
+            ["start"] | ["then"] => Trigger::Immediately,
+            ["after" | "when", n, "events"] => Trigger::Events(n.parse().map_err(|_| format!("Invalid number of events: {}", n))?),
+            ["after" | "when", event] => Trigger::Event(event.parse()?, None),
+            ["after" | "when", event, task_id] => Trigger::Event(event.parse()?, Some(task_id.parse().map_err(|_| format!("Invalid task id: {}", task_id))?)),
+            _ => return Err(format!("Invalid trigger: {}", trigger.trim())),
+        };
+
+        Ok(trigger)
+    }
+}
+
+/// A single step of a script, consisting of a trigger and the command to execute.
+#[derive(Debug, PartialEq)]
+pub struct Step {
+    pub line: usize,
+    pub trigger: Trigger,
+    pub command: Command,
+}
+
+/// A script, which drives a session without user input.
+///
+/// Each line of a script has the form `<trigger>: <command>`, where command is any command of the command line, e.g.
+/// ```text
+/// # comments start with a hash
+/// after PreInit: break
+/// when AddTask 5: break task 5
+/// after 10 events: step 3
+/// then: continue
+/// ```
+/// Steps are executed in order, each step waits for its trigger after the previous step was executed.
+#[derive(Debug, Default)]
+pub struct Script {
+    steps: VecDeque<Step>,
+    events_since_step: u64,
+}
+
+impl Script {
+    /// Read a script from a file.
+    pub fn from_file(path: &str) -> Result<Self, ScriptError> {
+        let file = fs::read_to_string(path).map_err(|e| ScriptError::Io(e.to_string()))?;
+        file.parse()
+    }
+
+    /// Returns true, if all steps were executed.
+    pub fn is_done(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    /// Returns all steps, which can be executed before any event was received.
+    pub fn start(&mut self) -> Vec<Step> {
+        self.take_immediate_steps()
+    }
+
+    /// Returns all steps triggered by the event, in the order they need to be executed.
+    pub fn next_steps(&mut self, event: &Event) -> Vec<Step> {
+        self.events_since_step += 1;
+
+        let is_triggered = match self.steps.front().map(|s| &s.trigger) {
+            Some(Trigger::Event(event_type, task_id)) => *event_type == event.event_type() && (task_id.is_none() || *task_id == event.task_id()),
+            Some(Trigger::Events(n)) => self.events_since_step >= *n,
+            _ => false,
+        };
+
+        if !is_triggered {
+            return Vec::new();
+        }
+
+        self.events_since_step = 0;
+        let mut steps = self.steps.pop_front().into_iter().collect::<Vec<_>>();
+        steps.extend(self.take_immediate_steps());
+        steps
+    }
+
+    /// Take all steps from the front of the script, which are triggered immediately.
+    fn take_immediate_steps(&mut self) -> Vec<Step> {
+        let mut steps = Vec::new();
+        while self.steps.front().is_some_and(|s| s.trigger == Trigger::Immediately) {
+            steps.extend(self.steps.pop_front());
+        }
+        steps
+    }
+}
+
+/// Executes the steps of a script, as soon as they are triggered by the received events.
+///
+/// Progress of the script is printed to stderr, a quit command stops receiving events.
+pub struct ScriptRunner {
+    script: Script,
+    breakpoints: Arc<Mutex<Breakpoints>>,
+    /// Write the output of commands to stderr, to keep stdout free for the events.
+    is_quiet: bool,
+    has_failed: bool,
+    has_quit: bool,
+}
+
+impl ScriptRunner {
+    /// Creates a runner for the script, the breakpoints are used by the commands of the script.
+    pub fn new(script: Script, breakpoints: Arc<Mutex<Breakpoints>>, is_quiet: bool) -> Self {
+        Self { script, breakpoints, is_quiet, has_failed: false, has_quit: false }
+    }
+
+    /// Execute all steps, which don't wait for an event. Returns false, if the script quit.
+    pub fn start(&mut self, state: &Arc<RwLock<AppState>>, stream: &TcpStream) -> bool {
+        let steps = self.script.start();
+        self.execute(steps, state, stream);
+        !self.has_quit
+    }
+
+    /// Returns true, if all steps were executed without errors.
+    pub fn is_successful(&self) -> bool {
+        !self.has_failed && self.script.is_done()
+    }
+
+    /// Returns true, if the script executed a quit command.
+    pub fn has_quit(&self) -> bool {
+        self.has_quit
+    }
+
+    fn execute(&mut self, steps: Vec<Step>, state: &Arc<RwLock<AppState>>, stream: &TcpStream) {
+        for step in steps {
+            eprintln!("Script line {}: {:?}", step.line, step.command);
+            if step.command == Command::Quit {
+                self.has_quit = true;
+                break;
+            }
+            match commands::execute(&step.command, state, &self.breakpoints, stream) {
+                Ok(output) if self.is_quiet => eprint!("{}", output),
+                Ok(output) => print!("{}", output),
+                Err(e) => {
+                    eprintln!("Script line {} failed: {}", step.line, e);
+                    self.has_failed = true;
+                },
+            }
+        }
+    }
+}
+
+impl EventHandler for ScriptRunner {
+    fn on_event(&mut self, ctx: &mut EventContext, event: &Event) {
+        if self.has_quit {
+            return;
+        }
+        let steps = self.script.next_steps(event);
+        self.execute(steps, ctx.state, ctx.stream);
+        if self.has_quit {
+            ctx.stop();
+        }
+    }
+}
+
+impl std::str::FromStr for Script {
+    type Err = ScriptError;
+
+    fn from_str(file: &str) -> Result<Self, Self::Err> {
+        let mut steps = VecDeque::new();
+
+        for (i, line) in file.lines().enumerate() {
+            let line_nr = i + 1;
+            // remove comments
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            let (trigger, command) = line.split_once(':')
+                .ok_or(ScriptError::Syntax { line: line_nr, msg: String::from("needs to be in the form of `<trigger>: <command>`") })?;
+            let trigger = Trigger::parse(trigger).map_err(|msg| ScriptError::Syntax { line: line_nr, msg })?;
+            let command = command.parse::<Command>().map_err(|e| ScriptError::Syntax { line: line_nr, msg: e.to_string() })?;
+
+            steps.push_back(Step { line: line_nr, trigger, command });
+        }
+
+        Ok(Script { steps, events_since_step: 0 })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCRIPT: &str = "
+# pause right after initialization
+after PreInit: break
+start: tasks
+when AddTask 5: break task 5
+after 2 events: step 3
+then: continue
+";
+
+    #[test]
+    fn parse_script() {
+        let mut script = SCRIPT.parse::<Script>().unwrap();
+        assert_eq!(script.steps.len(), 5);
+        assert_eq!(script.steps[0], Step { line: 3, trigger: Trigger::Event(EventType::PreInit, None), command: Command::Break });
+        assert_eq!(script.steps[2].trigger, Trigger::Event(EventType::AddTask, Some(5)));
+        assert_eq!(script.steps[3].trigger, Trigger::Events(2));
+        assert!(script.start().is_empty());
+    }
+
+    #[test]
+    fn parse_invalid_script() {
+        assert_eq!("after PreInit break".parse::<Script>().unwrap_err(), ScriptError::Syntax { line: 1, msg: String::from("needs to be in the form of `<trigger>: <command>`") });
+        assert!(matches!("\nafter Nothing: break".parse::<Script>(), Err(ScriptError::Syntax { line: 2, .. })));
+        assert!(matches!("after PreInit: jump".parse::<Script>(), Err(ScriptError::Syntax { line: 1, .. })));
+    }
+
+    #[test]
+    fn run_script() {
+        let mut script = SCRIPT.parse::<Script>().unwrap();
+
+        let steps = script.next_steps(&Event::PreInit { rt: 0, pid: 1 });
+        assert_eq!(steps.iter().map(|s| s.line).collect::<Vec<_>>(), vec![3, 4]);
+
+        assert!(script.next_steps(&Event::AddTask { task_id: 4, func_id: 0, priority: 0, scope_id: 0 }).is_empty());
+        assert_eq!(script.next_steps(&Event::AddTask { task_id: 5, func_id: 0, priority: 0, scope_id: 0 }).len(), 1);
+
+        assert!(script.next_steps(&Event::Barrier).is_empty());
+        let steps = script.next_steps(&Event::Barrier);
+        assert_eq!(steps.iter().map(|s| &s.command).collect::<Vec<_>>(), vec![&Command::Step(3), &Command::Continue]);
+        assert!(script.is_done());
+    }
+}
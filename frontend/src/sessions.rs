@@ -0,0 +1,320 @@
+use std::{fmt::Display, net::{Shutdown, TcpListener, TcpStream}, sync::{atomic::{AtomicBool, Ordering}, Arc, Mutex, RwLock}, thread};
+
+use utils::AppState;
+
+use crate::{ayu_event_handlers::{EventContext, EventHandler}, breakpoints::Breakpoints, commands::{self, Command, CommandError}};
+
+/// Identifies an instance of Ayudame, taken from its PreInit event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SessionKey {
+    pub rt: u64,
+    pub pid: u64,
+}
+
+impl Display for SessionKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.rt, self.pid)
+    }
+}
+
+/// A connection to a single instance of Ayudame, with its own state and breakpoints.
+#[derive(Debug)]
+pub struct Session {
+    pub id: usize,
+    pub address: String,
+    pub state: Arc<RwLock<AppState>>,
+    pub breakpoints: Arc<Mutex<Breakpoints>>,
+    pub stream: TcpStream,
+    /// Set once the PreInit event was received.
+    key: Mutex<Option<SessionKey>>,
+    is_closed: AtomicBool,
+}
+
+impl Session {
+    /// Returns the rt and pid of the session, if its PreInit event was received.
+    pub fn key(&self) -> Option<SessionKey> {
+        *self.key.lock().unwrap()
+    }
+
+    /// Mark the session as closed, e.g. after Ayudame finished. Its state can still be inspected.
+    pub fn close(&self) {
+        self.is_closed.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.is_closed.load(Ordering::Relaxed)
+    }
+}
+
+impl Display for Session {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let key = self.key().map_or(String::from("-"), |k| k.to_string());
+        let status = if self.is_closed() { "closed" } else { "connected" };
+        write!(f, "{}: rt:pid {}, {}, {}", self.id, key, self.address, status)
+    }
+}
+
+/// All sessions of the frontend, e.g. one per MPI rank, and the session commands are sent to.
+#[derive(Debug, Default)]
+pub struct SessionManager {
+    sessions: RwLock<Vec<Arc<Session>>>,
+    /// Id of the session, which receives the commands. None sends them to all sessions.
+    selected: Mutex<Option<usize>>,
+}
+
+impl SessionManager {
+    /// Creates a manager without any sessions.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a session for a new connection. The first session is selected.
+    pub fn add(&self, address: String, state: Arc<RwLock<AppState>>, breakpoints: Arc<Mutex<Breakpoints>>, stream: TcpStream) -> Arc<Session> {
+        let mut sessions = self.sessions.write().unwrap();
+        let session = Arc::new(Session {
+            // ids are not reused, even if a closed session was removed
+            id: sessions.last().map_or(1, |s| s.id + 1),
+            address,
+            state,
+            breakpoints,
+            stream,
+            key: Mutex::new(None),
+            is_closed: AtomicBool::new(false),
+        });
+        sessions.push(Arc::clone(&session));
+
+        let mut selected = self.selected.lock().unwrap();
+        if sessions.len() == 1 {
+            *selected = Some(session.id);
+        }
+
+        session
+    }
+
+    /// Set the rt and pid of a session. Returns an error, if another connected session has the same key.
+    ///
+    /// If a closed session has the same key, the rank reconnected: the session continues with the state and
+    /// breakpoints of the closed session, which is removed.
+    pub fn identify(&self, id: usize, key: SessionKey) -> Result<(), String> {
+        let mut sessions = self.sessions.write().unwrap();
+        let Some(session) = sessions.iter().find(|s| s.id == id).cloned() else {
+            return Ok(());
+        };
+        *session.key.lock().unwrap() = Some(key);
+
+        if let Some(index) = sessions.iter().position(|s| s.id != id && s.is_closed() && s.key() == Some(key)) {
+            let closed = sessions.remove(index);
+            std::mem::swap(&mut *session.state.write().unwrap(), &mut *closed.state.write().unwrap());
+            std::mem::swap(&mut *session.breakpoints.lock().unwrap(), &mut *closed.breakpoints.lock().unwrap());
+
+            let mut selected = self.selected.lock().unwrap();
+            if *selected == Some(closed.id) {
+                *selected = Some(id);
+            }
+        }
+
+        match sessions.iter().find(|s| s.id != id && !s.is_closed() && s.key() == Some(key)) {
+            Some(other) => Err(format!("Session {} has the same rt:pid {} as session {}, select it by id", id, key, other.id)),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns the number of sessions, including closed ones.
+    pub fn len(&self) -> usize {
+        self.sessions.read().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns a short description of the selected session, e.g. for the prompt.
+    pub fn selected(&self) -> String {
+        match *self.selected.lock().unwrap() {
+            Some(id) => id.to_string(),
+            None => String::from("all"),
+        }
+    }
+
+    /// The state of the selected session, or of the first session if all are selected.
+    pub fn selected_state(&self) -> Option<Arc<RwLock<AppState>>> {
+        let selected = *self.selected.lock().unwrap();
+        let sessions = self.sessions.read().unwrap();
+        let session = match selected {
+            Some(id) => sessions.iter().find(|s| s.id == id),
+            None => sessions.first(),
+        };
+        session.map(|s| Arc::clone(&s.state))
+    }
+
+    /// Select the session, which receives the commands, by id, `rt:pid` or `all`.
+    pub fn select(&self, target: &str) -> Result<String, CommandError> {
+        let sessions = self.sessions.read().unwrap();
+        let session = match target {
+            "all" => {
+                *self.selected.lock().unwrap() = None;
+                return Ok(format!("Sending commands to all {} sessions\n", sessions.len()));
+            },
+            _ if target.contains(':') => {
+                let mut matching = sessions.iter().filter(|s| s.key().is_some_and(|k| k.to_string() == target));
+                match (matching.next(), matching.next()) {
+                    (Some(session), None) => session,
+                    (Some(_), Some(_)) => return Err(CommandError::InvalidArgument(format!("Several sessions have rt:pid {}, select one by id", target))),
+                    _ => return Err(CommandError::InvalidArgument(format!("No session found for rt:pid {}", target))),
+                }
+            },
+            _ => {
+                let id = target.parse::<usize>().map_err(|_| CommandError::InvalidArgument(target.to_string()))?;
+                sessions.iter().find(|s| s.id == id).ok_or(CommandError::InvalidArgument(format!("No session found for id: {}", id)))?
+            },
+        };
+
+        *self.selected.lock().unwrap() = Some(session.id);
+        Ok(format!("Sending commands to session {}\n", session))
+    }
+
+    /// Execute a command for the selected session or for all sessions.
+    ///
+    /// If the command is executed for all sessions, every line of the output is prefixed with the session id.
+    pub fn execute(&self, command: &Command) -> Result<String, CommandError> {
+        match command {
+            Command::Sessions => return Ok(self.to_string()),
+            Command::SelectSession(target) => return self.select(target),
+            Command::Help(cmd) => return Ok(commands::help(cmd.as_deref())),
+            _ => (),
+        }
+
+        let selected = *self.selected.lock().unwrap();
+        let sessions = self.sessions.read().unwrap();
+        if let Some(id) = selected {
+            let session = sessions.iter().find(|s| s.id == id).ok_or(CommandError::InvalidArgument(format!("No session found for id: {}", id)))?;
+            return commands::execute(command, &session.state, &session.breakpoints, &session.stream);
+        }
+
+        if sessions.is_empty() {
+            return Err(CommandError::InvalidArgument(String::from("No session connected")));
+        }
+
+        // closed sessions can't receive requests anymore
+        let mut output = String::new();
+        for session in sessions.iter().filter(|s| command.request().is_none() || !s.is_closed()) {
+            let lines = match commands::execute(command, &session.state, &session.breakpoints, &session.stream) {
+                Ok(lines) => lines,
+                Err(e) => format!("{}\n", e),
+            };
+            output.extend(lines.lines().map(|l| format!("[{}] {}\n", session.id, l)));
+        }
+
+        Ok(output)
+    }
+
+    /// Close the connections of all sessions.
+    pub fn shutdown(&self) {
+        for session in self.sessions.read().unwrap().iter() {
+            let _ = session.stream.shutdown(Shutdown::Both);
+        }
+    }
+}
+
+impl Display for SessionManager {
+    /// Lists all sessions, one per line. The selected session is marked with a star.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let selected = *self.selected.lock().unwrap();
+        let sessions = self.sessions.read().unwrap();
+        if sessions.is_empty() {
+            writeln!(f, "No session connected.")?;
+        }
+        for session in sessions.iter() {
+            let marker = if selected.is_none_or(|id| id == session.id) { "*" } else { " " };
+            writeln!(f, "{} {}", marker, session)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Sets the rt and pid of a session, once its PreInit event was received.
+pub struct SessionHandler {
+    sessions: Arc<SessionManager>,
+    id: usize,
+}
+
+impl SessionHandler {
+    pub fn new(sessions: Arc<SessionManager>, id: usize) -> Self {
+        Self { sessions, id }
+    }
+}
+
+impl EventHandler for SessionHandler {
+    fn on_pre_init(&mut self, _ctx: &mut EventContext, rt: u64, pid: u64) {
+        if let Err(e) = self.sessions.identify(self.id, SessionKey { rt, pid }) {
+            eprintln!("{}", e);
+        }
+    }
+}
+
+/// Accept connections of Ayudame instances on another thread, `on_connect` is called for every connection.
+pub fn listen(address: &str, on_connect: impl Fn(String, TcpStream) + Send + 'static) -> Result<(), String> {
+    let listener = TcpListener::bind(address).map_err(|e| format!("Unable to listen on {}: {}", address, e))?;
+
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let address = stream.peer_addr().map_or(String::from("unknown"), |a| a.to_string());
+            on_connect(address, stream);
+        }
+    });
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn select_and_broadcast() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let connect = || TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+
+        let sessions = SessionManager::new();
+        for pid in [10, 11] {
+            let state = Arc::new(RwLock::new(AppState::new()));
+            let _ = state.write().unwrap().create_task(pid, false, None, 0);
+            let session = sessions.add(String::from("a"), state, Arc::new(Mutex::new(Breakpoints::new())), connect());
+            assert_eq!(sessions.identify(session.id, SessionKey { rt: 0, pid }), Ok(()));
+        }
+        assert!(sessions.identify(2, SessionKey { rt: 0, pid: 10 }).is_err());
+        assert_eq!(sessions.selected(), "1");
+
+        assert!(sessions.select("0:10").is_err());
+        assert!(sessions.select("3").is_err());
+        assert!(sessions.select("2").is_ok());
+        assert_eq!(sessions.execute(&Command::Tasks(None)).unwrap().lines().count(), 1);
+
+        assert!(sessions.execute(&Command::SelectSession(String::from("all"))).is_ok());
+        let output = sessions.execute(&Command::Tasks(None)).unwrap();
+        assert_eq!(output.lines().map(|l| &l[..3]).collect::<Vec<_>>(), ["[1]", "[2]"]);
+    }
+
+    #[test]
+    fn reattach_reconnected_rank() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let connect = || TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let key = SessionKey { rt: 0, pid: 10 };
+
+        let sessions = SessionManager::new();
+        let first = sessions.add(String::from("a"), Arc::new(RwLock::new(AppState::new())), Arc::new(Mutex::new(Breakpoints::new())), connect());
+        assert_eq!(sessions.identify(first.id, key), Ok(()));
+        let _ = first.state.write().unwrap().create_task(1, false, None, 0);
+        first.close();
+
+        let second = sessions.add(String::from("a"), Arc::new(RwLock::new(AppState::new())), Arc::new(Mutex::new(Breakpoints::new())), connect());
+        assert_eq!(sessions.identify(second.id, key), Ok(()));
+        assert_eq!(sessions.len(), 1);
+        assert_eq!(sessions.selected(), second.id.to_string());
+        assert_eq!(sessions.execute(&Command::Tasks(None)).unwrap().lines().count(), 1);
+
+        let third = sessions.add(String::from("a"), Arc::new(RwLock::new(AppState::new())), Arc::new(Mutex::new(Breakpoints::new())), connect());
+        assert_eq!(third.id, 3);
+    }
+}
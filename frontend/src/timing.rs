@@ -0,0 +1,191 @@
+use std::{fmt::Write, time::{Duration, UNIX_EPOCH}};
+
+use utils::events::{Event, EventMeta, EventType};
+
+use crate::ayu_event_handlers::{EventContext, EventHandler};
+
+/// Gaps between two events, which are at least this long, are counted.
+const GAP_THRESHOLD: Duration = Duration::from_millis(100);
+
+/// The runtime clock and the local clock may drift apart by this much between two events, before it counts as a jump.
+const CLOCK_JUMP_THRESHOLD: Duration = Duration::from_secs(1);
+
+/// Number of the longest gaps, which are shown in the summary.
+const LONGEST_GAPS: usize = 3;
+
+/// A gap between two consecutive events.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Gap {
+    duration: Duration,
+    /// Index of the event after the gap.
+    idx: usize,
+    event_type: EventType,
+}
+
+/// Analyses the timestamps set by Ayudame and the times the events were received.
+///
+/// The runtime clock and the local clock don't need to share an origin, so the latency is the delay of an event
+/// relative to the fastest event of the session. If Ayudame buffers events, the latency grows under load.
+#[derive(Debug, Default)]
+pub struct TimingAnalysis {
+    /// Timestamp and receive time of the previous event in nanoseconds.
+    previous: Option<(i128, i128)>,
+    /// Difference between receive time and timestamp for every event in nanoseconds.
+    offsets: Vec<i128>,
+    gaps: Vec<Gap>,
+    n_gaps: usize,
+    out_of_order: usize,
+    clock_jumps: usize,
+}
+
+impl TimingAnalysis {
+    /// Creates an analysis without any events.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a received event. Returns a warning, if the timestamp is out of order or the clock jumped.
+    pub fn observe(&mut self, event: &Event, meta: &EventMeta) -> Option<String> {
+        let idx = self.offsets.len();
+        let timestamp = meta.timestamp.as_nanos() as i128;
+        let received = meta.received.duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos() as i128;
+        self.offsets.push(received - timestamp);
+
+        let (previous_timestamp, previous_received) = self.previous.replace((timestamp, received))?;
+        let runtime_delta = timestamp - previous_timestamp;
+        let local_delta = received - previous_received;
+
+        if runtime_delta >= GAP_THRESHOLD.as_nanos() as i128 {
+            self.n_gaps += 1;
+            self.gaps.push(Gap { duration: Duration::from_nanos(runtime_delta as u64), idx, event_type: event.event_type() });
+            self.gaps.sort_by_key(|g| std::cmp::Reverse(g.duration));
+            self.gaps.truncate(LONGEST_GAPS);
+        }
+
+        if runtime_delta < 0 {
+            self.out_of_order += 1;
+            return Some(format!("Event {} ({:?}) is out of order, its timestamp is {} before the previous event", idx, event.event_type(), format_nanos(-runtime_delta)));
+        }
+
+        let drift = runtime_delta - local_delta;
+        if drift.abs() >= CLOCK_JUMP_THRESHOLD.as_nanos() as i128 {
+            self.clock_jumps += 1;
+            return Some(format!("Clock jump before event {} ({:?}), runtime clock advanced {}, local clock {}", idx, event.event_type(), format_nanos(runtime_delta), format_nanos(local_delta)));
+        }
+
+        None
+    }
+
+    /// Latencies of all events relative to the fastest event in nanoseconds, sorted ascending.
+    fn latencies(&self) -> Vec<i128> {
+        let fastest = self.offsets.iter().min().copied().unwrap_or_default();
+        let mut latencies = self.offsets.iter().map(|o| o - fastest).collect::<Vec<_>>();
+        latencies.sort();
+        latencies
+    }
+
+    /// A readable summary of the latency, gaps, out of order timestamps and clock jumps.
+    pub fn summary(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "Timing summary of {} events:", self.offsets.len());
+
+        let latencies = self.latencies();
+        if let (Some(max), Some(offset)) = (latencies.last(), self.offsets.iter().min()) {
+            let mean = latencies.iter().sum::<i128>() / latencies.len() as i128;
+            let _ = writeln!(
+                out,
+                "  latency (relative to the fastest event): mean {}, p50 {}, p90 {}, p99 {}, max {}",
+                format_nanos(mean), format_nanos(percentile(&latencies, 50)), format_nanos(percentile(&latencies, 90)), format_nanos(percentile(&latencies, 99)), format_nanos(*max)
+            );
+            let _ = writeln!(out, "  offset of the local clock to the runtime clock: {}", format_nanos(*offset));
+        }
+
+        let gaps = self.gaps.iter().map(|g| format!("{} before event {} ({:?})", format_nanos(g.duration.as_nanos() as i128), g.idx, g.event_type)).collect::<Vec<_>>();
+        match gaps.is_empty() {
+            true => { let _ = writeln!(out, "  gaps of at least {}: 0", format_nanos(GAP_THRESHOLD.as_nanos() as i128)); },
+            false => { let _ = writeln!(out, "  gaps of at least {}: {}, longest: {}", format_nanos(GAP_THRESHOLD.as_nanos() as i128), self.n_gaps, gaps.join(", ")); },
+        }
+        let _ = writeln!(out, "  out of order timestamps: {}", self.out_of_order);
+        let _ = writeln!(out, "  clock jumps: {}", self.clock_jumps);
+
+        out
+    }
+}
+
+/// Get the value at the given percentile of sorted values.
+fn percentile(sorted: &[i128], p: usize) -> i128 {
+    let idx = (sorted.len() * p).div_ceil(100).saturating_sub(1);
+    sorted.get(idx).copied().unwrap_or_default()
+}
+
+/// Format nanoseconds with a unit, e.g. `1.250 ms`.
+fn format_nanos(nanos: i128) -> String {
+    let abs = nanos.unsigned_abs() as f64;
+    let sign = if nanos < 0 { "-" } else { "" };
+    match abs {
+        a if a >= 1e9 => format!("{}{:.3} s", sign, a / 1e9),
+        a if a >= 1e6 => format!("{}{:.3} ms", sign, a / 1e6),
+        a if a >= 1e3 => format!("{}{:.3} us", sign, a / 1e3),
+        a => format!("{}{} ns", sign, a),
+    }
+}
+
+/// Reports warnings about the timestamps as they occur and a summary of the [TimingAnalysis] at Finish.
+pub struct TimingHandler {
+    analysis: TimingAnalysis,
+    /// Called with every warning and the summary.
+    report: Box<dyn FnMut(String) + Send>,
+}
+
+impl TimingHandler {
+    pub fn new(report: impl FnMut(String) + Send + 'static) -> Self {
+        Self { analysis: TimingAnalysis::new(), report: Box::new(report) }
+    }
+}
+
+impl EventHandler for TimingHandler {
+    fn on_event(&mut self, ctx: &mut EventContext, event: &Event) {
+        if let Some(warning) = self.analysis.observe(event, ctx.meta) {
+            (self.report)(warning);
+        }
+        // on_finish is called before on_event, but the summary needs to include the Finish event
+        if *event == Event::Finish {
+            (self.report)(self.analysis.summary());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn meta(timestamp_ms: u64, received_ms: u64) -> EventMeta {
+        EventMeta { rt: 0, timestamp: Duration::from_millis(timestamp_ms), received: UNIX_EPOCH + Duration::from_millis(received_ms) }
+    }
+
+    #[test]
+    fn analyse_timestamps() {
+        let mut analysis = TimingAnalysis::new();
+        assert_eq!(analysis.observe(&Event::Barrier, &meta(1000, 5000)), None);
+        assert_eq!(analysis.observe(&Event::Barrier, &meta(1001, 5003)), None);
+        // gap of 500ms, buffered for 2ms longer
+        assert_eq!(analysis.observe(&Event::RunTask { task_id: 1 }, &meta(1501, 5505)), None);
+        assert!(analysis.observe(&Event::Barrier, &meta(1400, 5506)).unwrap().contains("out of order"));
+        assert!(analysis.observe(&Event::Finish, &meta(4400, 5507)).unwrap().contains("Clock jump"));
+
+        let summary = analysis.summary();
+        assert!(summary.contains("Timing summary of 5 events"), "{}", summary);
+        assert!(summary.contains("max 2.999 s"), "{}", summary);
+        assert!(summary.contains("gaps of at least 100.000 ms: 2, longest: 3.000 s before event 4 (Finish), 500.000 ms before event 2 (RunTask)"), "{}", summary);
+        assert!(summary.contains("out of order timestamps: 1"), "{}", summary);
+        assert!(summary.contains("clock jumps: 1"), "{}", summary);
+    }
+
+    #[test]
+    fn percentiles() {
+        let values = (1..=100).collect::<Vec<_>>();
+        assert_eq!(percentile(&values, 50), 50);
+        assert_eq!(percentile(&values, 99), 99);
+        assert_eq!(percentile(&[], 50), 0);
+    }
+}
@@ -0,0 +1,403 @@
+use std::{collections::{BTreeMap, HashMap, VecDeque}, fmt::Display, io::{self, Stdout}, net::{Shutdown, TcpStream}, sync::{Arc, Mutex, RwLock}, time::{Duration, Instant}};
+
+use crossterm::{event::{self, Event as TermEvent, KeyCode, KeyEventKind, KeyModifiers}, execute, terminal::{self, EnterAlternateScreen, LeaveAlternateScreen}};
+use ratatui::{backend::CrosstermBackend, layout::{Constraint, Direction, Layout, Rect}, style::{Color, Modifier, Style}, text::Line, widgets::{Block, Borders, Cell, List, ListItem, Paragraph, Row, Table, TableState}, Frame, Terminal};
+use utils::{events::{Event, EventError}, AppState, Task, TaskState};
+
+use crate::{ayu_event_handlers::{EventContext, EventHandler}, breakpoints::Breakpoints, commands::{self, Command, CommandError}};
+
+/// Maximum number of lines kept in the event log.
+const LOG_SIZE: usize = 500;
+
+/// Number of samples kept for the activity of each thread.
+const LANE_SIZE: usize = 500;
+
+/// Time between two samples of the thread activity, which is also the time between two redraws.
+const TICK: Duration = Duration::from_millis(100);
+
+/// Maximum number of thread lanes shown at once.
+const MAX_LANES: u16 = 16;
+
+/// Activity of a single thread.
+#[derive(Debug, Default)]
+struct Lane {
+    /// The task currently running on the thread.
+    current: Option<u64>,
+    /// Whether the thread was running a task, sampled once per tick, newest last.
+    samples: VecDeque<bool>,
+}
+
+/// Everything shown in the dashboard, which is not part of the [AppState].
+///
+/// Updated by the event receiver and drawn by the terminal ui.
+#[derive(Debug, Default)]
+pub struct Dashboard {
+    log: VecDeque<String>,
+    lanes: BTreeMap<u64, Lane>,
+    /// Thread each task was queued on or runs on, the state only knows the scope of a task.
+    task_threads: HashMap<u64, u64>,
+}
+
+impl Dashboard {
+    /// Creates an empty dashboard.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Update the thread lanes with a received event and add it to the event log.
+    pub fn push_event(&mut self, event: &Event) {
+        match *event {
+            Event::AddTaskToQueue { task_id, thread_id } => {
+                self.task_threads.insert(task_id, thread_id);
+                self.lanes.entry(thread_id).or_default();
+            },
+            Event::PreRunTask { task_id, thread_id } => {
+                self.task_threads.insert(task_id, thread_id);
+                self.lanes.entry(thread_id).or_default().current = Some(task_id);
+            },
+            Event::PostRunTask { task_id } => self.lanes
+                .values_mut()
+                .filter(|l| l.current == Some(task_id))
+                .for_each(|l| l.current = None),
+            _ => (),
+        }
+
+        self.log(event.to_string());
+    }
+
+    /// Add a message to the event log.
+    pub fn log(&mut self, message: impl Into<String>) {
+        self.log.push_back(message.into());
+        if self.log.len() > LOG_SIZE {
+            self.log.pop_front();
+        }
+    }
+
+    /// Record for every thread, whether it is currently running a task.
+    fn sample(&mut self) {
+        for lane in self.lanes.values_mut() {
+            lane.samples.push_back(lane.current.is_some());
+            if lane.samples.len() > LANE_SIZE {
+                lane.samples.pop_front();
+            }
+        }
+    }
+}
+
+impl EventHandler for Arc<Mutex<Dashboard>> {
+    fn on_event(&mut self, _ctx: &mut EventContext, event: &Event) {
+        self.lock().unwrap().push_event(event);
+    }
+
+    fn on_decode_error(&mut self, error: &EventError) {
+        self.lock().unwrap().log(format!("Unable to handle received event: {}", error));
+    }
+}
+
+/// Filter for the task table, set with `filter state|function|thread <value>`.
+#[derive(Debug, Default, PartialEq)]
+struct TaskFilter {
+    state: Option<TaskState>,
+    function: Option<String>,
+    thread: Option<u64>,
+}
+
+impl TaskFilter {
+    /// Returns true, if the task is shown in the table.
+    fn matches(&self, task: &Task, state: &AppState, dashboard: &Dashboard) -> bool {
+        self.state.is_none_or(|s| task.get_state() == s)
+            && self.function.as_deref().is_none_or(|function| {
+                let expected = state.find_function(function).map(|f| f.id);
+                expected.is_some() && task.get_function().map(|f| f.id) == expected
+            })
+            && self.thread.is_none_or(|thread| dashboard.task_threads.get(&task.get_id()) == Some(&thread))
+    }
+
+    /// Update the filter from the arguments of a filter command.
+    fn update(&mut self, args: &[&str]) -> Result<(), String> {
+        match args {
+            [] | ["clear"] => *self = Self::default(),
+            ["state", state] => self.state = Some(state.parse()?),
+            ["function", function] => self.function = Some(function.to_string()),
+            ["thread", thread] => self.thread = Some(thread.parse().map_err(|_| format!("Invalid thread id: {}", thread))?),
+            _ => return Err(String::from("Usage: filter state|function|thread <value> or filter clear")),
+        }
+
+        Ok(())
+    }
+}
+
+impl Display for TaskFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if *self == Self::default() {
+            return write!(f, "none");
+        }
+        if let Some(state) = self.state {
+            write!(f, "state={} ", state)?;
+        }
+        if let Some(function) = &self.function {
+            write!(f, "function={} ", function)?;
+        }
+        if let Some(thread) = self.thread {
+            write!(f, "thread={} ", thread)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// State of the terminal ui itself.
+#[derive(Default)]
+struct Ui {
+    input: String,
+    filter: TaskFilter,
+    table: TableState,
+}
+
+impl Ui {
+    /// Execute a line of the command line. Returns false, if the ui should quit.
+    ///
+    /// Besides the usual commands, `filter` sets the filter of the task table and `tasks` filters it by state.
+    fn execute(&mut self, line: &str, state: &Arc<RwLock<AppState>>, breakpoints: &Mutex<Breakpoints>, dashboard: &Mutex<Dashboard>, stream: &TcpStream) -> bool {
+        let words = line.split_whitespace().collect::<Vec<_>>();
+        if let ["filter", args @ ..] = &words[..] {
+            let message = match self.filter.update(args) {
+                Ok(()) => format!("Task filter: {}", self.filter),
+                Err(e) => e,
+            };
+            dashboard.lock().unwrap().log(message);
+            return true;
+        }
+
+        let command = match line.parse::<Command>() {
+            Ok(Command::Quit) => return false,
+            Ok(Command::Tasks(filter)) => {
+                self.filter.state = filter;
+                return true;
+            },
+            Ok(command) => command,
+            Err(CommandError::Empty) => return true,
+            Err(e) => {
+                dashboard.lock().unwrap().log(e.to_string());
+                return true;
+            },
+        };
+
+        let result = commands::execute(&command, state, breakpoints, stream);
+        let mut dashboard = dashboard.lock().unwrap();
+        dashboard.log(format!("> {}", line.trim()));
+        match result {
+            Ok(output) => output.lines().for_each(|l| dashboard.log(l)),
+            Err(e) => dashboard.log(e.to_string()),
+        }
+
+        true
+    }
+
+    /// Move the selection of the task table by the given offset.
+    fn select(&mut self, offset: isize) {
+        let selected = self.table.selected().unwrap_or(0);
+        self.table.select(Some(selected.saturating_add_signed(offset)));
+    }
+}
+
+/// Runs the terminal ui until the user quits, requests are sent via the stream.
+///
+/// The dashboard and the state are updated by the event receiver on another thread.
+pub fn run(state: Arc<RwLock<AppState>>, breakpoints: Arc<Mutex<Breakpoints>>, dashboard: Arc<Mutex<Dashboard>>, stream: TcpStream) -> io::Result<()> {
+    terminal::enable_raw_mode()?;
+    execute!(io::stdout(), EnterAlternateScreen)?;
+    let mut terminal = Terminal::new(CrosstermBackend::new(io::stdout()))?;
+
+    let result = event_loop(&mut terminal, &state, &breakpoints, &dashboard, &stream);
+
+    // always restore the terminal, even if the ui failed
+    terminal::disable_raw_mode()?;
+    execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
+    terminal.show_cursor()?;
+    let _ = stream.shutdown(Shutdown::Both);
+
+    result
+}
+
+/// Draws the ui and handles key presses, until the user quits.
+fn event_loop(terminal: &mut Terminal<CrosstermBackend<Stdout>>, state: &Arc<RwLock<AppState>>, breakpoints: &Mutex<Breakpoints>, dashboard: &Mutex<Dashboard>, stream: &TcpStream) -> io::Result<()> {
+    let mut ui = Ui::default();
+    let mut last_sample = Instant::now();
+
+    loop {
+        if last_sample.elapsed() >= TICK {
+            dashboard.lock().unwrap().sample();
+            last_sample = Instant::now();
+        }
+        terminal.draw(|f| draw(f, &mut ui, &state.read().unwrap(), &dashboard.lock().unwrap()))?;
+
+        if !event::poll(TICK)? {
+            continue;
+        }
+        let TermEvent::Key(key) = event::read()? else {
+            continue;
+        };
+        if key.kind != KeyEventKind::Press {
+            continue;
+        }
+
+        match key.code {
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return Ok(()),
+            KeyCode::Char(c) => ui.input.push(c),
+            KeyCode::Backspace => {
+                ui.input.pop();
+            },
+            KeyCode::Esc => ui.input.clear(),
+            KeyCode::Up => ui.select(-1),
+            KeyCode::Down => ui.select(1),
+            KeyCode::Enter => {
+                let line = std::mem::take(&mut ui.input);
+                if !ui.execute(&line, state, breakpoints, dashboard, stream) {
+                    return Ok(());
+                }
+            },
+            _ => (),
+        }
+    }
+}
+
+/// Draws the task table, the thread lanes, the event log and the command line.
+fn draw(f: &mut Frame, ui: &mut Ui, state: &AppState, dashboard: &Dashboard) {
+    let n_lanes = (dashboard.lanes.len() as u16).clamp(1, MAX_LANES);
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(5), Constraint::Length(n_lanes + 2), Constraint::Length(10), Constraint::Length(3)])
+        .split(f.size());
+
+    draw_tasks(f, chunks[0], ui, state, dashboard);
+    draw_lanes(f, chunks[1], dashboard);
+
+    let height = chunks[2].height.saturating_sub(2) as usize;
+    let log = dashboard.log
+        .iter()
+        .skip(dashboard.log.len().saturating_sub(height))
+        .map(|l| ListItem::new(l.as_str()))
+        .collect::<Vec<_>>();
+    f.render_widget(List::new(log).block(Block::default().borders(Borders::ALL).title("Events")), chunks[2]);
+
+    let input = Paragraph::new(format!("> {}", ui.input))
+        .block(Block::default().borders(Borders::ALL).title("Command (help, filter state|function|thread <value>, quit)"));
+    f.render_widget(input, chunks[3]);
+    f.set_cursor(chunks[3].x + 3 + ui.input.len() as u16, chunks[3].y + 1);
+}
+
+/// Draws the table of all tasks matching the filter.
+fn draw_tasks(f: &mut Frame, area: Rect, ui: &mut Ui, state: &AppState, dashboard: &Dashboard) {
+    let tasks = state.get_tasks()
+        .iter()
+        .filter(|t| ui.filter.matches(t, state, dashboard))
+        .collect::<Vec<_>>();
+
+    let rows = tasks.iter().map(|t| {
+        let thread = dashboard.task_threads.get(&t.get_id()).map(|t| t.to_string()).unwrap_or_default();
+        let function = t.get_function().map(|f| f.get_name().to_string()).unwrap_or_default();
+        Row::new(vec![
+            Cell::from(t.get_id().to_string()),
+            Cell::from(t.get_state().to_string()).style(state_style(t.get_state())),
+            Cell::from(function),
+            Cell::from(thread),
+            Cell::from(format!("{:?}", t.get_parent_ids())),
+            Cell::from(format!("{:?}", t.get_child_ids())),
+        ])
+    });
+
+    if ui.table.selected().is_some_and(|s| s >= tasks.len()) {
+        ui.table.select(tasks.len().checked_sub(1));
+    }
+
+    let title = format!("Tasks ({}/{}), filter: {}", tasks.len(), state.get_tasks().len(), ui.filter);
+    let widths = [Constraint::Length(8), Constraint::Length(9), Constraint::Percentage(30), Constraint::Length(7), Constraint::Percentage(25), Constraint::Percentage(25)];
+    let table = Table::new(rows)
+        .header(Row::new(vec!["id", "state", "function", "thread", "parents", "children"]).style(Style::default().add_modifier(Modifier::BOLD)))
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .widths(&widths)
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    f.render_stateful_widget(table, area, &mut ui.table);
+}
+
+/// Draws one lane per thread, showing when the thread was running a task and the task it currently runs.
+fn draw_lanes(f: &mut Frame, area: Rect, dashboard: &Dashboard) {
+    // label and current task take 20 columns, the rest shows the most recent samples
+    let width = area.width.saturating_sub(22) as usize;
+    let lines = dashboard.lanes
+        .iter()
+        .take(MAX_LANES as usize)
+        .map(|(thread_id, lane)| {
+            let activity = lane.samples
+                .iter()
+                .skip(lane.samples.len().saturating_sub(width))
+                .map(|busy| if *busy { '█' } else { '·' })
+                .collect::<String>();
+            let current = lane.current.map(|t| format!("task {}", t)).unwrap_or(String::from("idle"));
+            Line::from(format!("T{:<4} {:<13} {}", thread_id, current, activity))
+        })
+        .collect::<Vec<_>>();
+
+    f.render_widget(Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title("Threads")), area);
+}
+
+/// Color of a task state in the table.
+fn state_style(state: TaskState) -> Style {
+    let color = match state {
+        TaskState::Created => Color::Gray,
+        TaskState::Queued => Color::Blue,
+        TaskState::PreRun => Color::Yellow,
+        TaskState::Running => Color::Green,
+        TaskState::Finished => Color::DarkGray,
+    };
+
+    Style::default().fg(color)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn thread_lanes() {
+        let mut dashboard = Dashboard::new();
+        dashboard.push_event(&Event::AddTaskToQueue { task_id: 1, thread_id: 2 });
+        dashboard.push_event(&Event::PreRunTask { task_id: 1, thread_id: 2 });
+        dashboard.sample();
+        dashboard.push_event(&Event::PostRunTask { task_id: 1 });
+        dashboard.sample();
+
+        let lane = &dashboard.lanes[&2];
+        assert_eq!(lane.current, None);
+        assert_eq!(lane.samples, [true, false]);
+        assert_eq!(dashboard.log.len(), 3);
+    }
+
+    #[test]
+    fn task_filter() {
+        let mut state = AppState::new();
+        state.create_function(4, "foo".to_string());
+        let _ = state.create_task(1, false, Some(4), 0);
+        let _ = state.create_task(2, false, None, 0);
+        state.set_task_state(2, TaskState::Running);
+        let mut dashboard = Dashboard::new();
+        dashboard.push_event(&Event::PreRunTask { task_id: 2, thread_id: 3 });
+
+        let shown = |filter: &TaskFilter| state.get_tasks()
+            .iter()
+            .filter(|t| filter.matches(t, &state, &dashboard))
+            .map(|t| t.get_id())
+            .collect::<Vec<_>>();
+
+        let mut filter = TaskFilter::default();
+        assert_eq!(shown(&filter), vec![1, 2]);
+        filter.update(&["function", "foo"]).unwrap();
+        assert_eq!(shown(&filter), vec![1]);
+        filter.update(&["clear"]).unwrap();
+        filter.update(&["thread", "3"]).unwrap();
+        filter.update(&["state", "running"]).unwrap();
+        assert_eq!(shown(&filter), vec![2]);
+        assert!(filter.update(&["thread", "x"]).is_err());
+    }
+}
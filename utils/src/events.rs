@@ -1,6 +1,9 @@
-use std::{fmt::Display, time::Duration};
+use std::{collections::HashMap, fmt::Display, str::FromStr, time::{Duration, SystemTime}};
 
-#[derive(Debug)]
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type")]
 pub enum Event {
     PreInit{ rt: u64, pid: u64 },
     Init{ n_threads: u64 },
@@ -39,6 +42,191 @@ impl Display for EventError {
 }
 
 
+impl Event {
+    /// Get the type of the event.
+    pub fn event_type(&self) -> EventType {
+        match self {
+            Event::PreInit { .. } => EventType::PreInit,
+            Event::Init { .. } => EventType::Init,
+            Event::Finish => EventType::Finish,
+            Event::RegisterFunction { .. } => EventType::RegisterFunction,
+            Event::AddTask { .. } => EventType::AddTask,
+            Event::AddDependency { .. } => EventType::AddDependency,
+            Event::AddTaskToQueue { .. } => EventType::AddTaskToQueue,
+            Event::PreRunTask { .. } => EventType::PreRunTask,
+            Event::RunTask { .. } => EventType::RunTask,
+            Event::PostRunTask { .. } => EventType::PostRunTask,
+            Event::RemoveTask { .. } => EventType::RemoveTask,
+            Event::WaitOn { .. } => EventType::WaitOn,
+            Event::Barrier => EventType::Barrier,
+        }
+    }
+
+    /// Get the id of the task the event refers to, if there is one.
+    /// 
+    /// For AddDependency events, this is the id of the dependent task (to_id).
+    pub fn task_id(&self) -> Option<u64> {
+        match *self {
+            Event::AddTask { task_id, .. } 
+            | Event::AddTaskToQueue { task_id, .. } 
+            | Event::PreRunTask { task_id, .. } 
+            | Event::RunTask { task_id } 
+            | Event::PostRunTask { task_id } 
+            | Event::RemoveTask { task_id } 
+            | Event::WaitOn { task_id } => Some(task_id),
+            Event::AddDependency { to_id, .. } => Some(to_id),
+            _ => None,
+        }
+    }
+
+    /// Encode the event into the 64 byte buffer sent by Ayudame, the counterpart of `Event::try_from(&[u8])`.
+    ///
+    /// The rt of PreInit events is taken from the event. The name of a RegisterFunction event is not part of the buffer,
+    /// it is sent with `string_len` bytes directly afterwards.
+    pub fn to_buffer(&self, rt: u64, timestamp: Duration) -> [u8; 64] {
+        let mut u64_buf = [0u64; 8];
+        u64_buf[0] = rt;
+        u64_buf[2] = self.event_type() as u64;
+        u64_buf[7] = timestamp.as_nanos() as u64;
+
+        match *self {
+            Event::PreInit { rt, pid } => (u64_buf[0], u64_buf[1]) = (rt, pid),
+            Event::Init { n_threads } => u64_buf[3] = n_threads,
+            Event::RegisterFunction { func_id, string_len } => (u64_buf[3], u64_buf[4]) = (string_len as u64, func_id),
+            Event::AddTask { task_id, func_id, priority, scope_id } => (u64_buf[1], u64_buf[3], u64_buf[4], u64_buf[6]) = (task_id, func_id, priority, scope_id),
+            Event::AddDependency { to_id, from_id, memaddr, orig_memaddr } => (u64_buf[1], u64_buf[3], u64_buf[4], u64_buf[5]) = (to_id, from_id, memaddr, orig_memaddr),
+            Event::AddTaskToQueue { task_id, thread_id } | Event::PreRunTask { task_id, thread_id } => (u64_buf[1], u64_buf[3]) = (task_id, thread_id),
+            Event::RunTask { task_id } | Event::PostRunTask { task_id } | Event::RemoveTask { task_id } | Event::WaitOn { task_id } => u64_buf[1] = task_id,
+            Event::Finish | Event::Barrier => (),
+        }
+
+        let mut buf = [0u8; 64];
+        for (chunk, value) in buf.chunks_exact_mut(8).zip(u64_buf) {
+            chunk.copy_from_slice(&value.to_be_bytes());
+        }
+        buf
+    }
+}
+
+impl Display for Event {
+    /// Formats the event as a single line, e.g. `AddTask task_id=1 func_id=0 priority=0 scope_id=0`.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self.event_type())?;
+        match *self {
+            Event::PreInit { rt, pid } => write!(f, " rt={} pid={}", rt, pid),
+            Event::Init { n_threads } => write!(f, " n_threads={}", n_threads),
+            Event::RegisterFunction { func_id, string_len } => write!(f, " func_id={} string_len={}", func_id, string_len),
+            Event::AddTask { task_id, func_id, priority, scope_id } => write!(f, " task_id={} func_id={} priority={} scope_id={}", task_id, func_id, priority, scope_id),
+            Event::AddDependency { to_id, from_id, memaddr, orig_memaddr } => write!(f, " to_id={} from_id={} memaddr={:#x} orig_memaddr={:#x}", to_id, from_id, memaddr, orig_memaddr),
+            Event::AddTaskToQueue { task_id, thread_id } | Event::PreRunTask { task_id, thread_id } => write!(f, " task_id={} thread_id={}", task_id, thread_id),
+            Event::RunTask { task_id } | Event::PostRunTask { task_id } | Event::RemoveTask { task_id } | Event::WaitOn { task_id } => write!(f, " task_id={}", task_id),
+            Event::Finish | Event::Barrier => Ok(()),
+        }
+    }
+}
+
+impl FromStr for Event {
+    type Err = String;
+
+    /// Parse an event from the format of [Display], e.g. `AddTask task_id=1 func_id=0`.
+    /// Missing fields are 0, values can be given in hex with a `0x` prefix.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut words = s.split_whitespace();
+        let event_type = words.next().ok_or("Empty event")?.parse::<EventType>()?;
+
+        let mut fields = HashMap::new();
+        for word in words {
+            let (key, value) = word.split_once('=').ok_or(format!("Expected key=value, found: {}", word))?;
+            let parsed = match value.strip_prefix("0x") {
+                Some(hex) => u64::from_str_radix(hex, 16),
+                None => value.parse(),
+            };
+            fields.insert(key, parsed.map_err(|_| format!("Invalid value for {}: {}", key, value))?);
+        }
+
+        let mut field = |key| fields.remove(key).unwrap_or(0);
+        let event = match event_type {
+            EventType::PreInit => Self::PreInit { rt: field("rt"), pid: field("pid") },
+            EventType::Init => Self::Init { n_threads: field("n_threads") },
+            EventType::RegisterFunction => Self::RegisterFunction { func_id: field("func_id"), string_len: field("string_len") as usize },
+            EventType::AddTask => Self::AddTask { task_id: field("task_id"), func_id: field("func_id"), priority: field("priority"), scope_id: field("scope_id") },
+            EventType::AddDependency => Self::AddDependency { to_id: field("to_id"), from_id: field("from_id"), memaddr: field("memaddr"), orig_memaddr: field("orig_memaddr") },
+            EventType::AddTaskToQueue => Self::AddTaskToQueue { task_id: field("task_id"), thread_id: field("thread_id") },
+            EventType::PreRunTask => Self::PreRunTask { task_id: field("task_id"), thread_id: field("thread_id") },
+            EventType::RunTask => Self::RunTask { task_id: field("task_id") },
+            EventType::PostRunTask => Self::PostRunTask { task_id: field("task_id") },
+            EventType::RemoveTask => Self::RemoveTask { task_id: field("task_id") },
+            EventType::WaitOn => Self::WaitOn { task_id: field("task_id") },
+            EventType::Barrier => Self::Barrier,
+            EventType::Finish => Self::Finish,
+            e_type => return Err(format!("Event Type not implemented: {:?}", e_type)),
+        };
+
+        match fields.into_keys().next() {
+            Some(key) => Err(format!("Unknown field for {:?}: {}", event_type, key)),
+            None => Ok(event),
+        }
+    }
+}
+
+/// Information about a received event, which is not part of the event itself.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EventMeta {
+    /// The runtime which emitted the event (first value of the buffer).
+    pub rt: u64,
+    /// The timestamp set by Ayudame (last value of the buffer).
+    pub timestamp: Duration,
+    /// The time the event was received.
+    pub received: SystemTime,
+}
+
+impl EventMeta {
+    /// Read the meta information from an event buffer, the receive time is set to now.
+    pub fn from_buffer(buf: &[u8]) -> Option<Self> {
+        let rt = u8_buf_to_u64_buf(buf.get(..8)?).ok()?[0];
+        let timestamp = get_timestamp(buf)?;
+
+        Some(Self { rt, timestamp, received: SystemTime::now() })
+    }
+}
+
+/// A received event, as it is written by `frontend --output jsonl` and read by the replay of the wrapper.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EventRecord {
+    pub rt: u64,
+    /// Timestamp set by Ayudame in nanoseconds.
+    pub timestamp: u64,
+    /// Time the event was received in nanoseconds since the unix epoch.
+    #[serde(default)]
+    pub received: u64,
+    /// Name of the function, only set for RegisterFunction events.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub event: Event,
+}
+
+/// A line of a recorded session, which is not a valid [EventRecord].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordError {
+    pub line: usize,
+    pub msg: String,
+}
+
+impl Display for RecordError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Invalid event in line {}: {}", self.line, self.msg)
+    }
+}
+
+/// Read a recorded session, which was written with `--output jsonl`. Empty lines are skipped.
+pub fn read_json_lines(text: &str) -> Result<Vec<EventRecord>, RecordError> {
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(idx, line)| serde_json::from_str(line).map_err(|e| RecordError { line: idx + 1, msg: e.to_string() }))
+        .collect()
+}
+
 // TODO implement error type for enum creation failure
 impl TryFrom<&[u8]> for Event {
     type Error = EventError;
@@ -100,7 +288,7 @@ impl TryFrom<&[u8]> for Event {
 /// # Ayudame Event Types
 /// 
 /// These are all the Events that get emitted by Ayudame.
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum EventType {
     Null,
     PreInit,
@@ -122,6 +310,18 @@ pub enum EventType {
     AddWaitOnTask,
 }
 
+impl FromStr for EventType {
+    type Err = String;
+
+    /// Parse an event type from its name, e.g. `PreRunTask`. Case is ignored.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        (0..=17)
+            .filter_map(|id| EventType::try_from(id).ok())
+            .find(|e| format!("{:?}", e).eq_ignore_ascii_case(s.trim()))
+            .ok_or(format!("Unknown event type: {}", s.trim()))
+    }
+}
+
 impl TryFrom<&[u8]> for EventType {
     type Error = EventError;
 
@@ -197,8 +397,9 @@ impl TryFrom<u64> for EventType {
 /// Converts a given u8 buffer containing a c string into a rust string
 /// will return an empty string if buffer contains invalid c_string
 pub fn read_function_name_from_buffer(buf: &[u8]) -> String {
-    // string is originally stored as CString, but we can just read in the buffer
-    String::from_utf8(buf.iter().cloned().collect::<Vec<u8>>()).unwrap_or(String::new())
+    // string is originally stored as CString, so strip the trailing null bytes
+    let len = buf.iter().rposition(|b| *b != 0).map_or(0, |i| i + 1);
+    String::from_utf8(buf[..len].to_vec()).unwrap_or_default()
 }
 
 /// Converts a buffer containing u8 integers to a buffer containing u64 integers.
@@ -290,6 +491,47 @@ fn test_get_timestamp() {
     assert!(actual_invalid.is_none());
 }
 
+#[test]
+fn test_event_display() {
+    let event = Event::AddTask { task_id: 1, func_id: 2, priority: 0, scope_id: 3 };
+    assert_eq!(event.to_string(), "AddTask task_id=1 func_id=2 priority=0 scope_id=3");
+    let event = Event::AddDependency { to_id: 2, from_id: 1, memaddr: 0xffee0002, orig_memaddr: 0xffee0001 };
+    assert_eq!(event.to_string(), "AddDependency to_id=2 from_id=1 memaddr=0xffee0002 orig_memaddr=0xffee0001");
+    assert_eq!(Event::Barrier.to_string(), "Barrier");
+}
+
+#[test]
+fn test_event_from_str() {
+    let event = Event::AddDependency { to_id: 2, from_id: 1, memaddr: 0xffee0002, orig_memaddr: 0xffee0001 };
+    assert_eq!(event.to_string().parse::<Event>(), Ok(event));
+    assert_eq!("addtask task_id=4".parse::<Event>(), Ok(Event::AddTask { task_id: 4, func_id: 0, priority: 0, scope_id: 0 }));
+    assert_eq!("Barrier".parse::<Event>(), Ok(Event::Barrier));
+    assert!("RunTask id=4".parse::<Event>().is_err());
+    assert!("RunTask task_id=x".parse::<Event>().is_err());
+    assert!("".parse::<Event>().is_err());
+}
+
+#[test]
+fn test_read_json_lines() {
+    let text = r#"{"rt":0,"timestamp":1000,"received":5,"event":{"type":"PreInit","rt":0,"pid":42}}
+
+{"rt":0,"timestamp":2000,"name":"foo","event":{"type":"RegisterFunction","func_id":1,"string_len":3}}"#;
+    let records = read_json_lines(text).unwrap();
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[1].name.as_deref(), Some("foo"));
+    assert_eq!(records[1].received, 0);
+    assert_eq!(records[1].event, Event::RegisterFunction { func_id: 1, string_len: 3 });
+
+    assert_eq!(read_json_lines("\n{}").unwrap_err().line, 2);
+}
+
+#[test]
+fn test_event_type_from_str() {
+    assert_eq!("PreRunTask".parse::<EventType>(), Ok(EventType::PreRunTask));
+    assert_eq!("barrier".parse::<EventType>(), Ok(EventType::Barrier));
+    assert!("NoEvent".parse::<EventType>().is_err());
+}
+
 #[test]
 fn test_event_type_try_from_slice() {
     let buf: [u8; 64] = [0, 0, 0, 0, 0, 0, 0, 0, 
@@ -326,4 +568,29 @@ fn test_u8_buf_to_u64_buf() {
 
     let buf = [128, 64, 32];
     assert!(u8_buf_to_u64_buf(buf.as_slice()).is_err());
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_event_to_buffer() {
+    let events = [
+        Event::PreInit { rt: 1, pid: 4242 },
+        Event::Init { n_threads: 4 },
+        Event::Finish,
+        Event::RegisterFunction { func_id: 3, string_len: 7 },
+        Event::AddTask { task_id: 5, func_id: 3, priority: 2, scope_id: 1 },
+        Event::AddDependency { to_id: 5, from_id: 4, memaddr: 0xff, orig_memaddr: 0xfe },
+        Event::AddTaskToQueue { task_id: 5, thread_id: 2 },
+        Event::PreRunTask { task_id: 5, thread_id: 2 },
+        Event::RunTask { task_id: 5 },
+        Event::PostRunTask { task_id: 5 },
+        Event::RemoveTask { task_id: 5 },
+        Event::WaitOn { task_id: 5 },
+        Event::Barrier,
+    ];
+
+    for event in events {
+        let buf = event.to_buffer(1, Duration::from_nanos(1234));
+        assert_eq!(Event::try_from(&buf[..]).unwrap(), event);
+        assert_eq!(EventMeta::from_buffer(&buf).unwrap().timestamp, Duration::from_nanos(1234));
+    }
+}
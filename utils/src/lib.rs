@@ -8,13 +8,20 @@ pub mod requests;
 /// Contains all the event types, which can be emitted by the runtime and sent to Ayudame,
 /// as well as some helper functions.
 pub mod events;
+/// Contains the timing statistics of tasks and functions.
+pub mod stats;
 
 use std::{
+    collections::BTreeMap,
     fmt::Write,
+    time::Duration,
     os::raw::c_char,
     fmt::Display, sync::{Arc, Weak, Mutex},
+    str::FromStr,
 };
 
+use stats::{FunctionStats, ParallelismProfile, TaskTiming, ThreadTimeline};
+
 
 /// State of the Application
 /// 
@@ -26,6 +33,8 @@ pub struct AppState {
     pub is_init: bool,
     tasks: Vec<Arc<Task>>,
     functions: Vec<Arc<Function>>,
+    /// Timestamps of all tasks, including removed ones.
+    timings: BTreeMap<u64, TaskTiming>,
 }
 
 impl AppState {
@@ -37,6 +46,7 @@ impl AppState {
             is_init: false,
             tasks: Vec::new(),
             functions: Vec::new(),
+            timings: BTreeMap::new(),
         }
     }
 
@@ -85,6 +95,33 @@ impl AppState {
         }
     }
 
+    /// Return all tasks currently contained in [AppState].
+    pub fn get_tasks(&self) -> &[Arc<Task>] {
+        &self.tasks
+    }
+
+    /// Return all functions registered so far.
+    pub fn get_functions(&self) -> &[Arc<Function>] {
+        &self.functions
+    }
+
+    /// Look up a function either by its id or by its name.
+    pub fn find_function(&self, id_or_name: &str) -> Option<&Arc<Function>> {
+        let id_or_name = id_or_name.trim();
+        match id_or_name.parse::<u64>() {
+            Ok(id) => self.functions.iter().find(|f| f.id == id),
+            Err(_) => self.functions.iter().find(|f| f.get_name() == id_or_name),
+        }
+    }
+
+    /// Update the lifecycle state of the task with the given id.
+    pub fn set_task_state(&self, task_id: u64, task_state: TaskState) -> Option<()> {
+        let task = self.get_task(task_id)?;
+        *task.state.lock().unwrap() = task_state;
+
+        Some(())
+    }
+
     /// Check if state contains task with the given id.
     pub fn does_task_exist(&self, id: u64) -> bool {
         self.tasks.iter().position(|t| t.id == id).is_some()
@@ -97,8 +134,8 @@ impl AppState {
             .and_then(|idx| self.tasks.get(idx))
     }
 
-    /// Get all dependencies between tasks.
-    fn get_dependencies(&self) -> Vec<(u64, u64)> {
+    /// Get all dependencies between tasks, as (parent, child) pairs.
+    pub fn get_dependencies(&self) -> Vec<(u64, u64)> {
         let mut dependencies = Vec::new();
         for parent in &self.tasks {
             for child_ptr in parent.children.lock().unwrap().iter() {
@@ -117,8 +154,8 @@ impl AppState {
         // check if function for provided id exists
         let function = match function_id {
             Some(id) => {
-                let id = self.functions.get(id as usize).ok_or("Provided id not in list.")?;
-                Some(Arc::downgrade(id))
+                let function = self.functions.iter().find(|f| f.id == id).ok_or("Provided id not in list.")?;
+                Some(Arc::downgrade(function))
             },
             None => None,
         };
@@ -128,6 +165,7 @@ impl AppState {
             thread_id,
             function,
             is_critical,
+            state: Mutex::new(TaskState::Created),
             parents: Mutex::new(Vec::new()),
             children: Mutex::new(Vec::new()),
         });
@@ -159,8 +197,84 @@ impl AppState {
             parents.push(Arc::downgrade(parent));
         }
 
+        let timing = self.timings.entry(child_id).or_insert_with(|| TaskTiming { task_id: child_id, ..Default::default() });
+        timing.parents.push(parent_id);
+
         Some(())
     }
+
+    /// Record the timestamp of the event, which moved a task into the given state.
+    /// 
+    /// The thread is set by AddTaskToQueue and PreRunTask. Timings are kept after the task was deleted.
+    pub fn record_task_time(&mut self, task_id: u64, task_state: TaskState, timestamp: Duration, thread_id: Option<u64>) {
+        let function_id = self.get_task(task_id).and_then(|t| t.get_function()).map(|f| f.id);
+        let timing = self.timings.entry(task_id).or_insert_with(|| TaskTiming { task_id, function_id, ..Default::default() });
+        timing.record(task_state, timestamp);
+        timing.thread_id = thread_id.or(timing.thread_id);
+    }
+
+    /// Get the timestamps of a task, which may already be deleted.
+    pub fn get_task_timing(&self, task_id: u64) -> Option<&TaskTiming> {
+        self.timings.get(&task_id)
+    }
+
+    /// Get the timestamps of all tasks, ordered by id.
+    pub fn get_task_timings(&self) -> impl Iterator<Item = &TaskTiming> {
+        self.timings.values()
+    }
+
+    /// Which thread ran which task and when, including busy and idle intervals and the utilization of every thread.
+    /// 
+    /// Returns None, if no task finished running yet.
+    pub fn thread_timeline(&self) -> Option<ThreadTimeline> {
+        ThreadTimeline::new(self.timings.values())
+    }
+
+    /// Ready and running tasks over time in the given number of samples and the critical path, including removed tasks.
+    /// 
+    /// Returns None, if no task finished running yet.
+    pub fn parallelism_profile(&self, samples: usize) -> Option<ParallelismProfile> {
+        ParallelismProfile::new(self.timings.values(), samples)
+    }
+
+    /// Aggregate the timings of all finished tasks per function, ordered by function id.
+    /// 
+    /// Tasks without a registered function are summarized last.
+    pub fn function_stats(&self) -> Vec<FunctionStats> {
+        let mut by_function = BTreeMap::<Option<u64>, Vec<&TaskTiming>>::new();
+        for timing in self.timings.values() {
+            by_function.entry(timing.function_id).or_default().push(timing);
+        }
+
+        let (with_function, without_function): (Vec<_>, Vec<_>) = by_function.into_iter().partition(|(id, _)| id.is_some());
+        with_function.into_iter()
+            .chain(without_function)
+            .filter_map(|(id, timings)| {
+                let name = match id {
+                    Some(id) => self.functions.iter().find(|f| f.id == id).map_or(id.to_string(), |f| f.get_name().to_string()),
+                    None => String::from("no function"),
+                };
+                FunctionStats::new(id, name, &timings)
+            })
+            .collect()
+    }
+
+    /// Create a graph of all tasks and dependencies in the DOT format, which can be rendered with graphviz.
+    /// 
+    /// Nodes are labeled with the id, function and state of a task, edges point from parent to child.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph tasks {\n");
+        for t in &self.tasks {
+            let function = t.get_function().map(|f| f.get_name().to_string()).unwrap_or_default();
+            let _ = writeln!(dot, "    {} [label=\"{}\\n{}\\n{}\"];", t.id, t.id, function.replace('"', "\\\""), t.get_state());
+        }
+        for (parent, child) in self.get_dependencies() {
+            let _ = writeln!(dot, "    {} -> {};", parent, child);
+        }
+        dot.push_str("}\n");
+
+        dot
+    }
 }
 
 impl Default for AppState {
@@ -190,6 +304,50 @@ impl Display for AppState {
     }
 }
 
+/// Lifecycle of a task, as reported by the events of the runtime.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TaskState {
+    /// The task was added (AddTask).
+    Created,
+    /// The task was added to the queue of a thread (AddTaskToQueue).
+    Queued,
+    /// The task is about to run (PreRunTask).
+    PreRun,
+    /// The task is currently running (RunTask).
+    Running,
+    /// The task has finished running (PostRunTask).
+    Finished,
+}
+
+impl Display for TaskState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            TaskState::Created => "created",
+            TaskState::Queued => "queued",
+            TaskState::PreRun => "prerun",
+            TaskState::Running => "running",
+            TaskState::Finished => "finished",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for TaskState {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "created" => Ok(TaskState::Created),
+            "queued" => Ok(TaskState::Queued),
+            "prerun" => Ok(TaskState::PreRun),
+            "running" => Ok(TaskState::Running),
+            "finished" => Ok(TaskState::Finished),
+            invalid => Err(format!("Unknown task state: {}", invalid)),
+        }
+    }
+}
+
 /// Represents a task created by the openMP runtime.
 #[derive(Debug)]
 pub struct Task {
@@ -197,6 +355,7 @@ pub struct Task {
     thread_id: u64,
     function: Option<Weak<Function>>,
     is_critical: bool,
+    state: Mutex<TaskState>,
     parents: Mutex<Vec<Weak<Task>>>,
     children: Mutex<Vec<Weak<Task>>>,
 }
@@ -216,6 +375,41 @@ impl Task {
     pub fn get_id(&self) -> u64 {
         self.id
     }
+
+    /// Get the thread id of the task.
+    pub fn get_thread_id(&self) -> u64 {
+        self.thread_id
+    }
+
+    /// Get the current lifecycle state of the task.
+    pub fn get_state(&self) -> TaskState {
+        *self.state.lock().unwrap()
+    }
+
+    /// Get the function of the task, if it has one.
+    pub fn get_function(&self) -> Option<Arc<Function>> {
+        self.function.as_ref().and_then(|f| f.upgrade())
+    }
+
+    /// Get the ids of all tasks this task depends on.
+    pub fn get_parent_ids(&self) -> Vec<u64> {
+        collect_ids(&self.parents)
+    }
+
+    /// Get the ids of all tasks depending on this task.
+    pub fn get_child_ids(&self) -> Vec<u64> {
+        collect_ids(&self.children)
+    }
+}
+
+/// Collect the ids of all tasks in a list of dependencies, which are still alive.
+fn collect_ids(tasks: &Mutex<Vec<Weak<Task>>>) -> Vec<u64> {
+    tasks.lock()
+        .unwrap()
+        .iter()
+        .filter_map(|t| t.upgrade())
+        .map(|t| t.id)
+        .collect()
 }
 
 impl Display for Task {
@@ -223,9 +417,9 @@ impl Display for Task {
         let f_label = self.function
                             .as_ref()
                             .and_then(|f| f.upgrade())
-                            .map_or("None".to_string(), |f| f.name.clone());
+                            .map_or("None".to_string(), |f| f.get_name().to_string());
 
-        let string = format!("{}: label = {}, is_critical = {}, thread_id = {}", self.id, f_label, self.is_critical, self.thread_id);
+        let string = format!("{}: label = {}, is_critical = {}, thread_id = {}, state = {}", self.id, f_label, self.is_critical, self.thread_id, self.get_state());
         write!(f, "{}", string)
     }
 }
@@ -239,6 +433,7 @@ impl From<u64> for Task {
             thread_id: 0,
             function: Some(Arc::downgrade(&Arc::new(0.into()))),
             is_critical: false,
+            state: Mutex::new(TaskState::Created),
             parents: Mutex::new(Vec::new()),
             children: Mutex::new(Vec::new()),
         }
@@ -268,6 +463,11 @@ impl Function {
         Ok(Self { id, name })
     }
 
+    /// Get the name of the function, without the trailing null byte.
+    pub fn get_name(&self) -> &str {
+        self.name.trim_end_matches('\0')
+    }
+
     /// turn the function into a tuple containing its id and a pointer to its name.
     pub fn into_raw_parts(&self) -> (u64, *mut c_char) {
         (self.id, self.name.as_ptr() as *mut c_char)
@@ -291,7 +491,7 @@ impl From<u64> for Function {
 
 #[cfg(test)]
 mod tests {
-    use super::{AppState, Function};
+    use super::{AppState, Function, TaskState};
 
     #[test]
     fn function_new_is_ok() {
@@ -348,6 +548,32 @@ mod tests {
         assert_eq!(f.name, "funco\0".to_string());
     }
 
+    #[test]
+    fn app_state_create_task_finds_function_by_id() {
+        let mut state = AppState::new();
+        state.create_function(7, "f7".to_string());
+
+        // function 0 would be the first in the list, but no function has id 0
+        assert!(state.create_task(1, false, Some(0), 0).is_err());
+        let task = state.create_task(2, false, Some(7), 0).unwrap();
+        assert_eq!(task.function.as_ref().and_then(|f| f.upgrade()).map(|f| f.id), Some(7));
+    }
+
+    #[test]
+    fn app_state_add_dependency_from_parent_to_child() {
+        let mut state = AppState::new();
+        let _ = state.create_task(1, false, None, 0);
+        let _ = state.create_task(2, false, None, 0);
+        // task 2 depends on task 1
+        assert!(state.add_dependency(1, 2).is_some());
+
+        let children = state.get_task(1).unwrap().children.lock().unwrap().iter().filter_map(|t| t.upgrade()).map(|t| t.id).collect::<Vec<_>>();
+        let parents = state.get_task(2).unwrap().parents.lock().unwrap().iter().filter_map(|t| t.upgrade()).map(|t| t.id).collect::<Vec<_>>();
+        assert_eq!(children, [2]);
+        assert_eq!(parents, [1]);
+        assert!(state.get_task(2).unwrap().children.lock().unwrap().is_empty());
+    }
+
     #[test]
     fn app_state_create_task() {
         let mut state = AppState::new();
@@ -358,4 +584,65 @@ mod tests {
 
         assert!(state.create_task(0, false, Some(0), 0).is_ok());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn app_state_find_function() {
+        let mut state = AppState::new();
+        state.create_function(0, "foo".to_string());
+        state.create_function(1, "bar".to_string());
+
+        assert_eq!(state.find_function("1").map(|f| f.id), Some(1));
+        assert_eq!(state.find_function("foo").map(|f| f.id), Some(0));
+        assert!(state.find_function("baz").is_none());
+    }
+
+    #[test]
+    fn app_state_task_state_and_dependencies() {
+        let mut state = AppState::new();
+        let _ = state.create_task(1, false, None, 0);
+        let _ = state.create_task(2, false, None, 0);
+        state.add_dependency(1, 2);
+
+        let task = state.get_task(1).unwrap();
+        assert_eq!(task.get_state(), TaskState::Created);
+        assert_eq!(task.get_child_ids(), vec![2]);
+        assert_eq!(state.get_task(2).unwrap().get_parent_ids(), vec![1]);
+
+        assert!(state.set_task_state(1, TaskState::Running).is_some());
+        assert_eq!(state.get_task(1).unwrap().get_state(), TaskState::Running);
+        assert!(state.set_task_state(3, TaskState::Running).is_none());
+    }
+
+    #[test]
+    fn app_state_function_stats() {
+        use std::time::Duration;
+
+        let mut state = AppState::new();
+        state.create_function(0, "foo".to_string());
+        let _ = state.create_task(1, false, Some(0), 0);
+        let _ = state.create_task(2, false, None, 0);
+        for (task_id, start, end) in [(1, 2, 5), (2, 3, 4)] {
+            state.record_task_time(task_id, TaskState::PreRun, Duration::from_millis(start), Some(7));
+            state.record_task_time(task_id, TaskState::Finished, Duration::from_millis(end), None);
+        }
+        state.delete_task(1);
+
+        assert_eq!(state.get_task_timing(1).and_then(|t| t.thread_id), Some(7));
+        let stats = state.function_stats();
+        assert_eq!(stats.iter().map(|s| (s.name.as_str(), s.duration.max)).collect::<Vec<_>>(), [("foo", Duration::from_millis(3)), ("no function", Duration::from_millis(1))]);
+    }
+
+    #[test]
+    fn app_state_to_dot() {
+        let mut state = AppState::new();
+        state.create_function(0, "foo".to_string());
+        let _ = state.create_task(1, false, Some(0), 0);
+        let _ = state.create_task(2, false, None, 0);
+        state.add_dependency(1, 2);
+
+        let dot = state.to_dot();
+        assert!(dot.starts_with("digraph tasks {"));
+        assert!(dot.contains("1 [label=\"1\\nfoo\\ncreated\"];"));
+        assert!(dot.contains("1 -> 2;"));
+    }
+}
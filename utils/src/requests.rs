@@ -36,7 +36,7 @@ impl Display for RequestError {
     }
 }
 
-#[derive(Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Request {
     Null = 0,
     NoRequest = 1,
@@ -0,0 +1,525 @@
+use std::{collections::BTreeMap, fmt::Display, time::Duration};
+
+use serde::{Deserialize, Serialize};
+
+use crate::TaskState;
+
+/// Upper bounds of the buckets of a [Histogram], the last bucket has no upper bound.
+pub const HISTOGRAM_BUCKETS: [Duration; 8] = [
+    Duration::from_micros(1),
+    Duration::from_micros(10),
+    Duration::from_micros(100),
+    Duration::from_millis(1),
+    Duration::from_millis(10),
+    Duration::from_millis(100),
+    Duration::from_secs(1),
+    Duration::from_secs(10),
+];
+
+/// Width of the longest bar of a [Histogram].
+const BAR_WIDTH: usize = 40;
+
+/// Timestamps of a task, as reported by the runtime. Kept after the task was removed.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TaskTiming {
+    pub task_id: u64,
+    pub function_id: Option<u64>,
+    /// Thread the task ran on, or was queued on, if it did not run yet.
+    pub thread_id: Option<u64>,
+    /// AddTask
+    pub created: Option<Duration>,
+    /// AddTaskToQueue
+    pub queued: Option<Duration>,
+    /// PreRunTask
+    pub pre_run: Option<Duration>,
+    /// RunTask
+    pub started: Option<Duration>,
+    /// PostRunTask
+    pub ended: Option<Duration>,
+    /// Ids of the tasks this task depends on.
+    pub parents: Vec<u64>,
+}
+
+impl TaskTiming {
+    /// Set the timestamp for the state the task just entered.
+    pub fn record(&mut self, task_state: TaskState, timestamp: Duration) {
+        let time = match task_state {
+            TaskState::Created => &mut self.created,
+            TaskState::Queued => &mut self.queued,
+            TaskState::PreRun => &mut self.pre_run,
+            TaskState::Running => &mut self.started,
+            TaskState::Finished => &mut self.ended,
+        };
+        *time = Some(timestamp);
+    }
+
+    /// Time the task started running, falls back to PreRunTask, if no RunTask event was received.
+    pub fn start(&self) -> Option<Duration> {
+        self.started.or(self.pre_run)
+    }
+
+    /// Time between queueing the task and starting it.
+    pub fn queue_wait(&self) -> Option<Duration> {
+        self.start()?.checked_sub(self.queued?)
+    }
+
+    /// Time the task was running.
+    pub fn duration(&self) -> Option<Duration> {
+        self.ended?.checked_sub(self.start()?)
+    }
+}
+
+/// Summary of a set of durations.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DurationStats {
+    pub count: usize,
+    pub min: Duration,
+    pub max: Duration,
+    pub mean: Duration,
+    pub p50: Duration,
+    pub p90: Duration,
+    pub p99: Duration,
+}
+
+impl DurationStats {
+    /// Summarize the durations, returns None if there are none.
+    pub fn new(mut durations: Vec<Duration>) -> Option<Self> {
+        durations.sort();
+        let count = durations.len();
+        let percentile = |p: usize| durations[(count * p).div_ceil(100).saturating_sub(1)];
+
+        Some(Self {
+            count,
+            min: *durations.first()?,
+            max: *durations.last()?,
+            mean: durations.iter().sum::<Duration>() / count as u32,
+            p50: percentile(50),
+            p90: percentile(90),
+            p99: percentile(99),
+        })
+    }
+}
+
+impl Display for DurationStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "n = {}, min = {:?}, mean = {:?}, p50 = {:?}, p90 = {:?}, p99 = {:?}, max = {:?}", self.count, self.min, self.mean, self.p50, self.p90, self.p99, self.max)
+    }
+}
+
+/// Number of durations per bucket, see [HISTOGRAM_BUCKETS].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Histogram {
+    pub buckets: [usize; HISTOGRAM_BUCKETS.len() + 1],
+}
+
+impl Histogram {
+    pub fn new(durations: &[Duration]) -> Self {
+        let mut histogram = Self::default();
+        for d in durations {
+            let idx = HISTOGRAM_BUCKETS.iter().position(|b| d <= b).unwrap_or(HISTOGRAM_BUCKETS.len());
+            histogram.buckets[idx] += 1;
+        }
+        histogram
+    }
+}
+
+impl Display for Histogram {
+    /// Draws one bar per bucket, e.g. `<= 1ms      ######## 12`.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let max = self.buckets.iter().max().copied().unwrap_or_default().max(1);
+        for (idx, n) in self.buckets.iter().enumerate() {
+            let label = match HISTOGRAM_BUCKETS.get(idx) {
+                Some(bound) => format!("<= {:?}", bound),
+                None => format!("> {:?}", HISTOGRAM_BUCKETS[HISTOGRAM_BUCKETS.len() - 1]),
+            };
+            writeln!(f, "{:<10} {:<width$} {}", label, "#".repeat(n * BAR_WIDTH / max), n, width = BAR_WIDTH)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Timing statistics of all finished tasks of a function.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionStats {
+    /// None for tasks without a registered function.
+    pub function_id: Option<u64>,
+    pub name: String,
+    pub duration: DurationStats,
+    pub queue_wait: Option<DurationStats>,
+    pub histogram: Histogram,
+}
+
+impl FunctionStats {
+    /// Aggregate the timings of the tasks of a function, returns None if none of the tasks finished.
+    pub fn new(function_id: Option<u64>, name: String, timings: &[&TaskTiming]) -> Option<Self> {
+        let durations = timings.iter().filter_map(|t| t.duration()).collect::<Vec<_>>();
+        let histogram = Histogram::new(&durations);
+
+        Some(Self {
+            function_id,
+            name,
+            duration: DurationStats::new(durations)?,
+            queue_wait: DurationStats::new(timings.iter().filter_map(|t| t.queue_wait()).collect()),
+            histogram,
+        })
+    }
+}
+
+impl Display for FunctionStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let id = self.function_id.map_or(String::from("-"), |id| id.to_string());
+        write!(f, "{}: {}\n\tduration: {}", id, self.name, self.duration)?;
+        if let Some(queue_wait) = &self.queue_wait {
+            write!(f, "\n\tqueue wait: {}", queue_wait)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Time a thread was running a task.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TaskInterval {
+    pub task_id: u64,
+    pub start: Duration,
+    pub end: Duration,
+}
+
+/// Busy and idle intervals of a single thread.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ThreadStats {
+    pub thread_id: u64,
+    /// Tasks run by the thread, ordered by start. Nested tasks may overlap.
+    pub tasks: Vec<TaskInterval>,
+    /// Intervals without a running task, as (start, end), within the span of the timeline.
+    pub idle: Vec<(Duration, Duration)>,
+    pub busy_time: Duration,
+    pub idle_time: Duration,
+}
+
+impl ThreadStats {
+    /// Share of the span of the timeline, in which the thread was running a task, between 0 and 1.
+    pub fn utilization(&self) -> f64 {
+        let span = self.busy_time + self.idle_time;
+        if span.is_zero() {
+            return 0.0;
+        }
+        self.busy_time.as_secs_f64() / span.as_secs_f64()
+    }
+}
+
+/// Which thread ran which task and when, for all threads that ran at least one task.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ThreadTimeline {
+    /// First and last timestamp of any task.
+    pub start: Duration,
+    pub end: Duration,
+    /// Ordered by thread id.
+    pub threads: Vec<ThreadStats>,
+}
+
+impl ThreadTimeline {
+    /// Create the timeline from the timings of all tasks, returns None if no task finished running.
+    pub fn new<'a>(timings: impl Iterator<Item = &'a TaskTiming> + Clone) -> Option<Self> {
+        let times = timings.clone().flat_map(|t| [t.created, t.queued, t.pre_run, t.started, t.ended]).flatten();
+        let start = times.clone().min()?;
+        let end = times.max()?;
+
+        let mut by_thread = BTreeMap::<u64, Vec<TaskInterval>>::new();
+        for t in timings {
+            if let (Some(thread_id), Some(task_start), Some(task_end)) = (t.thread_id, t.start(), t.ended) {
+                by_thread.entry(thread_id).or_default().push(TaskInterval { task_id: t.task_id, start: task_start, end: task_end });
+            }
+        }
+        if by_thread.is_empty() {
+            return None;
+        }
+
+        let threads = by_thread.into_iter()
+            .map(|(thread_id, mut tasks)| {
+                tasks.sort_by_key(|t| t.start);
+
+                // the thread is idle between the end of all previous tasks and the start of the next task
+                let mut idle = Vec::new();
+                let mut busy_until = start;
+                for task in &tasks {
+                    if task.start > busy_until {
+                        idle.push((busy_until, task.start));
+                    }
+                    busy_until = busy_until.max(task.end);
+                }
+                if end > busy_until {
+                    idle.push((busy_until, end));
+                }
+
+                let idle_time = idle.iter().map(|(s, e)| *e - *s).sum::<Duration>();
+                ThreadStats { thread_id, tasks, idle, busy_time: (end - start).saturating_sub(idle_time), idle_time }
+            })
+            .collect();
+
+        Some(Self { start, end, threads })
+    }
+
+    /// Load imbalance of the threads, the busy time of the busiest thread relative to the mean busy time minus 1.
+    ///
+    /// 0 means all threads were busy for the same time, 1 means the busiest thread was busy twice as long as the mean.
+    pub fn imbalance(&self) -> f64 {
+        let busy = self.threads.iter().map(|t| t.busy_time.as_secs_f64()).collect::<Vec<_>>();
+        let mean = busy.iter().sum::<f64>() / busy.len() as f64;
+        let max = busy.iter().copied().fold(0.0, f64::max);
+        if mean == 0.0 {
+            return 0.0;
+        }
+        max / mean - 1.0
+    }
+}
+
+/// Average number of ready and running tasks within a slice of time.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParallelismSample {
+    pub start: Duration,
+    pub end: Duration,
+    /// Tasks, which were queued, but did not start yet.
+    pub ready: f64,
+    pub running: f64,
+    /// Share of the slice, in which each thread was running a task, between 0 and 1. Ordered like [ParallelismProfile::thread_ids].
+    pub occupancy: Vec<f64>,
+}
+
+/// Available parallelism of a run over time and its critical path.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParallelismProfile {
+    /// First and last timestamp of any task.
+    pub start: Duration,
+    pub end: Duration,
+    /// Number of threads, which ran at least one task.
+    pub threads: usize,
+    /// Ids of these threads, in ascending order.
+    pub thread_ids: Vec<u64>,
+    pub samples: Vec<ParallelismSample>,
+    /// Time, in which all threads were running tasks and other tasks were ready.
+    pub saturated: Duration,
+    /// Sum of the durations of all tasks, which ran.
+    pub work: Duration,
+    /// Chain of dependent tasks with the longest sum of durations, ordered from the first task.
+    pub critical_path: Vec<TaskTiming>,
+    pub critical_path_length: Duration,
+}
+
+impl ParallelismProfile {
+    /// Create the profile with the given number of samples from the timings of all tasks, returns None if no task finished running.
+    ///
+    /// A task is ready between AddTaskToQueue and its start. Tasks which did not start or finish are ready or running until the end.
+    pub fn new<'a>(timings: impl Iterator<Item = &'a TaskTiming> + Clone, samples: usize) -> Option<Self> {
+        let timeline = ThreadTimeline::new(timings.clone())?;
+        let (start, end) = (timeline.start, timeline.end);
+
+        // number of ready and running tasks between consecutive changes, as (start, end, ready, running)
+        let mut changes = Vec::new();
+        for t in timings.clone() {
+            if let Some(queued) = t.queued {
+                changes.push((queued, 1, 0));
+                changes.push((t.start().unwrap_or(end).max(queued), -1, 0));
+            }
+            if let Some(task_start) = t.start() {
+                changes.push((task_start, 0, 1));
+                changes.push((t.ended.unwrap_or(end).max(task_start), 0, -1));
+            }
+        }
+        changes.sort_by_key(|(time, ..)| *time);
+
+        let mut segments = Vec::new();
+        let (mut ready, mut running) = (0i64, 0i64);
+        for pair in changes.windows(2) {
+            let ((time, d_ready, d_running), (next, ..)) = (pair[0], pair[1]);
+            (ready, running) = (ready + d_ready, running + d_running);
+            if next > time {
+                segments.push((time, next, ready, running));
+            }
+        }
+
+        let threads = timeline.threads.len();
+        let saturated = segments.iter()
+            .filter(|(.., ready, running)| *ready > 0 && *running >= threads as i64)
+            .map(|(s, e, ..)| *e - *s)
+            .sum();
+
+        let span = end - start;
+        let samples = (0..samples as u32)
+            .map(|i| {
+                let (sample_start, sample_end) = (start + span * i / samples as u32, start + span * (i + 1) / samples as u32);
+                let width = (sample_end - sample_start).max(Duration::from_nanos(1)).as_secs_f64();
+                let average = |count: fn(&(Duration, Duration, i64, i64)) -> i64| segments.iter()
+                    .map(|seg| seg.1.min(sample_end).saturating_sub(seg.0.max(sample_start)).as_secs_f64() * count(seg) as f64)
+                    .sum::<f64>() / width;
+                let occupancy = timeline.threads.iter()
+                    .map(|thread| {
+                        let idle = thread.idle.iter().map(|(s, e)| (*e).min(sample_end).saturating_sub((*s).max(sample_start))).sum::<Duration>();
+                        1.0 - idle.as_secs_f64() / width
+                    })
+                    .collect();
+                ParallelismSample { start: sample_start, end: sample_end, ready: average(|seg| seg.2), running: average(|seg| seg.3), occupancy }
+            })
+            .collect();
+
+        let ran = timings.filter(|t| t.duration().is_some()).map(|t| (t.task_id, t)).collect::<BTreeMap<_, _>>();
+        let (critical_path, critical_path_length) = critical_path(&ran);
+
+        Some(Self {
+            start,
+            end,
+            threads,
+            thread_ids: timeline.threads.iter().map(|t| t.thread_id).collect(),
+            samples,
+            saturated,
+            work: ran.values().filter_map(|t| t.duration()).sum(),
+            critical_path,
+            critical_path_length,
+        })
+    }
+
+    /// Average number of tasks, which could run in parallel with unlimited threads: the work divided by the critical path length.
+    pub fn available_parallelism(&self) -> f64 {
+        if self.critical_path_length.is_zero() {
+            return 0.0;
+        }
+        self.work.as_secs_f64() / self.critical_path_length.as_secs_f64()
+    }
+}
+
+/// Find the chain of dependent tasks with the longest sum of durations. Dependencies on tasks, which did not run, are ignored.
+fn critical_path(ran: &BTreeMap<u64, &TaskTiming>) -> (Vec<TaskTiming>, Duration) {
+    let duration = |id: &u64| ran[id].duration().unwrap_or_default();
+
+    let mut children = BTreeMap::<u64, Vec<u64>>::new();
+    let mut n_parents = ran.keys().map(|id| (*id, 0)).collect::<BTreeMap<_, _>>();
+    for t in ran.values() {
+        for parent in t.parents.iter().filter(|p| ran.contains_key(p)) {
+            children.entry(*parent).or_default().push(t.task_id);
+            *n_parents.entry(t.task_id).or_default() += 1;
+        }
+    }
+
+    // longest path ending at every task, as (length, previous task), in topological order. Tasks in cycles are not extended.
+    let mut longest = ran.keys().map(|id| (*id, (duration(id), None))).collect::<BTreeMap<u64, (Duration, Option<u64>)>>();
+    let mut queue = n_parents.iter().filter(|(_, n)| **n == 0).map(|(id, _)| *id).collect::<Vec<_>>();
+    while let Some(id) = queue.pop() {
+        let length = longest[&id].0;
+        for child in children.get(&id).into_iter().flatten() {
+            let candidate = length + duration(child);
+            if candidate > longest[child].0 {
+                longest.insert(*child, (candidate, Some(id)));
+            }
+            let n = n_parents.entry(*child).or_default();
+            *n -= 1;
+            if *n == 0 {
+                queue.push(*child);
+            }
+        }
+    }
+
+    let Some((mut id, (length, _))) = longest.iter().max_by_key(|(_, (length, _))| *length).map(|(id, l)| (*id, *l)) else {
+        return (Vec::new(), Duration::ZERO);
+    };
+    let mut path = vec![ran[&id].clone()];
+    while let Some(previous) = longest[&id].1 {
+        path.push(ran[&previous].clone());
+        id = previous;
+    }
+    path.reverse();
+
+    (path, length)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn duration_stats_and_histogram() {
+        let durations = (1..=100).map(Duration::from_millis).collect::<Vec<_>>();
+        let stats = DurationStats::new(durations.clone()).unwrap();
+        assert_eq!((stats.count, stats.min, stats.max), (100, Duration::from_millis(1), Duration::from_millis(100)));
+        assert_eq!(stats.p50, Duration::from_millis(50));
+        assert_eq!(stats.p99, Duration::from_millis(99));
+        assert_eq!(stats.mean, Duration::from_micros(50_500));
+        assert!(DurationStats::new(Vec::new()).is_none());
+
+        let histogram = Histogram::new(&durations);
+        assert_eq!(histogram.buckets[3..6], [1, 9, 90]);
+        assert!(histogram.to_string().lines().nth(5).unwrap().ends_with("# 90"));
+    }
+
+    #[test]
+    fn thread_timeline() {
+        let timing = |task_id, thread_id, start, end| TaskTiming {
+            task_id,
+            thread_id: Some(thread_id),
+            started: Some(Duration::from_millis(start)),
+            ended: Some(Duration::from_millis(end)),
+            ..Default::default()
+        };
+        let timings = [timing(1, 0, 0, 4), timing(2, 0, 2, 3), timing(3, 0, 6, 10), timing(4, 1, 5, 7)];
+
+        let timeline = ThreadTimeline::new(timings.iter()).unwrap();
+        assert_eq!((timeline.start, timeline.end), (Duration::ZERO, Duration::from_millis(10)));
+
+        let t0 = &timeline.threads[0];
+        assert_eq!(t0.idle, [(Duration::from_millis(4), Duration::from_millis(6))]);
+        assert_eq!(t0.busy_time, Duration::from_millis(8));
+        assert_eq!(t0.utilization(), 0.8);
+        assert_eq!(timeline.threads[1].idle.len(), 2);
+        assert_eq!(timeline.threads[1].busy_time, Duration::from_millis(2));
+
+        // mean busy time is 5ms, the busiest thread was busy for 8ms
+        assert!((timeline.imbalance() - 0.6).abs() < 1e-9);
+        assert!(ThreadTimeline::new([].iter()).is_none());
+    }
+
+    #[test]
+    fn parallelism_profile() {
+        let timing = |task_id, thread_id, queued, start, end, parents: &[u64]| TaskTiming {
+            task_id,
+            thread_id: Some(thread_id),
+            queued: Some(Duration::from_millis(queued)),
+            started: Some(Duration::from_millis(start)),
+            ended: Some(Duration::from_millis(end)),
+            parents: parents.to_vec(),
+            ..Default::default()
+        };
+        // 1 -> 2 -> 4 and 1 -> 3, task 3 waits for a free thread
+        let timings = [timing(1, 0, 0, 0, 2, &[]), timing(2, 0, 2, 2, 6, &[1]), timing(3, 1, 2, 2, 3, &[1]), timing(4, 1, 6, 6, 8, &[2]), timing(5, 0, 1, 3, 4, &[])];
+
+        let profile = ParallelismProfile::new(timings.iter(), 4).unwrap();
+        assert_eq!(profile.threads, 2);
+        assert_eq!(profile.work, Duration::from_millis(10));
+        assert_eq!(profile.critical_path.iter().map(|t| t.task_id).collect::<Vec<_>>(), [1, 2, 4]);
+        assert_eq!(profile.critical_path_length, Duration::from_millis(8));
+        assert_eq!(profile.available_parallelism(), 1.25);
+        // task 5 is ready from 1ms to 3ms, but both threads are busy from 2ms
+        assert_eq!(profile.saturated, Duration::from_millis(1));
+
+        assert_eq!(profile.samples.len(), 4);
+        assert_eq!((profile.samples[0].ready, profile.samples[0].running), (0.5, 1.0));
+        assert_eq!((profile.samples[1].ready, profile.samples[1].running), (0.5, 2.0));
+        assert_eq!(profile.thread_ids, [0, 1]);
+        assert_eq!(profile.samples[0].occupancy, [1.0, 0.0]);
+        assert_eq!(profile.samples[1].occupancy, [1.0, 0.5]);
+        assert!(ParallelismProfile::new([].iter(), 4).is_none());
+    }
+
+    #[test]
+    fn task_timing() {
+        let mut timing = TaskTiming::default();
+        timing.record(TaskState::Queued, Duration::from_millis(1));
+        timing.record(TaskState::PreRun, Duration::from_millis(3));
+        assert_eq!(timing.queue_wait(), Some(Duration::from_millis(2)));
+        assert_eq!(timing.duration(), None);
+
+        timing.record(TaskState::Running, Duration::from_millis(4));
+        timing.record(TaskState::Finished, Duration::from_millis(10));
+        assert_eq!(timing.queue_wait(), Some(Duration::from_millis(3)));
+        assert_eq!(timing.duration(), Some(Duration::from_millis(6)));
+    }
+}
@@ -2,28 +2,142 @@
 //! It makes it possible to connect to a frontend and create any kind of event.
 //! After starting the wrapper, it will wait for a frontend to connect.
 //! Note that AYU_PORT must be set to a free port, before starting the wrapper.
-//! 
+//!
 //! Usage: AYU_PORT=XXXX cargo run --release
+//!
+//! Instead of creating events by hand, a task graph can be sent from a file, generated randomly, or a recorded session can be replayed:
+//!
+//! Usage: AYU_PORT=XXXX cargo run --release -- file graph.txt
+//! Usage: AYU_PORT=XXXX cargo run --release -- file graph.dot
+//! Usage: AYU_PORT=XXXX cargo run --release -- --threads 4 generate --nodes 50
+//! Usage: AYU_PORT=XXXX cargo run --release -- replay session.jsonl --realtime
+//!
+//! Events are sent through the native Ayudame library by default. Without it, the wrapper can speak the Ayudame wire format itself
+//! or write the events to a file (see [sink]):
+//!
+//! Usage: AYU_PORT=XXXX cargo run --release --no-default-features -- --sink tcp file graph.txt
+//! Usage: cargo run --release -- --sink file --out session.jsonl generate
+//!
+//! The mock sink behaves like the socket side of Ayudame: it also reads the requests of the frontend and pauses on Break, Step
+//! and BreakAtTask (see [mock]). Events can also be written by hand, one per line:
+//!
+//! Usage: AYU_PORT=XXXX cargo run --release -- --sink mock events events.txt
+mod mock;
+mod sink;
 mod subcommands;
-use std::{ env, fs };
 
+use std::{env, process::ExitCode, time::Duration};
 
-use subcommands::custom;
-use subcommands::file;
-use subcommands::generate;
+use clap::{Parser, Subcommand};
 
-fn main() {
-    let args = env::args().collect::<Vec<String>>();
-    if args.len() == 3 {
-        if args[1] == "file".to_string() {
-            if let Err(e) = file::from_file(&args[2]) {
-                eprintln!("{}", e.to_string());
-            }
+use sink::SinkKind;
+use subcommands::{custom, events, file, generate, replay, Handshake};
 
-        } 
-    } else {
-        custom::run_custom_events();
-    }
-    //gg::run_generate_graph();
+/// Port the tcp sink listens on, if AYU_PORT is not set.
+const AYU_PORT: &str = "5555";
+
+/// A small wrapper around Ayudame, which sends events to a connected frontend like Temanejo.
+///
+/// AYU_PORT needs to be set to a free port, on which Ayudame waits for the frontend to connect.
+#[derive(Parser)]
+#[command(version, about)]
+struct Args {
+    #[command(subcommand)]
+    mode: Option<Mode>,
+    /// Don't send the PreInit and Init events at the start.
+    #[arg(long, global = true)]
+    no_init: bool,
+    /// Number of threads sent with the Init event.
+    #[arg(long, global = true, default_value_t = 2)]
+    threads: u64,
+    /// Id of the runtime sent with the PreInit event.
+    #[arg(long, global = true, default_value_t = 0)]
+    rt: u64,
+    /// Milliseconds to wait after the PreInit and after the Init event.
+    #[arg(long, global = true, default_value_t = 0)]
+    init_delay: u64,
+    /// Keep the connection open after all events were sent, until enter is pressed or the wrapper is terminated.
+    #[arg(long, global = true)]
+    keep_alive: bool,
+    /// Where the events are sent to, defaults to `ayudame`, if the wrapper was built with the `ayudame` feature, otherwise to `tcp`.
+    #[arg(long, global = true, value_enum)]
+    sink: Option<SinkKind>,
+    /// File the events are written to by the file sink.
+    #[arg(long, global = true)]
+    out: Option<String>,
 }
 
+/// What the wrapper sends to the frontend.
+#[derive(Subcommand)]
+enum Mode {
+    /// Create events interactively (default).
+    Custom,
+    /// Send the task graph of a file, which contains dependencies like `a -> b -> c`, declarations of tasks and functions, and optionally a schedule.
+    File {
+        path: String,
+    },
+    /// Send a randomly generated task graph.
+    Generate {
+        /// Number of tasks.
+        #[arg(long, default_value_t = 22)]
+        nodes: usize,
+        /// Number of dependencies of every task.
+        #[arg(long, default_value_t = 2)]
+        edges_per_node: usize,
+        /// Number of function ids, which are assigned to the tasks.
+        #[arg(long, default_value_t = 15)]
+        functions: u64,
+    },
+    /// Replay a session, which was recorded by the frontend with `--output jsonl`.
+    Replay {
+        trace: String,
+        /// Wait between the events as long as in the recorded session.
+        #[arg(long)]
+        realtime: bool,
+    },
+    /// Send the events of a file, which contains one event per line like `AddTask task_id=1 func_id=2`.
+    Events {
+        path: String,
+    },
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    let handshake = Handshake {
+        is_enabled: !args.no_init,
+        rt: args.rt,
+        threads: args.threads,
+        delay: Duration::from_millis(args.init_delay),
+        keep_alive: args.keep_alive,
+    };
+
+    let kind = args.sink.unwrap_or(if cfg!(feature = "ayudame") { SinkKind::Ayudame } else { SinkKind::Tcp });
+    let address = format!("127.0.0.1:{}", env::var("AYU_PORT").unwrap_or(AYU_PORT.to_string()));
+    let mut sink = match sink::create(kind, &address, args.out.as_deref()) {
+        Ok(sink) => sink,
+        Err(e) => {
+            eprintln!("Unable to create {:?} sink: {}", kind, e);
+            return ExitCode::FAILURE;
+        },
+    };
+    let sink = sink.as_mut();
+
+    let result = match args.mode.unwrap_or(Mode::Custom) {
+        Mode::Custom => custom::run_custom_events(handshake, sink).map_err(|e| e.to_string()),
+        Mode::File { path } => file::from_file(&path, handshake, sink).map_err(|e| e.to_string()),
+        Mode::Generate { nodes, edges_per_node, functions } => generate::run_generate_graph(nodes, edges_per_node, functions, handshake, sink).map_err(|e| e.to_string()),
+        Mode::Replay { trace, realtime } => replay::replay(&trace, realtime, handshake, sink).map_err(|e| e.to_string()),
+        Mode::Events { path } => events::from_events_file(&path, handshake, sink).map_err(|e| e.to_string()),
+    };
+
+    match result {
+        Ok(()) => {
+            handshake.linger();
+            ExitCode::SUCCESS
+        },
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        },
+    }
+}
@@ -0,0 +1,163 @@
+//! A mock of the socket side of Ayudame, to test frontends without the native library.
+//!
+//! Events are sent like by the tcp sink. In addition, the requests of the frontend are read and logged,
+//! and the emission pauses and resumes on Break, Continue, Step and BreakAtTask requests.
+use std::{collections::HashSet, io::{self, Read}, net::TcpStream, sync::{Arc, Condvar, Mutex}, thread};
+
+use utils::{events::Event, requests::Request};
+
+use crate::sink::{EventSink, TcpSink};
+
+/// The state of the emission, which is changed by the requests of the frontend.
+#[derive(Debug, Default)]
+struct Control {
+    is_paused: bool,
+    /// Number of events, which are still sent while paused.
+    steps: u64,
+    /// Tasks, before which the emission pauses.
+    break_at: HashSet<u64>,
+    /// Nothing is paused anymore, after the frontend disconnected.
+    is_disconnected: bool,
+}
+
+impl Control {
+    /// Apply a request with its two arguments. Requests other than Break, Continue, Step, BreakAtTask and UnbreakAtTask are only logged.
+    fn apply(&mut self, request: Request, args: [u64; 2]) {
+        match request {
+            Request::Break => self.is_paused = true,
+            Request::Continue => {
+                self.is_paused = false;
+                self.steps = 0;
+            },
+            Request::Step => {
+                self.is_paused = true;
+                self.steps = args[0];
+            },
+            Request::BreakAtTask => {
+                self.break_at.insert(args[0]);
+            },
+            Request::UnbreakAtTask => {
+                self.break_at.remove(&args[0]);
+            },
+            _ => println!("ignoring request {:?}", request),
+        }
+    }
+
+    /// Pause, if the event starts a task at which a break was requested.
+    fn check_break(&mut self, event: &Event) {
+        if let Event::PreRunTask { task_id, .. } = event {
+            if self.break_at.contains(task_id) {
+                println!("break at task {}", task_id);
+                self.is_paused = true;
+                self.steps = 0;
+            }
+        }
+    }
+
+    /// Returns if the next event can be sent. While paused, this uses up one step.
+    fn try_pass(&mut self) -> bool {
+        if !self.is_paused || self.is_disconnected {
+            return true;
+        }
+        if self.steps > 0 {
+            self.steps -= 1;
+            return true;
+        }
+        false
+    }
+}
+
+/// Sends the events like [TcpSink] and pauses on the requests of the frontend.
+pub(crate) struct MockServer {
+    sink: TcpSink,
+    control: Arc<(Mutex<Control>, Condvar)>,
+}
+
+impl MockServer {
+    /// Wait for a frontend to connect on the given address and start reading its requests.
+    pub(crate) fn listen(address: &str) -> io::Result<Self> {
+        let sink = TcpSink::listen(address)?;
+        let control = Arc::new((Mutex::new(Control::default()), Condvar::new()));
+
+        let stream = sink.requests()?;
+        let receiver_control = Arc::clone(&control);
+        thread::spawn(move || receive_requests(stream, receiver_control));
+
+        Ok(Self { sink, control })
+    }
+}
+
+impl EventSink for MockServer {
+    fn emit(&mut self, event: &Event, name: Option<&str>) -> io::Result<()> {
+        let (lock, cvar) = &*self.control;
+        let mut control = lock.lock().unwrap();
+        control.check_break(event);
+        if control.is_paused && control.steps == 0 {
+            println!("paused before: {}", event);
+        }
+        while !control.try_pass() {
+            control = cvar.wait(control).unwrap();
+        }
+        drop(control);
+
+        self.sink.emit(event, name)
+    }
+}
+
+/// Read the request buffers of the frontend until it disconnects, log them and apply them to the control.
+fn receive_requests(mut stream: TcpStream, control: Arc<(Mutex<Control>, Condvar)>) {
+    let (lock, cvar) = &*control;
+    let mut buf = [0u8; 64];
+    while stream.read_exact(&mut buf).is_ok() {
+        let word = |i: usize| u64::from_be_bytes(buf[i * 8..(i + 1) * 8].try_into().unwrap());
+        match Request::try_from(word(1) as i64) {
+            Ok(request) => {
+                println!("received request {:?} {} {}", request, word(2), word(3));
+                lock.lock().unwrap().apply(request, [word(2), word(3)]);
+                cvar.notify_all();
+            },
+            Err(e) => eprintln!("{}", e),
+        }
+    }
+
+    println!("frontend disconnected");
+    lock.lock().unwrap().is_disconnected = true;
+    cvar.notify_all();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pause_and_resume() {
+        let mut control = Control::default();
+        assert!(control.try_pass());
+
+        control.apply(Request::Break, [0, 0]);
+        assert!(!control.try_pass());
+
+        control.apply(Request::Step, [2, 0]);
+        assert!(control.try_pass());
+        assert!(control.try_pass());
+        assert!(!control.try_pass());
+
+        control.apply(Request::Continue, [0, 0]);
+        assert!(control.try_pass());
+
+        control.apply(Request::BreakAtTask, [3, 0]);
+        control.check_break(&Event::PreRunTask { task_id: 2, thread_id: 0 });
+        assert!(control.try_pass());
+        control.check_break(&Event::PreRunTask { task_id: 3, thread_id: 0 });
+        assert!(!control.try_pass());
+
+        control.apply(Request::UnbreakAtTask, [3, 0]);
+        control.apply(Request::Continue, [0, 0]);
+        control.check_break(&Event::PreRunTask { task_id: 3, thread_id: 0 });
+        assert!(control.try_pass());
+
+        control.apply(Request::Break, [0, 0]);
+        control.is_disconnected = true;
+        assert!(control.try_pass());
+    }
+}
@@ -0,0 +1,192 @@
+//! Destinations of the events created by the wrapper.
+use std::{fs::File, io::{self, BufWriter, Write}, net::{TcpListener, TcpStream}, time::{Duration, SystemTime, UNIX_EPOCH}};
+
+use clap::ValueEnum;
+use utils::events::{Event, EventRecord};
+
+use crate::mock::MockServer;
+
+/// Receives the events created by the subcommands.
+pub(crate) trait EventSink {
+    /// Send an event. `name` is the name of the function for RegisterFunction events.
+    fn emit(&mut self, event: &Event, name: Option<&str>) -> io::Result<()>;
+}
+
+/// Kinds of sinks, which can be selected on the command line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub(crate) enum SinkKind {
+    /// Send the events through the native Ayudame library (requires the `ayudame` feature).
+    Ayudame,
+    /// Wait for a frontend to connect on AYU_PORT and send the events in the Ayudame wire format.
+    Tcp,
+    /// Write the events as json lines, in the same format as `frontend --output jsonl`.
+    File,
+    /// Like `tcp`, but also reads the requests of the frontend and pauses on Break, Step and BreakAtTask like Ayudame.
+    Mock,
+}
+
+/// Nanoseconds since the unix epoch, used as timestamp of the events.
+fn now() -> Duration {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default()
+}
+
+/// Sends the events through `ayudame_core_rs`, which requires the native Ayudame library.
+#[cfg(feature = "ayudame")]
+pub(crate) struct AyudameSink;
+
+#[cfg(feature = "ayudame")]
+impl EventSink for AyudameSink {
+    fn emit(&mut self, event: &Event, name: Option<&str>) -> io::Result<()> {
+        use ayudame_core_rs::ayu_events::*;
+
+        match *event {
+            Event::PreInit { rt, .. } => ayu_event_preinit(rt),
+            Event::Init { n_threads } => ayu_event_init(n_threads),
+            Event::Finish => ayu_event_finish(),
+            Event::RegisterFunction { func_id, .. } => {
+                let function = utils::Function::new(func_id, name.unwrap_or_default().to_string())
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+                let (id, name) = function.into_raw_parts();
+                ayu_event_registerfunction(id, name);
+            },
+            Event::AddTask { task_id, func_id, priority, scope_id } => ayu_event_addtask(task_id, func_id, priority, scope_id),
+            Event::AddDependency { to_id, from_id, memaddr, orig_memaddr } => ayu_event_adddependency(to_id, from_id, memaddr, orig_memaddr),
+            Event::AddTaskToQueue { task_id, thread_id } => ayu_event_addtasktoqueue(task_id, thread_id),
+            Event::PreRunTask { task_id, thread_id } => ayu_event_preruntask(task_id, thread_id),
+            Event::RunTask { task_id } => ayu_event_runtask(task_id),
+            Event::PostRunTask { task_id } => ayu_event_postruntask(task_id),
+            Event::RemoveTask { task_id } => ayu_event_removetask(task_id),
+            Event::WaitOn { task_id } => ayu_event_waiton(task_id),
+            Event::Barrier => ayu_event_barrier(),
+        }
+
+        Ok(())
+    }
+}
+
+/// Sends the events to a frontend in the Ayudame wire format, without the native library.
+///
+/// Every event is a buffer of 64 bytes, the name of a function directly follows its RegisterFunction event.
+/// Requests sent by the frontend are not read.
+pub(crate) struct TcpSink {
+    stream: TcpStream,
+    /// Taken from the last PreInit event.
+    rt: u64,
+}
+
+impl TcpSink {
+    /// Wait for a frontend to connect on the given address.
+    pub(crate) fn listen(address: &str) -> io::Result<Self> {
+        let listener = TcpListener::bind(address)?;
+        println!("waiting for a frontend to connect on {}", address);
+        let (stream, peer) = listener.accept()?;
+        println!("frontend connected from {}", peer);
+
+        Ok(Self { stream, rt: 0 })
+    }
+
+    /// A second handle to the connection, from which the requests of the frontend can be read.
+    pub(crate) fn requests(&self) -> io::Result<TcpStream> {
+        self.stream.try_clone()
+    }
+}
+
+impl EventSink for TcpSink {
+    fn emit(&mut self, event: &Event, name: Option<&str>) -> io::Result<()> {
+        if let Event::PreInit { rt, .. } = event {
+            self.rt = *rt;
+        }
+
+        // the name is sent in the same write, so it is not read as part of the next event
+        let mut buf = event.to_buffer(self.rt, now()).to_vec();
+        if let Event::RegisterFunction { .. } = event {
+            buf.extend_from_slice(name.unwrap_or_default().as_bytes());
+        }
+        self.stream.write_all(&buf)
+    }
+}
+
+/// Writes the events to a trace file, which can be replayed or analysed by the frontend.
+pub(crate) struct FileSink {
+    writer: BufWriter<File>,
+    rt: u64,
+}
+
+impl FileSink {
+    pub(crate) fn create(path: &str) -> io::Result<Self> {
+        Ok(Self { writer: BufWriter::new(File::create(path)?), rt: 0 })
+    }
+}
+
+impl EventSink for FileSink {
+    fn emit(&mut self, event: &Event, name: Option<&str>) -> io::Result<()> {
+        if let Event::PreInit { rt, .. } = event {
+            self.rt = *rt;
+        }
+
+        let timestamp = now().as_nanos() as u64;
+        let record = EventRecord { rt: self.rt, timestamp, received: timestamp, name: name.map(str::to_string), event: *event };
+        serde_json::to_writer(&mut self.writer, &record)?;
+        writeln!(self.writer)?;
+        self.writer.flush()
+    }
+}
+
+/// Keeps all events in memory, for tests.
+#[cfg(test)]
+#[derive(Debug, Default)]
+pub(crate) struct MemorySink {
+    pub events: Vec<(Event, Option<String>)>,
+}
+
+#[cfg(test)]
+impl EventSink for MemorySink {
+    fn emit(&mut self, event: &Event, name: Option<&str>) -> io::Result<()> {
+        self.events.push((*event, name.map(str::to_string)));
+        Ok(())
+    }
+}
+
+/// Create the sink of the given kind. The address is used by the tcp sink, the path by the file sink.
+pub(crate) fn create(kind: SinkKind, address: &str, path: Option<&str>) -> io::Result<Box<dyn EventSink>> {
+    match kind {
+        #[cfg(feature = "ayudame")]
+        SinkKind::Ayudame => Ok(Box::new(AyudameSink)),
+        #[cfg(not(feature = "ayudame"))]
+        SinkKind::Ayudame => Err(io::Error::new(io::ErrorKind::Unsupported, "the wrapper was built without the ayudame feature")),
+        SinkKind::Tcp => Ok(Box::new(TcpSink::listen(address)?)),
+        SinkKind::Mock => Ok(Box::new(MockServer::listen(address)?)),
+        SinkKind::File => {
+            let path = path.ok_or(io::Error::new(io::ErrorKind::InvalidInput, "the file sink needs a path (--out)"))?;
+            Ok(Box::new(FileSink::create(path)?))
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+
+    use super::*;
+
+    #[test]
+    fn tcp_sink_sends_buffers() {
+        let address = "127.0.0.1:0";
+        let listener = TcpListener::bind(address).unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (stream, _) = listener.accept().unwrap();
+
+        let mut sink = TcpSink { stream, rt: 0 };
+        sink.emit(&Event::PreInit { rt: 3, pid: 42 }, None).unwrap();
+        sink.emit(&Event::RegisterFunction { func_id: 1, string_len: 3 }, Some("foo")).unwrap();
+        drop(sink);
+
+        let mut buf = Vec::new();
+        (&client).read_to_end(&mut buf).unwrap();
+        assert_eq!(buf.len(), 64 + 64 + 3);
+        assert_eq!(Event::try_from(&buf[64..128]).unwrap(), Event::RegisterFunction { func_id: 1, string_len: 3 });
+        assert_eq!(&buf[..8], 3u64.to_be_bytes());
+        assert_eq!(&buf[64..72], 3u64.to_be_bytes());
+        assert_eq!(&buf[128..], b"foo");
+    }
+}
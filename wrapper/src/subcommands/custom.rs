@@ -8,15 +8,12 @@
 
 use std::fmt::Display;
 use std::convert::TryFrom;
+use std::io;
 use utils::AppState;
-use utils::events::EventType;
+use utils::events::{Event, EventType};
 use io_utils::{match_or_continue, get_numerical_input, get_input};
-use ayudame_core_rs::ayu_events::*;
 
-use crate::subcommands::generate_mem_address_from_id;
-
-/// Dummy memory address for adddependency events.
-const DUMMY_MEMADDR: u64 = 0xffee0000;
+use crate::{sink::EventSink, subcommands::{generate_mem_address_from_id, Handshake, SubcommandError}};
 
 /// Error message displayed, when user enters invalid input.
 static PARSE_UNSIGNED_ERROR_MSG: &str = "Invalid input, must be positive numeric";
@@ -30,6 +27,8 @@ type Result<T> = std::result::Result<T, UserInputError>;
 enum Command {
     AddEvent,
     PrintState,
+    /// Stop creating events, also used when stdin was closed.
+    Quit,
 }
 
 /// Some error types for invalid user input.
@@ -39,7 +38,8 @@ enum UserInputError {
     AlreadyInitialized(&'static str),
     InvalidFunctionName(String),
     SameTaskDependency,
-    EventNotImplemented(EventType)
+    EventNotImplemented(EventType),
+    Emit(SubcommandError),
 }
 
 impl Display for UserInputError {
@@ -48,6 +48,7 @@ impl Display for UserInputError {
         use UserInputError::*;
         
         let msg = match self {
+            Emit(e) => return write!(f, "{}", e),
             AlreadyInitialized(init) => format!("{} should only be called once. Will not emit event.", init),
             TaskIdNotFound(id) => format!("Task with id: {} not found.", id),
             InvalidFunctionName(name) => format!("Invalid Name: {}. Can only contain ASCII characters", name.trim()),
@@ -60,32 +61,50 @@ impl Display for UserInputError {
 
 impl std::error::Error for UserInputError { }
 
+impl From<io::Error> for UserInputError {
+    fn from(e: io::Error) -> Self {
+        UserInputError::Emit(e.into())
+    }
+}
+
 
-pub(crate) fn run_custom_events() {
+pub(crate) fn run_custom_events(handshake: Handshake, sink: &mut dyn EventSink) -> io::Result<()> {
     // create event loop
     let mut state = AppState::default();
+    if handshake.send(sink)? {
+        state.is_pre_init = true;
+        state.is_init = true;
+    }
     
     loop {
         match ask_for_command() {
             Command::AddEvent => {
                 print_event_types();
 
-                if let Err(e) = handle_user_input(&mut state) {
+                if let Err(e) = handle_user_input(&mut state, &handshake, sink) {
                     eprintln!("{}", e);
                 }
             },
             Command::PrintState => println!("{}", state),
+            // the connection is kept open by `--keep-alive` afterwards
+            Command::Quit => return Ok(()),
         }
     }
 }
 
 /// Ask the user if he wants to add a new event or print the state.
 fn ask_for_command() -> Command {
-    println!("Options:\n\t(a)dd new event\n\t(p)rint current state");
+    println!("Options:\n\t(a)dd new event\n\t(p)rint current state\n\t(q)uit");
     loop {
-        break match get_input().trim() {
+        let input = get_input();
+        if input.is_empty() {
+            // stdin was closed, e.g. when started by Temanejo
+            return Command::Quit;
+        }
+        break match input.trim() {
             "a" => Command::AddEvent,
             "p" => Command::PrintState,
+            "q" => Command::Quit,
             invalid => {
                 eprintln!("Invalid Option: {}, try again", invalid);
                 continue;
@@ -131,42 +150,42 @@ pub fn get_event_type() -> EventType {
 }
 
 /// Handler for creating event.
-fn handle_user_input(state: &mut AppState) -> Result<()> {
+fn handle_user_input(state: &mut AppState, handshake: &Handshake, sink: &mut dyn EventSink) -> Result<()> {
     match get_event_type() {
-        EventType::PreInit => create_pre_init(state),
-        EventType::Init => create_init(state),
-        EventType::AddTask => create_add_task(state),
-        EventType::RegisterFunction => create_register_function(state),
-        EventType::AddDependency => create_add_dependency(state),
-        EventType::AddTaskToQueue => create_add_task_to_queue(state),
-        EventType::PreRunTask => create_pre_run_task(state),
-        EventType::RunTask => create_run_task(state),
-        EventType::PostRunTask => create_post_run_task(state),
-        EventType::RemoveTask => create_remove_task(state),
-        EventType::Barrier => create_barrier(),
-        EventType::WaitOn => create_wait_on(state),
-        EventType::Finish => create_finish(),
+        EventType::PreInit => create_pre_init(state, handshake.rt, sink),
+        EventType::Init => create_init(state, handshake.threads, sink),
+        EventType::AddTask => create_add_task(state, sink),
+        EventType::RegisterFunction => create_register_function(state, sink),
+        EventType::AddDependency => create_add_dependency(state, sink),
+        EventType::AddTaskToQueue => create_add_task_to_queue(state, sink),
+        EventType::PreRunTask => create_pre_run_task(state, sink),
+        EventType::RunTask => create_run_task(state, sink),
+        EventType::PostRunTask => create_post_run_task(state, sink),
+        EventType::RemoveTask => create_remove_task(state, sink),
+        EventType::Barrier => create_barrier(sink),
+        EventType::WaitOn => create_wait_on(state, sink),
+        EventType::Finish => create_finish(sink),
         event => Err(UserInputError::EventNotImplemented(event))
     }
 }
 
-/// Create a pre init event, requires no further input by the user.
-fn create_pre_init(state: &mut AppState) -> Result<()> {
+/// Create a pre init event with the rt id of the handshake, requires no further input by the user.
+fn create_pre_init(state: &mut AppState, rt: u64, sink: &mut dyn EventSink) -> Result<()> {
     if state.is_pre_init {
         return Err(UserInputError::AlreadyInitialized("PreInit"));
     }
-    ayu_event_preinit(0); 
+    sink.emit(&Event::PreInit { rt, pid: std::process::id() as u64 }, None)?;
 
     state.is_pre_init = true;
     Ok(())
 }
 
-/// Create an init event, requires no further input by the user.
-fn create_init(state: &mut AppState) -> Result<()> {
+/// Create an init event with the number of threads of the handshake, requires no further input by the user.
+fn create_init(state: &mut AppState, threads: u64, sink: &mut dyn EventSink) -> Result<()> {
     if state.is_init {
         return Err(UserInputError::AlreadyInitialized("Init"));
     }
-    ayu_event_init(2);
+    sink.emit(&Event::Init { n_threads: threads }, None)?;
 
     state.is_init = true;
 
@@ -177,7 +196,7 @@ fn create_init(state: &mut AppState) -> Result<()> {
 /// select label/function for a task (optional). 
 /// 
 /// Task ids are created automatically.
-fn create_add_task(state: &mut AppState) -> Result<()>{
+fn create_add_task(state: &mut AppState, sink: &mut dyn EventSink) -> Result<()> {
     // TODO: Return with error on wrong input
     println!("Specify Task to add: (leave empty for default values");
 
@@ -212,28 +231,28 @@ fn create_add_task(state: &mut AppState) -> Result<()>{
 
     let (task_id, func_id, priority, scope_id) = task.into_raw_parts();
 
-    ayu_event_addtask(task_id, func_id, priority, scope_id);
+    sink.emit(&Event::AddTask { task_id, func_id, priority, scope_id }, None)?;
 
     Ok(())
 }
 
 /// Ask user to enter the name of a new function/label for a task.
-fn create_register_function(state: &mut AppState) -> Result<()> {
+fn create_register_function(state: &mut AppState, sink: &mut dyn EventSink) -> Result<()> {
     println!("Enter a name for function (empty for default)");
     let name = get_input();
     let id = state.create_function_id();
     let function = state.create_function(id, name.clone()).ok_or(UserInputError::InvalidFunctionName(name))?;
 
-    let (id, name) = function.into_raw_parts();
+    let name = function.get_name();
 
-    ayu_event_registerfunction(id, name);
+    sink.emit(&Event::RegisterFunction { func_id: function.id, string_len: name.len() }, Some(name))?;
 
     Ok(())
 }
 
 /// Create a dependency between two tasks. 
 /// Will fail if there are less than two tasks.
-fn create_add_dependency(state: &mut AppState) -> Result<()> {
+fn create_add_dependency(state: &mut AppState, sink: &mut dyn EventSink) -> Result<()> {
     state.list_tasks();
 
     println!("Enter source, then target id");
@@ -249,90 +268,90 @@ fn create_add_dependency(state: &mut AppState) -> Result<()> {
     let memaddr = generate_mem_address_from_id(target_id);
     let orig_memaddr = generate_mem_address_from_id(source_id);
     
-    state.add_dependency(target_id, source_id);
+    state.add_dependency(source_id, target_id);
 
-    ayu_event_adddependency(target_id, source_id, memaddr, orig_memaddr);
+    sink.emit(&Event::AddDependency { to_id: target_id, from_id: source_id, memaddr, orig_memaddr }, None)?;
     Ok(())
 }
 
 /// Create an addtasktoqueue event. Ask user for a task id.
-fn create_add_task_to_queue(state: & AppState) -> Result<()> {
+fn create_add_task_to_queue(state: & AppState, sink: &mut dyn EventSink) -> Result<()> {
     state.list_tasks();
     
     let task_id = get_numerical_input();
     let (_, _, _, scope_id) = state.get_task(task_id).ok_or(UserInputError::TaskIdNotFound(task_id))?.into_raw_parts();
 
-    ayu_event_addtasktoqueue(task_id, scope_id);
+    sink.emit(&Event::AddTaskToQueue { task_id, thread_id: scope_id }, None)?;
 
     Ok(())
 }
 
 /// Create a prerun task event. Ask user for a task id.
-fn create_pre_run_task(state: &AppState) -> Result<()> {
+fn create_pre_run_task(state: &AppState, sink: &mut dyn EventSink) -> Result<()> {
     state.list_tasks();
     let task_id = get_numerical_input();
 
     let (_, _, _, scope_id) = state.get_task(task_id).ok_or(UserInputError::TaskIdNotFound(task_id))?.into_raw_parts();
 
-    ayu_event_preruntask(task_id, scope_id);
+    sink.emit(&Event::PreRunTask { task_id, thread_id: scope_id }, None)?;
 
     Ok(())
 }
 
 /// Create a runtask event. Ask user for a task id.
-fn create_run_task(state: &AppState) -> Result<()> {
+fn create_run_task(state: &AppState, sink: &mut dyn EventSink) -> Result<()> {
     state.list_tasks();
     let task_id = specify_task_id(state)?;
 
-    ayu_event_runtask(task_id);
+    sink.emit(&Event::RunTask { task_id }, None)?;
 
     Ok(())
 }
 
 /// Create a postruntask event. Ask user for a task id.
-fn create_post_run_task(state: &AppState) -> Result<()> {
+fn create_post_run_task(state: &AppState, sink: &mut dyn EventSink) -> Result<()> {
     state.list_tasks();
     let task_id = specify_task_id(state)?;
 
-    ayu_event_postruntask(task_id);
+    sink.emit(&Event::PostRunTask { task_id }, None)?;
 
     Ok(())
 }
 
 /// Create a removetask event. Asks user for a task id.
 /// Will fail if given id is not found in state.
-fn create_remove_task(state: &mut AppState) -> Result<()> {
+fn create_remove_task(state: &mut AppState, sink: &mut dyn EventSink) -> Result<()> {
     state.list_tasks();
     let task_id = specify_task_id(state)?;
 
     state.delete_task(task_id).ok_or(UserInputError::TaskIdNotFound(task_id))?;
 
-    ayu_event_removetask(task_id);
+    sink.emit(&Event::RemoveTask { task_id }, None)?;
 
     Ok(())
 }
 
 /// Create a barrier event. Requires no further input.
-fn create_barrier() -> Result<()> {
-    ayu_event_barrier();
+fn create_barrier(sink: &mut dyn EventSink) -> Result<()> {
+    sink.emit(&Event::Barrier, None)?;
 
     Ok(())
 }
 
 /// Create a waiton event. Requires no further input.
-fn create_wait_on(state: &AppState) -> Result<()> {
+fn create_wait_on(state: &AppState, sink: &mut dyn EventSink) -> Result<()> {
     state.list_tasks();
     let task_id = specify_task_id(state)?;
 
-    ayu_event_waiton(task_id);
+    sink.emit(&Event::WaitOn { task_id }, None)?;
 
     Ok(())
 }
 
 /// Create a finish event.
 /// This will end the application.
-fn create_finish() -> Result<()> {
-    ayu_event_finish();
+fn create_finish(sink: &mut dyn EventSink) -> Result<()> {
+    sink.emit(&Event::Finish, None)?;
 
     Ok(())
 }
@@ -0,0 +1,391 @@
+//! Import of task graphs from Graphviz DOT files.
+//!
+//! Nodes become tasks, their `label` attributes become registered functions and edges become dependencies.
+//! Subgraphs are flattened, an edge to or from a subgraph like `a -> {b c}` connects all of its nodes.
+//! All other attributes, ports and attribute statements are ignored.
+use super::{file::{Edge, Graph}, SubcommandError, DUMMY_MEMADDR};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    /// An identifier, a number, a quoted string or an HTML string.
+    Id(String),
+    Symbol(&'static str),
+}
+
+/// A token together with its line and column, both starting at 1.
+#[derive(Debug)]
+struct Positioned {
+    token: Token,
+    line: usize,
+    column: usize,
+}
+
+const SYMBOLS: [&str; 8] = ["{", "}", "[", "]", ";", ",", "=", ":"];
+
+fn syntax(line: usize, column: usize, msg: String) -> SubcommandError {
+    SubcommandError::Syntax { line, column: Some(column), msg }
+}
+
+/// Splits a DOT file into tokens, skipping whitespace and comments.
+struct Lexer {
+    chars: Vec<char>,
+    pos: usize,
+    line: usize,
+    column: usize,
+}
+
+impl Lexer {
+    fn peek(&self, offset: usize) -> Option<char> {
+        self.chars.get(self.pos + offset).copied()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek(0)?;
+        self.pos += 1;
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+
+    fn tokenize(&mut self) -> Result<Vec<Positioned>, SubcommandError> {
+        let mut tokens = Vec::new();
+        while let Some(c) = self.peek(0) {
+            let (line, column) = (self.line, self.column);
+            let token = match (c, self.peek(1)) {
+                (c, _) if c.is_whitespace() => {
+                    self.bump();
+                    continue;
+                },
+                // line comments and preprocessor output
+                ('/', Some('/')) | ('#', _) => {
+                    while self.bump().is_some_and(|c| c != '\n') {}
+                    continue;
+                },
+                ('/', Some('*')) => {
+                    self.bump();
+                    self.bump();
+                    while !(self.peek(0) == Some('*') && self.peek(1) == Some('/')) {
+                        self.bump().ok_or_else(|| syntax(line, column, "Unterminated comment".to_string()))?;
+                    }
+                    self.bump();
+                    self.bump();
+                    continue;
+                },
+                ('-', Some('>')) | ('-', Some('-')) => {
+                    self.bump();
+                    let symbol = if self.bump() == Some('>') { "->" } else { "--" };
+                    Token::Symbol(symbol)
+                },
+                ('"', _) => Token::Id(self.quoted().ok_or_else(|| syntax(line, column, "Unterminated string".to_string()))?),
+                ('<', _) => Token::Id(self.html().ok_or_else(|| syntax(line, column, "Unterminated HTML string".to_string()))?),
+                (c, _) if is_id_char(c) || c == '-' => Token::Id(self.identifier()),
+                (c, _) => {
+                    let symbol = SYMBOLS.iter().find(|s| s.starts_with(c)).ok_or_else(|| syntax(line, column, format!("Unexpected character {}", c)))?;
+                    self.bump();
+                    Token::Symbol(symbol)
+                },
+            };
+            tokens.push(Positioned { token, line, column });
+        }
+
+        Ok(tokens)
+    }
+
+    /// An identifier or a number, which may start with `-`.
+    fn identifier(&mut self) -> String {
+        let mut id = String::new();
+        id.extend(self.bump());
+        while let Some(c) = self.peek(0).filter(|&c| is_id_char(c)) {
+            self.bump();
+            id.push(c);
+        }
+        id
+    }
+
+    /// A string in double quotes, only escaped quotes are unescaped. Returns None if the string is not terminated.
+    fn quoted(&mut self) -> Option<String> {
+        self.bump();
+        let mut string = String::new();
+        loop {
+            match self.bump()? {
+                '"' => return Some(string),
+                '\\' if self.peek(0) == Some('"') => string.extend(self.bump()),
+                // line continuation
+                '\\' if self.peek(0) == Some('\n') => {
+                    self.bump();
+                },
+                c => string.push(c),
+            }
+        }
+    }
+
+    /// An HTML string in angle brackets, which may be nested. Returns None if the string is not terminated.
+    fn html(&mut self) -> Option<String> {
+        self.bump();
+        let mut string = String::new();
+        let mut depth = 1;
+        loop {
+            let c = self.bump()?;
+            match c {
+                '<' => depth += 1,
+                '>' if depth == 1 => return Some(string),
+                '>' => depth -= 1,
+                _ => {},
+            }
+            string.push(c);
+        }
+    }
+}
+
+fn is_id_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '.' || !c.is_ascii()
+}
+
+/// Nodes of an edge statement, either a single node or all nodes of a subgraph.
+struct Operand {
+    tasks: Vec<usize>,
+    is_node: bool,
+    line: usize,
+    column: usize,
+}
+
+/// Recursive descent parser for the DOT language, which builds the graph while parsing.
+struct Parser {
+    tokens: Vec<Positioned>,
+    pos: usize,
+    /// Position after the last character, for errors at the end of the file.
+    end: (usize, usize),
+    graph: Graph,
+    is_strict: bool,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|t| &t.token)
+    }
+
+    fn position(&self) -> (usize, usize) {
+        self.tokens.get(self.pos).map_or(self.end, |t| (t.line, t.column))
+    }
+
+    fn error(&self, msg: String) -> SubcommandError {
+        let (line, column) = self.position();
+        syntax(line, column, msg)
+    }
+
+    /// Keywords of DOT are case-insensitive.
+    fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Token::Id(id)) if id.eq_ignore_ascii_case(keyword))
+    }
+
+    /// Skip the symbol, returns if it was found.
+    fn symbol(&mut self, symbol: &str) -> bool {
+        let is_found = matches!(self.peek(), Some(Token::Symbol(s)) if *s == symbol);
+        if is_found {
+            self.pos += 1;
+        }
+        is_found
+    }
+
+    fn expect(&mut self, symbol: &str) -> Result<(), SubcommandError> {
+        match self.symbol(symbol) {
+            true => Ok(()),
+            false => Err(self.error(format!("Expected `{}`, found {}", symbol, self.describe()))),
+        }
+    }
+
+    fn id(&mut self) -> Result<String, SubcommandError> {
+        match self.peek() {
+            Some(Token::Id(id)) => {
+                let id = id.clone();
+                self.pos += 1;
+                Ok(id)
+            },
+            _ => Err(self.error(format!("Expected an identifier, found {}", self.describe()))),
+        }
+    }
+
+    /// The current token for error messages.
+    fn describe(&self) -> String {
+        match self.peek() {
+            Some(Token::Id(id)) => id.clone(),
+            Some(Token::Symbol(symbol)) => format!("`{}`", symbol),
+            None => "the end of the file".to_string(),
+        }
+    }
+
+    /// `[strict] (graph | digraph) [id] { statements }`
+    fn parse_graph(&mut self) -> Result<(), SubcommandError> {
+        if self.is_keyword("strict") {
+            self.pos += 1;
+            self.is_strict = true;
+        }
+        if !(self.is_keyword("graph") || self.is_keyword("digraph")) {
+            return Err(self.error(format!("Expected `graph` or `digraph`, found {}", self.describe())));
+        }
+        self.pos += 1;
+        if let Some(Token::Id(_)) = self.peek() {
+            self.pos += 1;
+        }
+        self.expect("{")?;
+        self.statements()?;
+        self.expect("}")?;
+
+        match self.peek() {
+            None => Ok(()),
+            Some(_) => Err(self.error(format!("Unexpected {} after the graph", self.describe()))),
+        }
+    }
+
+    /// Statements up to the closing brace, returns the tasks used in them.
+    fn statements(&mut self) -> Result<Vec<usize>, SubcommandError> {
+        let mut tasks = Vec::new();
+        while !matches!(self.peek(), None | Some(Token::Symbol("}"))) {
+            tasks.extend(self.statement()?);
+            self.symbol(";");
+        }
+        Ok(tasks)
+    }
+
+    fn statement(&mut self) -> Result<Vec<usize>, SubcommandError> {
+        // attribute statements
+        if self.is_keyword("graph") || self.is_keyword("node") || self.is_keyword("edge") {
+            self.pos += 1;
+            self.attributes()?;
+            return Ok(Vec::new());
+        }
+        // attributes of the graph like `rankdir = LR`
+        if let (Some(Token::Id(_)), Some(Token::Symbol("="))) = (self.peek(), self.tokens.get(self.pos + 1).map(|t| &t.token)) {
+            self.pos += 2;
+            self.id()?;
+            return Ok(Vec::new());
+        }
+
+        let mut operands = vec![self.operand()?];
+        while self.symbol("->") || self.symbol("--") {
+            operands.push(self.operand()?);
+        }
+        let attributes = self.attributes()?;
+
+        if let [Operand { tasks, is_node: true, .. }] = &operands[..] {
+            let label = attributes.iter().find(|(key, _)| key == "label").map(|(_, value)| value);
+            // `\N` is the default label, the name of the node
+            if let Some(label) = label.filter(|label| !label.is_empty() && *label != "\\N") {
+                let function = self.graph.function(label);
+                self.graph.tasks[tasks[0]].function = Some(function);
+            }
+        }
+        for pair in operands.windows(2) {
+            for &from in &pair[0].tasks {
+                for &to in &pair[1].tasks {
+                    if from == to {
+                        return Err(syntax(pair[1].line, pair[1].column, format!("Task {} can't depend on itself", self.graph.tasks[to].name)));
+                    }
+                    if self.is_strict && self.graph.edges.iter().any(|e| e.from == from && e.to == to) {
+                        continue;
+                    }
+                    self.graph.edges.push(Edge { from, to, memaddr: DUMMY_MEMADDR, orig_memaddr: DUMMY_MEMADDR });
+                }
+            }
+        }
+
+        Ok(operands.into_iter().flat_map(|operand| operand.tasks).collect())
+    }
+
+    /// A node with an optional port, or a subgraph.
+    fn operand(&mut self) -> Result<Operand, SubcommandError> {
+        let (line, column) = self.position();
+        if self.is_keyword("subgraph") || self.peek() == Some(&Token::Symbol("{")) {
+            if self.is_keyword("subgraph") {
+                self.pos += 1;
+                if let Some(Token::Id(_)) = self.peek() {
+                    self.pos += 1;
+                }
+            }
+            self.expect("{")?;
+            let tasks = self.statements()?;
+            self.expect("}")?;
+            return Ok(Operand { tasks, is_node: false, line, column });
+        }
+
+        let name = self.id()?;
+        // port and compass point
+        if self.symbol(":") {
+            self.id()?;
+            if self.symbol(":") {
+                self.id()?;
+            }
+        }
+        Ok(Operand { tasks: vec![self.graph.task(&name)], is_node: true, line, column })
+    }
+
+    /// Any number of attribute lists like `[label="foo", color=red]`.
+    fn attributes(&mut self) -> Result<Vec<(String, String)>, SubcommandError> {
+        let mut attributes = Vec::new();
+        while self.symbol("[") {
+            while !self.symbol("]") {
+                let key = self.id()?;
+                self.expect("=")?;
+                let value = self.id()?;
+                attributes.push((key, value));
+                let _ = self.symbol(",") || self.symbol(";");
+            }
+        }
+        Ok(attributes)
+    }
+}
+
+/// Parse a graph from a DOT file.
+pub(crate) fn parse_dot(file: &str) -> Result<Graph, SubcommandError> {
+    let mut lexer = Lexer { chars: file.chars().collect(), pos: 0, line: 1, column: 1 };
+    let tokens = lexer.tokenize()?;
+
+    let mut parser = Parser { tokens, pos: 0, end: (lexer.line, lexer.column), graph: Graph::default(), is_strict: false };
+    parser.parse_graph()?;
+
+    Ok(parser.graph)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_dot_graph() {
+        let file = r#"/* from a paper */
+strict digraph "cholesky" {
+    rankdir = LR;
+    node [shape=box];
+    a [label="potrf"]; b [label=trsm, color=red]
+    c [label="\N"]
+    a -> b -> c:n // chained
+    a -> {b; c} [style=dashed]
+    subgraph cluster_0 { d [label=<<b>gemm</b>>] } -> a
+}"#;
+        let graph = parse_dot(file).unwrap();
+        assert_eq!(graph.functions, ["potrf", "trsm", "<b>gemm</b>"]);
+        assert_eq!(graph.tasks.iter().map(|t| t.name.as_str()).collect::<Vec<_>>(), ["a", "b", "c", "d"]);
+        assert_eq!(graph.tasks.iter().map(|t| t.function).collect::<Vec<_>>(), [Some(0), Some(1), None, Some(2)]);
+        let edges = graph.edges.iter().map(|e| (e.from, e.to)).collect::<Vec<_>>();
+        assert_eq!(edges, [(0, 1), (1, 2), (0, 2), (3, 0)]);
+    }
+
+    #[test]
+    fn dot_syntax_errors() {
+        let error = |file: &str| match parse_dot(file) {
+            Err(SubcommandError::Syntax { line, column: Some(column), .. }) => (line, column),
+            other => panic!("expected syntax error, got {:?}", other),
+        };
+        assert_eq!(error("tree { a }"), (1, 1));
+        assert_eq!(error("digraph {\n  a -> }"), (2, 8));
+        assert_eq!(error("digraph {\n  a -> a\n}"), (2, 8));
+        assert_eq!(error("digraph { a [label] }"), (1, 19));
+        assert_eq!(error("digraph { a [label=\"x] }"), (1, 20));
+        assert_eq!(error("digraph { a }\n}"), (2, 1));
+        assert_eq!(error("digraph { a"), (1, 12));
+    }
+}
@@ -0,0 +1,66 @@
+use std::fs;
+
+use utils::events::Event;
+
+use crate::sink::EventSink;
+
+use super::{Handshake, SubcommandError};
+
+/// Send the events of a file, which contains one event per line, e.g. `AddTask task_id=1 func_id=2`.
+///
+/// PreInit and Init of the file are skipped, if the handshake is enabled, since they were already sent.
+pub(crate) fn from_events_file(filename: &str, handshake: Handshake, sink: &mut dyn EventSink) -> Result<(), SubcommandError> {
+    let file = fs::read_to_string(filename).map_err(|e| SubcommandError::Io(format!("{}: {}", filename, e)))?;
+    let events = parse_events(&file)?;
+    println!("parsed {} events", events.len());
+
+    let is_initialized = handshake.send(sink)?;
+    for (event, name) in events {
+        if is_initialized && matches!(event, Event::PreInit { .. } | Event::Init { .. }) {
+            continue;
+        }
+        sink.emit(&event, name.as_deref())?;
+    }
+    println!("finished sending events...");
+
+    Ok(())
+}
+
+/// Parse the events of a file in the format of [Event]'s Display. Empty lines and lines starting with `#` are skipped.
+///
+/// RegisterFunction events take the name of the function as `name=...`, which also sets the length of the name.
+fn parse_events(file: &str) -> Result<Vec<(Event, Option<String>)>, SubcommandError> {
+    let mut events = Vec::new();
+    for (i, line) in file.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let name = line.split_whitespace().find_map(|word| word.strip_prefix("name="));
+        let fields = line.split_whitespace().filter(|word| !word.starts_with("name=")).collect::<Vec<_>>().join(" ");
+        let mut event = fields.parse::<Event>().map_err(|msg| SubcommandError::Syntax { line: i + 1, column: None, msg })?;
+        if let (Event::RegisterFunction { string_len, .. }, Some(name)) = (&mut event, name) {
+            *string_len = name.len();
+        }
+        events.push((event, name.map(str::to_string)));
+    }
+
+    Ok(events)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_events_file() {
+        let file = "# two tasks\nRegisterFunction func_id=1 name=foo\n\nAddTask task_id=1 func_id=1\nAddDependency to_id=2 from_id=1 memaddr=0xff\nBarrier";
+        let events = parse_events(file).unwrap();
+        assert_eq!(events.len(), 4);
+        assert_eq!(events[0], (Event::RegisterFunction { func_id: 1, string_len: 3 }, Some("foo".to_string())));
+        assert_eq!(events[2].0, Event::AddDependency { to_id: 2, from_id: 1, memaddr: 0xff, orig_memaddr: 0 });
+
+        assert!(matches!(parse_events("Barrier\nRunTask 3"), Err(SubcommandError::Syntax { line: 2, .. })));
+    }
+}
@@ -1,81 +1,409 @@
-use std::{fs, collections::HashMap};
+use std::{collections::HashMap, fs, io, path::Path, thread, time::Duration};
 
-use ayudame_core_rs::ayu_events::*;
+use utils::events::Event;
 
-use super::DUMMY_MEMADDR;
+use crate::sink::EventSink;
 
-pub (crate) enum FileError {
-    Io(&'static str),
-    Syntax(&'static str),
+use super::{dot, Handshake, SubcommandError, DUMMY_MEMADDR};
+
+/// A task of a graph, its id is its index in [Graph::tasks] + 1.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct TaskDecl {
+    pub name: String,
+    /// Index into [Graph::functions], the task has function 0 if None.
+    pub function: Option<usize>,
+    /// Tasks with a priority above 0 are critical.
+    pub priority: u64,
+    /// Sent as scope of the AddTask event, which the frontend shows as thread.
+    pub thread: u64,
 }
 
-impl ToString for FileError {
-    fn to_string(&self) -> String {
-        let m = match self {
-            FileError::Io(m) => m,
-            FileError::Syntax(m) => m,
-        };
-        m.to_string()
+/// A dependency between two tasks, given as indices into [Graph::tasks].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Edge {
+    pub from: usize,
+    pub to: usize,
+    pub memaddr: u64,
+    pub orig_memaddr: u64,
+}
+
+/// A step of the schedule, which is played after the graph was sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Step {
+    Event(Event),
+    Delay(Duration),
+}
+
+/// A task graph, which is sent as RegisterFunction, AddTask and AddDependency events, followed by its schedule.
+#[derive(Debug, Default)]
+pub(crate) struct Graph {
+    /// Names of the functions, the id of a function is its index + 1.
+    pub functions: Vec<String>,
+    pub tasks: Vec<TaskDecl>,
+    pub edges: Vec<Edge>,
+    pub schedule: Vec<Step>,
+    function_indices: HashMap<String, usize>,
+    task_indices: HashMap<String, usize>,
+}
+
+impl Graph {
+    /// Index of the task with the given name, the task is created if it does not exist yet.
+    pub(crate) fn task(&mut self, name: &str) -> usize {
+        if let Some(&index) = self.task_indices.get(name) {
+            return index;
+        }
+        self.tasks.push(TaskDecl { name: name.to_string(), function: None, priority: 0, thread: 0 });
+        self.task_indices.insert(name.to_string(), self.tasks.len() - 1);
+        self.tasks.len() - 1
+    }
+
+    /// Index of the function with the given name, the function is created if it does not exist yet.
+    pub(crate) fn function(&mut self, name: &str) -> usize {
+        if let Some(&index) = self.function_indices.get(name) {
+            return index;
+        }
+        self.functions.push(name.to_string());
+        self.function_indices.insert(name.to_string(), self.functions.len() - 1);
+        self.functions.len() - 1
+    }
+
+    /// Id of the task with the given name, if it exists.
+    pub(crate) fn task_id(&self, name: &str) -> Option<u64> {
+        self.task_indices.get(name).map(|&index| index as u64 + 1)
+    }
+
+    /// Send the functions, the tasks and the dependencies of the graph, then play its schedule.
+    pub(crate) fn emit(&self, sink: &mut dyn EventSink) -> io::Result<()> {
+        for (i, name) in self.functions.iter().enumerate() {
+            sink.emit(&Event::RegisterFunction { func_id: i as u64 + 1, string_len: name.len() }, Some(name))?;
+        }
+        for (i, task) in self.tasks.iter().enumerate() {
+            let func_id = task.function.map_or(0, |f| f as u64 + 1);
+            sink.emit(&Event::AddTask { task_id: i as u64 + 1, func_id, priority: task.priority, scope_id: task.thread }, None)?;
+        }
+        for edge in &self.edges {
+            let dependency = Event::AddDependency { to_id: edge.to as u64 + 1, from_id: edge.from as u64 + 1, memaddr: edge.memaddr, orig_memaddr: edge.orig_memaddr };
+            sink.emit(&dependency, None)?;
+        }
+        for step in &self.schedule {
+            match step {
+                Step::Event(event) => sink.emit(event, None)?,
+                Step::Delay(delay) => thread::sleep(*delay),
+            }
+        }
+
+        Ok(())
     }
 }
 
-pub (crate) fn from_file(filename: &str) -> Result<(), FileError> {
-    let file = fs::read_to_string(filename).map_err(|_| FileError::Io("Unable to open file..."))?;
+/// Send the graph of a file, files ending with `.dot` or `.gv` are read as Graphviz DOT files (see [dot]).
+pub (crate) fn from_file(filename: &str, handshake: Handshake, sink: &mut dyn EventSink) -> Result<(), SubcommandError> {
+    let file = fs::read_to_string(filename).map_err(|e| SubcommandError::Io(format!("{}: {}", filename, e)))?;
+    let graph = match Path::new(filename).extension().and_then(|extension| extension.to_str()) {
+        Some("dot" | "gv") => dot::parse_dot(&file)?,
+        _ => parse_graph(&file)?,
+    };
     println!("parsed input file");
-    let edges = parse_inputs(file)?;
-    let mut ids = HashMap::new();
-    let mut id_count = 1;
 
     // initialize with temanejo
-    println!("sending preinit");
-    ayu_event_preinit(0);
-    println!("sending init");
-    ayu_event_init(2);
-
-    // build the graph
-    for (from, to) in edges {
-        if !ids.contains_key(&from) {
-            ids.insert(from.clone(), id_count);
-            ayu_event_addtask(id_count, 0, 0, 0);
-            id_count += 1;
-            // send out event
-        } 
-        if !ids.contains_key(&to) {
-            ids.insert(to.clone(), id_count);
-            ayu_event_addtask(id_count, 0, 0, 0);
-            id_count += 1;
-        }
+    handshake.send(sink)?;
 
-        // send add dependency event
-        let from_id = *ids.get(&from).unwrap();
-        let to_id = *ids.get(&to).unwrap();
-        ayu_event_adddependency(to_id, from_id, DUMMY_MEMADDR, DUMMY_MEMADDR);
-    }
+    graph.emit(sink)?;
     println!("finished sending graph...");
 
     Ok(())
 }
 
-fn parse_inputs(file: String) -> Result<Vec<(String, String)>, FileError> {
-    let mut edges = Vec::new();
+/// Parse a graph file. Every line is one of:
+///
+/// - `function <name>`: register a function
+/// - `task <name> [function=<name>] [priority=<n>] [critical] [thread=<n>]`: declare a task
+/// - `<from> -> <to> [-> <to> ...] [memaddr=<addr>] [orig_memaddr=<addr>]`: add dependencies
+///
+/// Tasks and functions are created at their first use, if they are not declared. Everything after `#` is a comment.
+/// A line `schedule` starts the schedule, all following lines are steps (see [parse_step]).
+pub(crate) fn parse_graph(file: &str) -> Result<Graph, SubcommandError> {
+    let mut graph = Graph::default();
+    let mut declared = Vec::new();
+    let mut is_schedule = false;
 
-    for mut line in file.lines() {
+    for (i, line) in file.lines().enumerate() {
+        let syntax = |column: usize, msg: String| SubcommandError::Syntax { line: i + 1, column: Some(column), msg };
         // remove comments
-        if line.contains('#') {
-            let comment = line.find('#').unwrap();
-            line = &line[..comment];
+        let line = line.split('#').next().unwrap_or_default();
+        let tokens = tokenize(line);
+        let end = line.chars().count() + 1;
+
+        match tokens.first() {
+            None => continue,
+            Some((column, "schedule")) if tokens.len() == 1 => match is_schedule {
+                true => return Err(syntax(*column, "The schedule was already started".to_string())),
+                false => is_schedule = true,
+            },
+            Some(_) if is_schedule => {
+                let steps = parse_step(&graph, &tokens, end, syntax)?;
+                graph.schedule.extend(steps);
+            },
+            Some(_) if tokens.iter().any(|(_, token)| *token == "->") => parse_edges(&mut graph, &tokens, end, syntax)?,
+            Some((_, "function")) => match &tokens[1..] {
+                [(_, name)] => {
+                    graph.function(name);
+                },
+                [] => return Err(syntax(end, "Expected the name of the function".to_string())),
+                [_, (column, token), ..] => return Err(syntax(*column, format!("Unexpected {} after the name of the function", token))),
+            },
+            Some((_, "task")) => {
+                let (column, name) = tokens.get(1).ok_or_else(|| syntax(end, "Expected the name of the task".to_string()))?;
+                let index = graph.task(name);
+                if declared.contains(&index) {
+                    return Err(syntax(*column, format!("Task {} is already declared", name)));
+                }
+                declared.push(index);
+                parse_task_attributes(&mut graph, index, &tokens[2..], syntax)?;
+            },
+            Some((column, token)) => return Err(syntax(*column, format!("Expected `task`, `function` or `from -> to`, found {}", token))),
         }
-        // if line is empty, continue
-        if line.len() == 0 {
+    }
+
+    Ok(graph)
+}
+
+/// Set the attributes of a declared task.
+fn parse_task_attributes(graph: &mut Graph, index: usize, tokens: &[(usize, &str)], syntax: impl Fn(usize, String) -> SubcommandError) -> Result<(), SubcommandError> {
+    let mut is_critical = false;
+    for &(column, token) in tokens {
+        if token == "critical" {
+            is_critical = true;
             continue;
         }
-        // try to parse edges
-        let split = line.split_whitespace().collect::<Vec<_>>();
-        match &split[..] {
-            [from, "->", to] => edges.push((from.to_string(), to.to_string())),
-            _ => return Err(FileError::Syntax("Invalid syntax in file, needs to be in the form of `from` -> `to`")),
+        let (key, value) = token.split_once('=').ok_or_else(|| syntax(column, format!("Expected key=value or `critical`, found {}", token)))?;
+        let value_column = column + key.chars().count() + 1;
+        let number = || parse_number(value).ok_or_else(|| syntax(value_column, format!("Invalid value for {}: {}", key, value)));
+        match key {
+            "function" => graph.tasks[index].function = Some(graph.function(value)),
+            "priority" => graph.tasks[index].priority = number()?,
+            "thread" => graph.tasks[index].thread = number()?,
+            _ => return Err(syntax(column, format!("Unknown attribute of a task: {}", key))),
         }
     }
+    if is_critical {
+        graph.tasks[index].priority = graph.tasks[index].priority.max(1);
+    }
+
+    Ok(())
+}
+
+/// Add the dependencies of a chain of tasks like `a -> b -> c`, followed by the memory addresses of the dependencies.
+fn parse_edges(graph: &mut Graph, tokens: &[(usize, &str)], end: usize, syntax: impl Fn(usize, String) -> SubcommandError) -> Result<(), SubcommandError> {
+    let mut tasks: Vec<(usize, usize)> = Vec::new();
+    let mut memaddr = DUMMY_MEMADDR;
+    let mut orig_memaddr = DUMMY_MEMADDR;
+    let mut expects_task = true;
+    let mut has_attributes = false;
+
+    for &(column, token) in tokens {
+        match token.split_once('=') {
+            _ if token == "->" && (expects_task || has_attributes) => return Err(syntax(column, "Expected a task before `->`".to_string())),
+            _ if token == "->" => expects_task = true,
+            Some((key, value)) if !expects_task => {
+                has_attributes = true;
+                let value = parse_number(value).ok_or_else(|| syntax(column + key.chars().count() + 1, format!("Invalid address: {}", value)))?;
+                match key {
+                    "memaddr" => memaddr = value,
+                    "orig_memaddr" => orig_memaddr = value,
+                    _ => return Err(syntax(column, format!("Unknown attribute of a dependency: {}", key))),
+                }
+            },
+            None if expects_task && !has_attributes => {
+                let index = graph.task(token);
+                if tasks.last().is_some_and(|&(previous, _)| previous == index) {
+                    return Err(syntax(column, format!("Task {} can't depend on itself", token)));
+                }
+                tasks.push((index, column));
+                expects_task = false;
+            },
+            _ if expects_task => return Err(syntax(column, format!("Expected a task, found {}", token))),
+            _ => return Err(syntax(column, format!("Expected `->`, found {}", token))),
+        }
+    }
+    if expects_task {
+        return Err(syntax(end, "Expected a task after `->`".to_string()));
+    }
+
+    for pair in tasks.windows(2) {
+        graph.edges.push(Edge { from: pair[0].0, to: pair[1].0, memaddr, orig_memaddr });
+    }
 
-    Ok(edges)
-}
\ No newline at end of file
+    Ok(())
+}
+
+/// Parse a step of the schedule. Every line is one of:
+///
+/// - `queue|prerun <task> [<task> ...] [thread=<n>]`: AddTaskToQueue or PreRunTask, on the thread of the task by default
+/// - `run|postrun|remove|waiton <task> [<task> ...]`: RunTask, PostRunTask, RemoveTask or WaitOn
+/// - `barrier`, `finish`: Barrier or Finish
+/// - `delay <ms>`: wait before the next step
+fn parse_step(graph: &Graph, tokens: &[(usize, &str)], end: usize, syntax: impl Fn(usize, String) -> SubcommandError) -> Result<Vec<Step>, SubcommandError> {
+    let (column, keyword) = tokens[0];
+    let args = &tokens[1..];
+
+    match (keyword, args) {
+        ("barrier", []) => return Ok(vec![Step::Event(Event::Barrier)]),
+        ("finish", []) => return Ok(vec![Step::Event(Event::Finish)]),
+        ("barrier" | "finish", [(column, token), ..]) => return Err(syntax(*column, format!("Unexpected {} after {}", token, keyword))),
+        ("delay", [(column, ms)]) => {
+            let ms = parse_number(ms).ok_or_else(|| syntax(*column, format!("Invalid delay: {}", ms)))?;
+            return Ok(vec![Step::Delay(Duration::from_millis(ms))]);
+        },
+        ("delay", [_, (column, token), ..]) => return Err(syntax(*column, format!("Unexpected {} after the delay", token))),
+        ("delay", []) => return Err(syntax(end, "Expected the delay in milliseconds".to_string())),
+        ("queue" | "prerun" | "run" | "postrun" | "remove" | "waiton", _) => {},
+        _ => return Err(syntax(column, format!("Expected a step like `run <task>`, found {}", keyword))),
+    }
+
+    let mut tasks = Vec::new();
+    let mut thread = None;
+    for &(column, token) in args {
+        match token.split_once('=') {
+            Some((key @ "thread", value)) if matches!(keyword, "queue" | "prerun") => {
+                thread = Some(parse_number(value).ok_or_else(|| syntax(column + key.chars().count() + 1, format!("Invalid value for thread: {}", value)))?);
+            },
+            Some((key, _)) => return Err(syntax(column, format!("Unknown attribute of {}: {}", keyword, key))),
+            None => {
+                let task_id = graph.task_id(token).ok_or_else(|| syntax(column, format!("Unknown task: {}", token)))?;
+                tasks.push(task_id);
+            },
+        }
+    }
+    if tasks.is_empty() {
+        return Err(syntax(end, format!("Expected a task after {}", keyword)));
+    }
+
+    let steps = tasks.into_iter().map(|task_id| {
+        let thread_id = thread.unwrap_or(graph.tasks[task_id as usize - 1].thread);
+        let event = match keyword {
+            "queue" => Event::AddTaskToQueue { task_id, thread_id },
+            "prerun" => Event::PreRunTask { task_id, thread_id },
+            "run" => Event::RunTask { task_id },
+            "postrun" => Event::PostRunTask { task_id },
+            "remove" => Event::RemoveTask { task_id },
+            _ => Event::WaitOn { task_id },
+        };
+        Step::Event(event)
+    });
+
+    Ok(steps.collect())
+}
+
+/// Split a line into its words, together with their columns starting at 1.
+fn tokenize(line: &str) -> Vec<(usize, &str)> {
+    let mut tokens = Vec::new();
+    let mut start = None;
+    for (i, c) in line.char_indices().chain([(line.len(), ' ')]) {
+        match (start, c.is_whitespace()) {
+            (None, false) => start = Some(i),
+            (Some(s), true) => {
+                tokens.push((line[..s].chars().count() + 1, &line[s..i]));
+                start = None;
+            },
+            _ => {},
+        }
+    }
+
+    tokens
+}
+
+/// Parse a decimal number, or a hexadecimal number starting with `0x`.
+fn parse_number(value: &str) -> Option<u64> {
+    match value.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => value.parse().ok(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::sink::MemorySink;
+
+    use super::*;
+
+    #[test]
+    fn send_graph_from_file() {
+        let path = std::env::temp_dir().join(format!("ayu_wrapper_graph_{}.txt", std::process::id()));
+        fs::write(&path, "a -> b # comment\n\nb -> c\n").unwrap();
+
+        let mut sink = MemorySink::default();
+        let handshake = Handshake { is_enabled: true, rt: 1, threads: 4, delay: Duration::ZERO, keep_alive: false };
+        assert!(from_file(path.to_str().unwrap(), handshake, &mut sink).is_ok());
+        let _ = fs::remove_file(&path);
+
+        let events = sink.events.iter().map(|(e, _)| *e).collect::<Vec<_>>();
+        assert_eq!(events.len(), 7);
+        assert_eq!(events[1], Event::Init { n_threads: 4 });
+        assert_eq!(events[6], Event::AddDependency { to_id: 3, from_id: 2, memaddr: DUMMY_MEMADDR, orig_memaddr: DUMMY_MEMADDR });
+    }
+
+    #[test]
+    fn parse_declarations_and_chains() {
+        let file = "function init\ntask a function=init critical thread=2\n# b and c are created by the edge\na -> b -> c memaddr=0x10\ntask c function=solve priority=3";
+        let graph = parse_graph(file).unwrap();
+        assert_eq!(graph.functions, ["init", "solve"]);
+        assert_eq!(graph.tasks[0], TaskDecl { name: "a".to_string(), function: Some(0), priority: 1, thread: 2 });
+        assert_eq!(graph.tasks[2], TaskDecl { name: "c".to_string(), function: Some(1), priority: 3, thread: 0 });
+        assert_eq!(graph.edges, [
+            Edge { from: 0, to: 1, memaddr: 0x10, orig_memaddr: DUMMY_MEMADDR },
+            Edge { from: 1, to: 2, memaddr: 0x10, orig_memaddr: DUMMY_MEMADDR },
+        ]);
+
+        let mut sink = MemorySink::default();
+        graph.emit(&mut sink).unwrap();
+        assert_eq!(sink.events[1], (Event::RegisterFunction { func_id: 2, string_len: 5 }, Some("solve".to_string())));
+        assert_eq!(sink.events[2].0, Event::AddTask { task_id: 1, func_id: 1, priority: 1, scope_id: 2 });
+    }
+
+    #[test]
+    fn parse_schedule() {
+        let file = "task a thread=1\na -> b\nschedule\nqueue a b\nprerun a thread=3\ndelay 5\nrun a\nwaiton b\nbarrier\nfinish";
+        let graph = parse_graph(file).unwrap();
+        assert_eq!(graph.schedule, [
+            Step::Event(Event::AddTaskToQueue { task_id: 1, thread_id: 1 }),
+            Step::Event(Event::AddTaskToQueue { task_id: 2, thread_id: 0 }),
+            Step::Event(Event::PreRunTask { task_id: 1, thread_id: 3 }),
+            Step::Delay(Duration::from_millis(5)),
+            Step::Event(Event::RunTask { task_id: 1 }),
+            Step::Event(Event::WaitOn { task_id: 2 }),
+            Step::Event(Event::Barrier),
+            Step::Event(Event::Finish),
+        ]);
+
+        let mut sink = MemorySink::default();
+        graph.emit(&mut sink).unwrap();
+        assert_eq!(sink.events.len(), 3 + 7);
+        assert_eq!(sink.events[3].0, Event::AddTaskToQueue { task_id: 1, thread_id: 1 });
+    }
+
+    #[test]
+    fn syntax_errors_have_positions() {
+        let error = |file: &str| match parse_graph(file) {
+            Err(SubcommandError::Syntax { line, column: Some(column), .. }) => (line, column),
+            other => panic!("expected syntax error, got {:?}", other),
+        };
+        assert_eq!(error("a -> b\nfoo bar"), (2, 1));
+        assert_eq!(error("a -> b ->"), (1, 10));
+        assert_eq!(error("a -> -> b"), (1, 6));
+        assert_eq!(error("a b -> c"), (1, 3));
+        assert_eq!(error("a -> a"), (1, 6));
+        assert_eq!(error("a -> b memaddr=zz"), (1, 16));
+        assert_eq!(error("task a\n  task a"), (2, 8));
+        assert_eq!(error("task a thread=x"), (1, 15));
+        assert_eq!(error("task a color=red"), (1, 8));
+        assert_eq!(error("a -> b\nschedule\nrun c"), (3, 5));
+        assert_eq!(error("a -> b\nschedule\na -> c"), (3, 1));
+        assert_eq!(error("a -> b\nschedule\nrun a thread=1"), (3, 7));
+        assert_eq!(error("a -> b\nschedule\nqueue a thread=x"), (3, 16));
+        assert_eq!(error("schedule\nbarrier 3"), (2, 9));
+        assert_eq!(error("schedule\nschedule"), (2, 1));
+    }
+}
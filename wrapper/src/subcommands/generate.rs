@@ -1,33 +1,63 @@
-use std::collections::HashSet;
+use std::{collections::HashSet, io};
 
-use graph_generator;
-use ayudame_core_rs::ayu_events::*;
+use utils::events::Event;
 
-use super::generate_mem_address_from_id;
+use crate::sink::EventSink;
 
-pub(crate) fn run_generate_graph() {
-    let num_nodes = 22;
-    let edges_per_node = 2;
+use super::{generate_mem_address_from_id, Handshake};
+
+/// Send a random task graph, the function of a task is its id modulo `num_functions`.
+pub(crate) fn run_generate_graph(num_nodes: usize, edges_per_node: usize, num_functions: u64, handshake: Handshake, sink: &mut dyn EventSink) -> io::Result<()> {
     let graph = graph_generator::GraphLayout::new_from_num_nodes(num_nodes, edges_per_node);
     let edges = graph.build_edges();
-    let mut tasks = HashSet::new();
-    initialize_with_frontend();
+    handshake.send(sink)?;
     // send graph to ayudame    
+    send_edges(edges.into_iter().map(|(p, s)| (p as u64, s as u64)), num_functions, sink)
+}
 
-    for (predecessor, successor) in edges.into_iter().map(|(p, s)| (p as u64, s as u64)) {
+/// Send the edges as (predecessor, successor), every task is added once, before its first dependency.
+fn send_edges(edges: impl Iterator<Item = (u64, u64)>, num_functions: u64, sink: &mut dyn EventSink) -> io::Result<()> {
+    let mut tasks = HashSet::new();
+
+    for (predecessor, successor) in edges {
         if tasks.insert(predecessor) {
-            ayu_event_addtask(predecessor, predecessor % 15, 0, 0);
+            sink.emit(&Event::AddTask { task_id: predecessor, func_id: predecessor % num_functions.max(1), priority: 0, scope_id: 0 }, None)?;
             // ayu_event_runtask(predecessor);
         }
 
-        tasks.insert(successor);
-        ayu_event_addtask(successor, successor % 15, 0, 0);
-        ayu_event_adddependency(successor, predecessor, generate_mem_address_from_id(successor), generate_mem_address_from_id(predecessor));
+        if tasks.insert(successor) {
+            sink.emit(&Event::AddTask { task_id: successor, func_id: successor % num_functions.max(1), priority: 0, scope_id: 0 }, None)?;
+        }
+        let dependency = Event::AddDependency {
+            to_id: successor,
+            from_id: predecessor,
+            memaddr: generate_mem_address_from_id(successor),
+            orig_memaddr: generate_mem_address_from_id(predecessor),
+        };
+        sink.emit(&dependency, None)?;
         // ayu_event_runtask(successor);
     }
+
+    Ok(())
 }
 
-fn initialize_with_frontend() {
-    ayu_event_preinit(0);
-    ayu_event_init(0);
-}
\ No newline at end of file
+#[cfg(test)]
+mod tests {
+    use crate::sink::MemorySink;
+
+    use super::*;
+
+    #[test]
+    fn add_every_task_once() {
+        // task 3 has two parents and is the parent of task 4
+        let mut sink = MemorySink::default();
+        assert!(send_edges([(1, 3), (2, 3), (3, 4)].into_iter(), 2, &mut sink).is_ok());
+
+        let added = sink.events.iter().filter_map(|(e, _)| match e {
+            Event::AddTask { task_id, .. } => Some(*task_id),
+            _ => None,
+        });
+        assert_eq!(added.collect::<Vec<_>>(), [1, 3, 2, 4]);
+        assert_eq!(sink.events.iter().filter(|(e, _)| matches!(e, Event::AddDependency { .. })).count(), 3);
+    }
+}
@@ -1,9 +1,91 @@
 pub(crate) mod generate;
 pub(crate) mod custom;
 pub(crate) mod file;
+pub(crate) mod dot;
+pub(crate) mod replay;
+pub(crate) mod events;
+
+use std::{fmt::Display, io, thread, time::Duration};
+
+use utils::events::Event;
+
+use crate::sink::EventSink;
 
 const DUMMY_MEMADDR: u64 = 0xffee0000;
 
 fn generate_mem_address_from_id(id: u64) -> u64 {
     DUMMY_MEMADDR | id
-}
\ No newline at end of file
+}
+
+/// Errors of the subcommands, which read their events from a file.
+#[derive(Debug, PartialEq)]
+pub(crate) enum SubcommandError {
+    /// The file could not be read.
+    Io(String),
+    /// Line and column start at 1, the column is only known for some formats.
+    Syntax { line: usize, column: Option<usize>, msg: String },
+    Emit(String),
+}
+
+impl Display for SubcommandError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SubcommandError::Io(msg) => write!(f, "Unable to read file: {}", msg),
+            SubcommandError::Syntax { line, column: Some(column), msg } => write!(f, "Invalid syntax in line {}, column {}: {}", line, column, msg),
+            SubcommandError::Syntax { line, column: None, msg } => write!(f, "Invalid syntax in line {}: {}", line, msg),
+            SubcommandError::Emit(msg) => write!(f, "Unable to send event: {}", msg),
+        }
+    }
+}
+
+impl From<io::Error> for SubcommandError {
+    fn from(e: io::Error) -> Self {
+        SubcommandError::Emit(e.to_string())
+    }
+}
+
+/// The PreInit and Init events, which are sent before any other event.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Handshake {
+    pub is_enabled: bool,
+    /// Id of the runtime, sent with PreInit.
+    pub rt: u64,
+    /// Number of threads, sent with Init.
+    pub threads: u64,
+    /// Time to wait after PreInit and after Init.
+    pub delay: Duration,
+    /// Keep the connection open after all events were sent, see [Handshake::linger].
+    pub keep_alive: bool,
+}
+
+impl Handshake {
+    /// Send PreInit and Init, if the handshake is enabled. Returns if the events were sent.
+    pub(crate) fn send(&self, sink: &mut dyn EventSink) -> io::Result<bool> {
+        if !self.is_enabled {
+            return Ok(false);
+        }
+        println!("sending preinit");
+        sink.emit(&Event::PreInit { rt: self.rt, pid: std::process::id() as u64 }, None)?;
+        thread::sleep(self.delay);
+        println!("sending init");
+        sink.emit(&Event::Init { n_threads: self.threads }, None)?;
+        thread::sleep(self.delay);
+
+        Ok(true)
+    }
+
+    /// Keep the wrapper and with it the connection to the frontend alive, if enabled, until enter is pressed.
+    ///
+    /// If stdin is closed, e.g. when the wrapper was started by Temanejo, it waits until it is terminated.
+    pub(crate) fn linger(&self) {
+        if !self.keep_alive {
+            return;
+        }
+        println!("all events sent, press enter to close the connection");
+        if let Ok(0) = io::stdin().read_line(&mut String::new()) {
+            loop {
+                thread::park();
+            }
+        }
+    }
+}
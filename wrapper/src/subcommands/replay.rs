@@ -0,0 +1,35 @@
+use std::{fs, thread, time::Duration};
+
+use utils::events::{self, Event};
+
+use crate::sink::EventSink;
+
+use super::{Handshake, SubcommandError};
+
+/// Send all events of a recorded session, optionally with the same time between the events as recorded.
+///
+/// PreInit and Init of the session are skipped, if the handshake is enabled, since they were already sent.
+pub(crate) fn replay(filename: &str, realtime: bool, handshake: Handshake, sink: &mut dyn EventSink) -> Result<(), SubcommandError> {
+    let file = fs::read_to_string(filename).map_err(|e| SubcommandError::Io(format!("{}: {}", filename, e)))?;
+    let records = events::read_json_lines(&file).map_err(|e| SubcommandError::Syntax { line: e.line, column: None, msg: e.msg })?;
+    println!("parsed {} events", records.len());
+
+    let is_initialized = handshake.send(sink)?;
+    let mut previous = None;
+    for record in records {
+        if realtime {
+            if let Some(previous) = previous {
+                thread::sleep(Duration::from_nanos(record.timestamp.saturating_sub(previous)));
+            }
+            previous = Some(record.timestamp);
+        }
+
+        if is_initialized && matches!(record.event, Event::PreInit { .. } | Event::Init { .. }) {
+            continue;
+        }
+        sink.emit(&record.event, record.name.as_deref())?;
+    }
+    println!("finished replaying session...");
+
+    Ok(())
+}